@@ -2,9 +2,11 @@
 //!
 //! This file contains various agent patterns for common use cases.
 
-use rig::providers::openai;
-use rig::completion::Prompt;
+use rig::completion::{CompletionError, Prompt, PromptError};
 use anyhow::Result;
+use futures::future::BoxFuture;
+
+use crate::config_template::{FailoverLink, CLIENTS};
 
 // =============================================================================
 // BASIC AGENT
@@ -12,7 +14,7 @@ use anyhow::Result;
 
 /// Simple agent for basic Q&A
 pub async fn basic_agent() -> Result<()> {
-    let client = openai::Client::from_env();
+    let client = CLIENTS.openai();
     
     let agent = client
         .agent("gpt-4o")
@@ -25,13 +27,31 @@ pub async fn basic_agent() -> Result<()> {
     Ok(())
 }
 
+/// Basic agent grounded with Wikipedia lookups
+pub async fn facts_agent() -> Result<()> {
+    use crate::tool_template::WikiLookup;
+
+    let client = CLIENTS.openai();
+    
+    let agent = client
+        .agent("gpt-4o")
+        .preamble("You answer factual questions. Look facts up instead of guessing, and cite the article URL.")
+        .tool(WikiLookup::default())
+        .build();
+    
+    let response = agent.prompt("When was the Rust programming language first released?").await?;
+    println!("{}", response);
+    
+    Ok(())
+}
+
 // =============================================================================
 // CONFIGURED AGENT
 // =============================================================================
 
 /// Agent with temperature and token limit
 pub async fn configured_agent() -> Result<()> {
-    let client = openai::Client::from_env();
+    let client = CLIENTS.openai();
     
     let agent = client
         .agent("gpt-4o")
@@ -54,7 +74,7 @@ pub async fn configured_agent() -> Result<()> {
 
 /// Code review agent
 pub async fn code_review_agent() -> Result<()> {
-    let client = openai::Client::from_env();
+    let client = CLIENTS.openai();
     
     let agent = client
         .agent("gpt-4o")
@@ -90,7 +110,7 @@ pub async fn code_review_agent() -> Result<()> {
 
 /// SQL generation agent
 pub async fn sql_agent() -> Result<()> {
-    let client = openai::Client::from_env();
+    let client = CLIENTS.openai();
     
     let agent = client
         .agent("gpt-4o")
@@ -119,7 +139,7 @@ use rig::completion::{Chat, Message};
 
 /// Multi-turn chat agent
 pub async fn chat_agent() -> Result<()> {
-    let client = openai::Client::from_env();
+    let client = CLIENTS.openai();
     
     let agent = client
         .agent("gpt-4o")
@@ -152,7 +172,7 @@ use futures::StreamExt;
 
 /// Streaming response agent
 pub async fn streaming_agent() -> Result<()> {
-    let client = openai::Client::from_env();
+    let client = CLIENTS.openai();
     
     let agent = client
         .agent("gpt-4o")
@@ -167,7 +187,7 @@ pub async fn streaming_agent() -> Result<()> {
     while let Some(chunk) = stream.next().await {
         match chunk {
             Ok(text) => print!("{}", text),
-            Err(e) => eprintln!("\nError: {}", e),
+            Err(e) => tracing::warn!(error = %e, "stream chunk failed"),
         }
     }
     println!();
@@ -181,15 +201,20 @@ pub async fn streaming_agent() -> Result<()> {
 
 /// Agent with fallback to cheaper model
 pub async fn fallback_agent() -> Result<()> {
-    let client = openai::Client::from_env();
+    use crate::config_template::{select_model, Constraints, ProviderKind, TaskKind};
+
+    let client = CLIENTS.openai();
     
     let primary = client
         .agent("gpt-4o")
         .preamble("You are a helpful assistant.")
         .build();
     
+    // Cheapest OpenAI model that can still do the job
+    let constraints = Constraints::default().provider(ProviderKind::OpenAi).min_context(128_000);
+    let fallback_model = select_model(TaskKind::Chat, &constraints)?;
     let fallback = client
-        .agent("gpt-4o-mini")
+        .agent(fallback_model.id)
         .preamble("You are a helpful assistant.")
         .build();
     
@@ -197,8 +222,8 @@ pub async fn fallback_agent() -> Result<()> {
     
     let response = match primary.prompt(query).await {
         Ok(r) => r,
-        Err(_) => {
-            println!("Primary failed, using fallback...");
+        Err(e) => {
+            tracing::warn!(error = %e, "primary failed, using fallback");
             fallback.prompt(query).await?
         }
     };
@@ -206,3 +231,57 @@ pub async fn fallback_agent() -> Result<()> {
     println!("{}", response);
     Ok(())
 }
+
+/// Tries each link in order, skipping ones whose health check fails, and
+/// returns the first successful response
+pub struct FallbackChain {
+    links: Vec<FailoverLink>,
+}
+
+impl FallbackChain {
+    pub fn new(links: Vec<FailoverLink>) -> Self {
+        Self { links }
+    }
+}
+
+impl crate::config_template::DynAgent for FallbackChain {
+    fn prompt<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, Result<String, PromptError>> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for link in &self.links {
+                if !link.healthy().await {
+                    tracing::warn!(link = %link.label, "unhealthy, skipping");
+                    continue;
+                }
+                match link.agent.prompt(prompt).await {
+                    Ok(response) => return Ok(response),
+                    Err(e) => {
+                        tracing::warn!(link = %link.label, error = %e, "failed, trying next link");
+                        last_error = Some(e);
+                    }
+                }
+            }
+            Err(last_error.unwrap_or_else(|| {
+                PromptError::CompletionError(CompletionError::ProviderError("no healthy provider".into()))
+            }))
+        })
+    }
+}
+
+/// OpenAI, then Anthropic, then a local model when both are down
+pub async fn failover_agent() -> Result<()> {
+    use crate::config_template::{Config, DynAgent, FailoverBuilder, ProviderKind};
+
+    let config = Config::load("rig.toml")?;
+    let links = FailoverBuilder::new(&config, "You are a helpful assistant.")
+        .link(ProviderKind::OpenAi, "gpt-4o")
+        .link(ProviderKind::Anthropic, "claude-3-5-sonnet-20241022")
+        .link(ProviderKind::Ollama, "llama3.2")
+        .build()?;
+
+    let agent = FallbackChain::new(links);
+    let response = agent.prompt("What is Rust?").await?;
+    println!("{}", response);
+
+    Ok(())
+}
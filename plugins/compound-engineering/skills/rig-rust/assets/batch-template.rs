@@ -0,0 +1,289 @@
+//! Batch Templates for Rig
+//!
+//! Run an agent or pipeline over thousands of inputs: a bounded number of
+//! calls in flight, transient failures retried per item, a progress bar, and
+//! a report that keeps every output alongside the inputs that still failed,
+//! so one bad document doesn't cost the other nine thousand.
+//!
+//! ```toml
+//! [dependencies]
+//! indicatif = "0.17"
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures::stream::{FuturesUnordered, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use rig::completion::Prompt;
+use rig::pipeline::Op;
+use tokio::sync::Semaphore;
+
+use crate::config_template::{ProviderPolicy, CLIENTS};
+use crate::pipeline_template::{RawDocument, SUMMARY_PREAMBLE};
+
+// =============================================================================
+// REPORT
+// =============================================================================
+
+#[derive(Debug)]
+pub struct BatchFailure<I> {
+    /// Position in the input vec
+    pub index: usize,
+    pub input: I,
+    pub attempts: u32,
+    pub error: anyhow::Error,
+}
+
+#[derive(Debug)]
+pub struct BatchReport<I, O> {
+    /// (input index, output), in input order
+    pub succeeded: Vec<(usize, O)>,
+    /// In input order
+    pub failed: Vec<BatchFailure<I>>,
+    pub elapsed: Duration,
+}
+
+impl<I, O> BatchReport<I, O> {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Every output in input order, or an error if any item failed
+    pub fn into_result(self) -> Result<Vec<O>> {
+        if let Some(first) = self.failed.first() {
+            anyhow::bail!(
+                "{} of {} items failed; first was item {}: {:#}",
+                self.failed.len(),
+                self.failed.len() + self.succeeded.len(),
+                first.index,
+                first.error
+            );
+        }
+        Ok(self.succeeded.into_iter().map(|(_, output)| output).collect())
+    }
+
+    /// The inputs to run again, once whatever failed them is fixed
+    pub fn failed_inputs(&mut self) -> Vec<I> {
+        self.failed.drain(..).map(|failure| failure.input).collect()
+    }
+}
+
+impl<I, O> fmt::Display for BatchReport<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} succeeded, {} failed in {:.1?}", self.succeeded.len(), self.failed.len(), self.elapsed)?;
+        for failure in self.failed.iter().take(10) {
+            write!(f, "\n  item {} after {} attempts: {:#}", failure.index, failure.attempts, failure.error)?;
+        }
+        if self.failed.len() > 10 {
+            write!(f, "\n  ... and {} more", self.failed.len() - 10)?;
+        }
+        Ok(())
+    }
+}
+
+// =============================================================================
+// BATCH RUNNER
+// =============================================================================
+
+// Usage:
+//     let report = BatchRunner::new()
+//         .concurrency(8)
+//         .message("summarizing")
+//         .run(docs, |doc| async move { Ok(agent.prompt(doc.text.as_str()).await?) })
+//         .await;
+//     println!("{report}");
+//
+// For a `rig::pipeline` op, `run_op(&pipeline, inputs)` does the same. A slot
+// is held only while a call is in flight, not during retry backoff, and
+// runners built with `.limit(shared)` share one cap (say, per provider).
+
+pub struct BatchRunner {
+    limit: Arc<Semaphore>,
+    policy: ProviderPolicy,
+    any_error: bool,
+    progress: bool,
+    message: String,
+}
+
+impl Default for BatchRunner {
+    fn default() -> Self {
+        Self {
+            limit: Arc::new(Semaphore::new(8)),
+            policy: ProviderPolicy::default(),
+            any_error: false,
+            progress: true,
+            message: String::new(),
+        }
+    }
+}
+
+impl BatchRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls in flight at once
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.limit = Arc::new(Semaphore::new(concurrency.max(1)));
+        self
+    }
+
+    /// Share a concurrency cap with other runners
+    pub fn limit(mut self, limit: Arc<Semaphore>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Retries per item and backoff between them; transient errors only
+    pub fn policy(mut self, policy: ProviderPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Also retry parse and extraction failures
+    pub fn retry_any_error(mut self) -> Self {
+        self.any_error = true;
+        self
+    }
+
+    /// No progress bar; for logs and non-interactive jobs
+    pub fn hide_progress(mut self) -> Self {
+        self.progress = false;
+        self
+    }
+
+    /// Label shown before the progress bar
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// Run `f` on every input. Never fails as a whole: items that still fail
+    /// after their retries are in the report with their inputs
+    pub async fn run<I, O, F, Fut>(&self, inputs: Vec<I>, f: F) -> BatchReport<I, O>
+    where
+        I: Clone,
+        F: Fn(I) -> Fut,
+        Fut: Future<Output = Result<O>>,
+    {
+        let started = Instant::now();
+        let bar = self.progress_bar(inputs.len());
+        let (mut succeeded, mut failed) = (Vec::new(), Vec::new());
+
+        let mut pending: FuturesUnordered<_> = inputs.into_iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let f = &f;
+                async move {
+                    let (attempts, result) = self.attempt(f, input.clone()).await;
+                    (index, input, attempts, result)
+                }
+            })
+            .collect();
+
+        while let Some((index, input, attempts, result)) = pending.next().await {
+            match result {
+                Ok(output) => succeeded.push((index, output)),
+                Err(error) => {
+                    tracing::warn!(index, attempts, error = %error, "batch item failed");
+                    failed.push(BatchFailure { index, input, attempts, error });
+                    bar.set_message(format!("{} ({} failed)", self.message, failed.len()));
+                }
+            }
+            bar.inc(1);
+        }
+        bar.finish();
+
+        succeeded.sort_by_key(|(index, _)| *index);
+        failed.sort_by_key(|failure| failure.index);
+        BatchReport { succeeded, failed, elapsed: started.elapsed() }
+    }
+
+    /// Every input through a pipeline op
+    pub async fn run_op<P, T, E>(&self, op: &P, inputs: Vec<P::Input>) -> BatchReport<P::Input, T>
+    where
+        P: Op<Output = Result<T, E>>,
+        P::Input: Clone,
+        E: Into<anyhow::Error>,
+    {
+        self.run(inputs, |input| async move { op.call(input).await.map_err(Into::into) }).await
+    }
+
+    /// (attempts made, last result)
+    async fn attempt<I, O, F, Fut>(&self, f: &F, input: I) -> (u32, Result<O>)
+    where
+        I: Clone,
+        F: Fn(I) -> Fut,
+        Fut: Future<Output = Result<O>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _slot = self.limit.acquire().await.expect("batch semaphore is never closed");
+                f(input.clone()).await
+            };
+            match result {
+                Err(e) if attempt < self.policy.max_retries
+                    && (self.any_error || ProviderPolicy::is_transient(&*e)) =>
+                {
+                    attempt += 1;
+                    tracing::debug!(attempt, error = %e, "retrying batch item");
+                    tokio::time::sleep(self.policy.delay(attempt)).await;
+                }
+                result => return (attempt + 1, result),
+            }
+        }
+    }
+
+    fn progress_bar(&self, len: usize) -> ProgressBar {
+        if !self.progress {
+            return ProgressBar::hidden();
+        }
+        // Draws to stderr, and nothing at all when stderr isn't a terminal
+        let bar = ProgressBar::new(len as u64);
+        bar.set_style(
+            ProgressStyle::with_template("{msg} {wide_bar} {pos}/{len} [{elapsed_precise}, eta {eta}]")
+                .expect("valid progress template"),
+        );
+        bar.set_message(self.message.clone());
+        bar
+    }
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// Summarize every document in `docs/`, then give the failures one more pass
+pub async fn summarize_corpus() -> Result<()> {
+    let agent = CLIENTS.openai().agent("gpt-4o-mini").preamble(SUMMARY_PREAMBLE).build();
+    let summarize = |doc: RawDocument| {
+        let agent = &agent;
+        async move { Ok((doc.id.clone(), agent.prompt(doc.text.as_str()).await?)) }
+    };
+
+    let runner = BatchRunner::new().concurrency(16).message("summarizing");
+    let mut report = runner.run(RawDocument::read_dir("docs")?, summarize).await;
+    println!("{report}");
+
+    if !report.is_complete() {
+        // By now the rate limit has usually recovered. Indices in `second`
+        // count into the retried inputs, so results carry the document id
+        let second = runner.message("retrying failures").run(report.failed_inputs(), summarize).await;
+        println!("{second}");
+        report.succeeded.extend(second.succeeded);
+        report.failed = second.failed;
+    }
+
+    for (_, (id, summary)) in &report.succeeded {
+        println!("## {id}\n{summary}\n");
+    }
+    for failure in &report.failed {
+        tracing::warn!(id = %failure.input.id, error = %format!("{:#}", failure.error), "gave up on document");
+    }
+    Ok(())
+}
@@ -0,0 +1,531 @@
+//! Chunking Templates for Rig
+//!
+//! Splitters that turn long text into embedding-sized chunks. Every splitter
+//! takes the same `ChunkConfig` (maximum size, overlap, and how size is
+//! measured), so loaders and RAG templates can swap strategies freely.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, LazyLock};
+
+use anyhow::Result;
+use serde_json::Value;
+use tiktoken_rs::CoreBPE;
+use tree_sitter::{Node, Parser};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::rag_template::{Document, Metadata};
+
+// =============================================================================
+// SHARED
+// =============================================================================
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub text: String,
+    /// Position within the source, starting at 0
+    pub index: usize,
+    pub metadata: Metadata,
+}
+
+impl Chunk {
+    /// A `Document` with id `{source}#{index}` and the source recorded in metadata
+    pub fn into_document(self, source: &str) -> Document {
+        let mut document = Document::new(format!("{source}#{}", self.index), self.text);
+        document.metadata = self.metadata;
+        document.metadata.insert("source".to_string(), source.into());
+        document.metadata.insert("chunk".to_string(), self.index.into());
+        document
+    }
+}
+
+/// How chunk size is measured
+pub trait Sizer: Send + Sync {
+    fn size(&self, text: &str) -> usize;
+}
+
+pub struct CharSizer;
+
+impl Sizer for CharSizer {
+    fn size(&self, text: &str) -> usize {
+        text.chars().count()
+    }
+}
+
+static CL100K: LazyLock<CoreBPE> =
+    LazyLock::new(|| tiktoken_rs::cl100k_base().expect("cl100k_base is bundled"));
+
+/// Counts tokens with the cl100k tokenizer (GPT-4, text-embedding-3-*).
+/// Other models tokenize differently, but close enough to stay under limits.
+pub struct TokenSizer;
+
+impl Sizer for TokenSizer {
+    fn size(&self, text: &str) -> usize {
+        CL100K.encode_ordinary(text).len()
+    }
+}
+
+#[derive(Clone)]
+pub struct ChunkConfig {
+    pub max_size: usize,
+    /// Trailing content repeated at the start of the next chunk
+    pub overlap: usize,
+    pub sizer: Arc<dyn Sizer>,
+}
+
+impl Default for ChunkConfig {
+    /// 512 tokens with 64 of overlap
+    fn default() -> Self {
+        Self { max_size: 512, overlap: 64, sizer: Arc::new(TokenSizer) }
+    }
+}
+
+impl ChunkConfig {
+    pub fn tokens(max_size: usize, overlap: usize) -> Result<Self> {
+        Self::new(max_size, overlap, Arc::new(TokenSizer))
+    }
+
+    pub fn chars(max_size: usize, overlap: usize) -> Result<Self> {
+        Self::new(max_size, overlap, Arc::new(CharSizer))
+    }
+
+    pub fn new(max_size: usize, overlap: usize, sizer: Arc<dyn Sizer>) -> Result<Self> {
+        anyhow::ensure!(max_size > 0, "max_size must be greater than 0");
+        anyhow::ensure!(overlap < max_size, "overlap ({overlap}) must be smaller than max_size ({max_size})");
+        Ok(Self { max_size, overlap, sizer })
+    }
+}
+
+pub trait Chunker: Send + Sync {
+    fn chunk(&self, text: &str) -> Vec<Chunk>;
+}
+
+/// Greedily pack `pieces` (which concatenate back to the source text) into
+/// chunks of at most `max_size`, carrying up to `overlap` of trailing pieces
+/// into the next chunk. A single piece larger than `max_size` becomes its own
+/// chunk; split it further before calling this if that matters.
+fn merge(pieces: &[&str], config: &ChunkConfig) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut window: VecDeque<(&str, usize)> = VecDeque::new();
+    let mut size = 0;
+    // Whether the window holds anything not already emitted
+    let mut fresh = false;
+
+    for piece in pieces {
+        let n = config.sizer.size(piece);
+        if size + n > config.max_size && fresh {
+            chunks.push(window.iter().map(|(p, _)| *p).collect::<String>());
+            fresh = false;
+            while size > config.overlap || (size + n > config.max_size && !window.is_empty()) {
+                let (_, dropped) = window.pop_front().expect("window is non-empty while size > 0");
+                size -= dropped;
+            }
+        }
+        window.push_back((piece, n));
+        size += n;
+        fresh = true;
+    }
+    if fresh {
+        chunks.push(window.iter().map(|(p, _)| *p).collect::<String>());
+    }
+
+    chunks.into_iter()
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+fn number(texts: Vec<String>, metadata: &Metadata) -> Vec<Chunk> {
+    texts.into_iter()
+        .enumerate()
+        .map(|(index, text)| Chunk { text, index, metadata: metadata.clone() })
+        .collect()
+}
+
+// =============================================================================
+// FIXED SIZE
+// =============================================================================
+
+/// Packs whole words up to the size limit, ignoring sentence and paragraph
+/// structure. Predictable and fast; the baseline to compare others against.
+pub struct FixedSizeChunker {
+    pub config: ChunkConfig,
+}
+
+impl Chunker for FixedSizeChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let words: Vec<&str> = text.split_inclusive(char::is_whitespace).collect();
+        number(merge(&words, &self.config), &Metadata::new())
+    }
+}
+
+// =============================================================================
+// SENTENCE BOUNDARY
+// =============================================================================
+
+/// Packs whole sentences (Unicode sentence boundaries), so no chunk starts or
+/// ends mid-sentence. Sentences longer than the limit fall back to words.
+pub struct SentenceChunker {
+    pub config: ChunkConfig,
+}
+
+impl Chunker for SentenceChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let mut pieces = Vec::new();
+        for sentence in text.split_sentence_bounds() {
+            if self.config.sizer.size(sentence) > self.config.max_size {
+                pieces.extend(sentence.split_inclusive(char::is_whitespace));
+            } else {
+                pieces.push(sentence);
+            }
+        }
+        number(merge(&pieces, &self.config), &Metadata::new())
+    }
+}
+
+// =============================================================================
+// RECURSIVE
+// =============================================================================
+
+/// Splits on the coarsest separator that works: paragraphs, then lines, then
+/// sentences, then words. Keeps related text together better than the other
+/// splitters for prose and mixed content; the usual default.
+pub struct RecursiveChunker {
+    pub config: ChunkConfig,
+    pub separators: Vec<&'static str>,
+}
+
+impl RecursiveChunker {
+    pub fn new(config: ChunkConfig) -> Self {
+        Self { config, separators: vec!["\n\n", "\n", ". ", " "] }
+    }
+
+    fn split<'t>(&self, text: &'t str, separators: &[&str], out: &mut Vec<&'t str>) {
+        if self.config.sizer.size(text) <= self.config.max_size {
+            out.push(text);
+            return;
+        }
+        let Some((separator, rest)) = separators.split_first() else {
+            // No separators left: emit as-is rather than cutting inside a word
+            out.push(text);
+            return;
+        };
+        for piece in text.split_inclusive(separator) {
+            self.split(piece, rest, out);
+        }
+    }
+}
+
+impl Chunker for RecursiveChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let mut pieces = Vec::new();
+        self.split(text, &self.separators, &mut pieces);
+        number(merge(&pieces, &self.config), &Metadata::new())
+    }
+}
+
+// =============================================================================
+// MARKDOWN HEADERS
+// =============================================================================
+
+/// Splits at markdown headings first, so chunks never span sections, then
+/// splits long sections recursively. Each chunk records its heading path as
+/// `headings` (e.g. `["Install", "From source"]`) and repeats the heading in
+/// its text so the embedding carries the section context.
+pub struct MarkdownChunker {
+    pub config: ChunkConfig,
+}
+
+/// (heading path, section text including its heading line)
+fn markdown_sections(text: &str) -> Vec<(Vec<String>, String)> {
+    let mut sections = Vec::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut body = String::new();
+    let mut in_fence = false;
+
+    let headings = |path: &[(usize, String)]| path.iter().map(|(_, h)| h.clone()).collect::<Vec<_>>();
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        let is_heading = !in_fence
+            && (1..=6).contains(&level)
+            && trimmed[level..].starts_with(' ');
+
+        if is_heading {
+            if !body.trim().is_empty() {
+                sections.push((headings(&path), std::mem::take(&mut body)));
+            }
+            body.clear();
+            path.retain(|(l, _)| *l < level);
+            path.push((level, trimmed[level..].trim().to_string()));
+        }
+        body.push_str(line);
+        body.push('\n');
+    }
+    if !body.trim().is_empty() {
+        sections.push((headings(&path), body));
+    }
+    sections
+}
+
+impl Chunker for MarkdownChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let recursive = RecursiveChunker::new(self.config.clone());
+        let mut chunks = Vec::new();
+        for (headings, section) in markdown_sections(text) {
+            let mut metadata = Metadata::new();
+            metadata.insert(
+                "headings".to_string(),
+                Value::Array(headings.iter().cloned().map(Value::String).collect()),
+            );
+            let heading_line = headings.last().map(|h| format!("{h}\n"));
+            for (i, chunk) in recursive.chunk(&section).into_iter().enumerate() {
+                // The first piece already starts with the heading line
+                let text = match &heading_line {
+                    Some(h) if i > 0 => format!("{h}{}", chunk.text),
+                    _ => chunk.text,
+                };
+                chunks.push(Chunk { text, index: chunks.len(), metadata: metadata.clone() });
+            }
+        }
+        chunks
+    }
+}
+
+// =============================================================================
+// SOURCE CODE
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeLanguage {
+    Rust,
+    TypeScript,
+    /// TypeScript with JSX; a separate grammar, since `<T>x` casts don't parse as TSX
+    Tsx,
+    Python,
+}
+
+impl CodeLanguage {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "rs" => Some(Self::Rust),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            "py" | "pyi" => Some(Self::Python),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::TypeScript | Self::Tsx => "typescript",
+            Self::Python => "python",
+        }
+    }
+
+    fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+        }
+    }
+
+    /// Separator between a container and its members in symbol names
+    fn path_separator(self) -> &'static str {
+        match self {
+            Self::Rust => "::",
+            Self::TypeScript | Self::Tsx | Self::Python => ".",
+        }
+    }
+
+    /// Wrapper nodes whose interesting part is one field down
+    fn unwrap<'t>(self, node: Node<'t>) -> Node<'t> {
+        let field = match (self, node.kind()) {
+            (Self::TypeScript | Self::Tsx, "export_statement") => "declaration",
+            (Self::Python, "decorated_definition") => "definition",
+            _ => return node,
+        };
+        node.child_by_field_name(field).unwrap_or(node)
+    }
+
+    fn is_definition(self, kind: &str) -> bool {
+        match self {
+            Self::Rust => matches!(
+                kind,
+                "function_item" | "function_signature_item" | "impl_item" | "trait_item" | "struct_item"
+                    | "enum_item" | "union_item" | "mod_item" | "macro_definition" | "const_item"
+                    | "static_item" | "type_item"
+            ),
+            Self::TypeScript | Self::Tsx => matches!(
+                kind,
+                "function_declaration" | "generator_function_declaration" | "class_declaration"
+                    | "abstract_class_declaration" | "interface_declaration" | "type_alias_declaration"
+                    | "enum_declaration" | "lexical_declaration" | "method_definition"
+                    | "public_field_definition" | "internal_module"
+            ),
+            Self::Python => matches!(kind, "function_definition" | "class_definition"),
+        }
+    }
+
+    fn symbol(self, node: Node, source: &str) -> Option<String> {
+        let node = self.unwrap(node);
+        let text = |n: Node| source[n.byte_range()].to_string();
+        match node.kind() {
+            "impl_item" => {
+                let ty = node.child_by_field_name("type").map(text)?;
+                Some(match node.child_by_field_name("trait").map(text) {
+                    Some(tr) => format!("<{ty} as {tr}>"),
+                    None => ty,
+                })
+            }
+            "lexical_declaration" => {
+                let mut cursor = node.walk();
+                let declarator = node.named_children(&mut cursor).find(|c| c.kind() == "variable_declarator")?;
+                declarator.child_by_field_name("name").map(text)
+            }
+            _ => node.child_by_field_name("name").map(text),
+        }
+    }
+}
+
+/// Splits source files along definition boundaries (functions, impls,
+/// classes, ...) using tree-sitter, so a chunk never starts halfway through a
+/// function. Small neighbouring definitions share a chunk; an impl or class
+/// too big for one chunk is split into its members, and a single oversized
+/// function falls back to line-based splitting.
+///
+/// Chunks carry `language`, `symbols` (e.g. `["Indexer::run"]`), and
+/// `start_line`/`end_line` metadata. Comments and attributes directly above a
+/// definition stay with it.
+pub struct CodeChunker {
+    pub config: ChunkConfig,
+    pub language: CodeLanguage,
+}
+
+/// A span of source and the symbols defined in it
+struct Unit {
+    start: usize,
+    end: usize,
+    symbols: Vec<String>,
+}
+
+impl CodeChunker {
+    /// Units covering `start..end` of `parent`'s named children, gaps included
+    fn units(&self, parent: Node, source: &str, start: usize, end: usize, prefix: Option<&str>, out: &mut Vec<Unit>) {
+        let mut cursor = parent.walk();
+        let mut pending = start;
+        for child in parent.named_children(&mut cursor) {
+            if child.end_byte() <= start || child.start_byte() >= end {
+                continue;
+            }
+            let inner = self.language.unwrap(child);
+            if !self.language.is_definition(inner.kind()) {
+                continue;
+            }
+            // Text since the previous definition (comments, attributes, imports)
+            // travels with this one
+            let span_start = pending;
+            let span_end = child.end_byte();
+            pending = span_end;
+
+            let name = self.language.symbol(child, source);
+            let symbol = match (prefix, name) {
+                (Some(p), Some(n)) => Some(format!("{p}{}{n}", self.language.path_separator())),
+                (None, Some(n)) => Some(n),
+                (_, None) => None,
+            };
+
+            let too_big = self.config.sizer.size(&source[span_start..span_end]) > self.config.max_size;
+            let body = inner.child_by_field_name("body");
+            match (too_big, body) {
+                (true, Some(body)) if body.named_child_count() > 0 => {
+                    // Header through the body's opening brace (Python blocks have none),
+                    // then each member
+                    let body_start = body.start_byte() + usize::from(source[body.byte_range()].starts_with('{'));
+                    out.push(Unit { start: span_start, end: body_start, symbols: symbol.clone().into_iter().collect() });
+                    let container = symbol.as_deref().or(prefix);
+                    self.units(body, source, body_start, span_end, container, out);
+                }
+                _ => out.push(Unit { start: span_start, end: span_end, symbols: symbol.into_iter().collect() }),
+            }
+        }
+        if pending < end {
+            out.push(Unit { start: pending, end, symbols: Vec::new() });
+        }
+    }
+
+    fn fallback(&self, text: &str) -> Vec<Chunk> {
+        let mut metadata = Metadata::new();
+        metadata.insert("language".to_string(), self.language.name().into());
+        let mut chunks = RecursiveChunker::new(self.config.clone()).chunk(text);
+        chunks.iter_mut().for_each(|c| c.metadata = metadata.clone());
+        chunks
+    }
+}
+
+fn line_of(source: &str, byte: usize) -> usize {
+    source[..byte].matches('\n').count() + 1
+}
+
+impl Chunker for CodeChunker {
+    fn chunk(&self, source: &str) -> Vec<Chunk> {
+        let mut parser = Parser::new();
+        if parser.set_language(&self.language.grammar()).is_err() {
+            return self.fallback(source);
+        }
+        let Some(tree) = parser.parse(source, None) else {
+            return self.fallback(source);
+        };
+
+        let mut units = Vec::new();
+        self.units(tree.root_node(), source, 0, source.len(), None, &mut units);
+
+        // Pack neighbouring units up to the size limit
+        let mut groups: Vec<Unit> = Vec::new();
+        for unit in units {
+            let fits = groups.last().is_some_and(|last| {
+                self.config.sizer.size(&source[last.start..unit.end]) <= self.config.max_size
+            });
+            match groups.last_mut() {
+                Some(last) if fits => {
+                    last.end = unit.end;
+                    last.symbols.extend(unit.symbols);
+                }
+                _ => groups.push(unit),
+            }
+        }
+
+        let recursive = RecursiveChunker::new(self.config.clone());
+        let mut chunks = Vec::new();
+        for group in groups {
+            let text = &source[group.start..group.end];
+            if text.trim().is_empty() {
+                continue;
+            }
+            // Still too big: a single huge function; split it by lines
+            let pieces = if self.config.sizer.size(text) > self.config.max_size {
+                recursive.chunk(text).into_iter().map(|c| c.text).collect()
+            } else {
+                vec![text.trim().to_string()]
+            };
+            for piece in pieces {
+                let mut metadata = Metadata::new();
+                metadata.insert("language".to_string(), self.language.name().into());
+                metadata.insert(
+                    "symbols".to_string(),
+                    Value::Array(group.symbols.iter().cloned().map(Value::String).collect()),
+                );
+                metadata.insert("start_line".to_string(), line_of(source, group.start).into());
+                metadata.insert("end_line".to_string(), line_of(source, group.end).into());
+                chunks.push(Chunk { text: piece, index: chunks.len(), metadata });
+            }
+        }
+        chunks
+    }
+}
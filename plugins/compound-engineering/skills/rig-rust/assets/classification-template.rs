@@ -0,0 +1,298 @@
+//! Classification Templates for Rig
+//!
+//! Label text with a closed set of labels you define as an enum: the model
+//! returns one of them plus a confidence, batches run concurrently, and a
+//! confusion matrix against a labeled set shows which labels get mixed up.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::path::Path;
+
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use rig::completion::CompletionModel;
+use rig::extractor::Extractor;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config_template::CLIENTS;
+
+// =============================================================================
+// LABEL SCHEMA
+// =============================================================================
+
+// Derive the serde and schema traits on a plain enum and describe each
+// label; the descriptions go into the preamble, the enum into the schema, so
+// the model can't answer with a label that doesn't exist:
+//
+//     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+//     #[serde(rename_all = "snake_case")]
+//     enum Topic { Billing, Outage, HowTo }
+//
+//     impl Label for Topic {
+//         const ALL: &'static [Self] = &[Topic::Billing, Topic::Outage, Topic::HowTo];
+//         fn describe(self) -> &'static str { ... }
+//     }
+
+pub trait Label:
+    Copy + Eq + Hash + fmt::Debug + Serialize + DeserializeOwned + JsonSchema + Send + Sync + 'static
+{
+    /// Every label, in the order reports list them
+    const ALL: &'static [Self];
+
+    /// When this label applies, in one line for the model
+    fn describe(self) -> &'static str;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Prediction<L> {
+    pub label: L,
+    /// 0 to 1
+    pub confidence: f32,
+}
+
+impl<L: Label> Prediction<L> {
+    /// The label, or `None` when the model wasn't sure enough to act on it
+    pub fn confident(&self, threshold: f32) -> Option<L> {
+        (self.confidence >= threshold).then_some(self.label)
+    }
+}
+
+/// Preamble for a classifier extractor: the task plus every label's description
+pub fn label_preamble<L: Label>(task: &str) -> String {
+    let mut preamble = format!("{task}\n\nChoose exactly one label:\n");
+    for label in L::ALL {
+        let name = serde_json::to_value(label).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_else(|| format!("{label:?}"));
+        preamble.push_str(&format!("- {name}: {}\n", label.describe()));
+    }
+    preamble.push_str("\nSet confidence to how sure you are, from 0 to 1; use below 0.5 when two labels fit equally well.");
+    preamble
+}
+
+// =============================================================================
+// CLASSIFIER
+// =============================================================================
+
+pub struct Classifier<M: CompletionModel, L> {
+    extractor: Extractor<M, Prediction<L>>,
+    concurrency: usize,
+}
+
+impl<M: CompletionModel, L: Label> Classifier<M, L> {
+    /// `extractor` should use `label_preamble::<L>` (plus any examples) as its preamble
+    pub fn new(extractor: Extractor<M, Prediction<L>>) -> Self {
+        Self { extractor, concurrency: 8 }
+    }
+
+    /// Texts in flight at once in `classify_batch`
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn classify(&self, text: &str) -> Result<Prediction<L>> {
+        let mut prediction = self.extractor.extract(text).await?;
+        prediction.confidence = prediction.confidence.clamp(0.0, 1.0);
+        Ok(prediction)
+    }
+
+    /// One result per text, in input order; a failed text doesn't fail the batch
+    pub async fn classify_batch(&self, texts: &[String]) -> Vec<Result<Prediction<L>>> {
+        stream::iter(texts)
+            .map(|text| self.classify(text))
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+}
+
+// =============================================================================
+// EVALUATION
+// =============================================================================
+
+// One JSON object per line:
+//     {"text": "I was charged twice this month", "label": "billing"}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabeledExample<L> {
+    pub text: String,
+    pub label: L,
+}
+
+pub fn load_labeled<L: Label>(path: impl AsRef<Path>) -> Result<Vec<LabeledExample<L>>> {
+    let path = path.as_ref();
+    let raw = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+    raw.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| anyhow::anyhow!("{}:{}: {e}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// Counts of (expected, predicted) pairs
+#[derive(Debug, Clone)]
+pub struct ConfusionMatrix<L: Label> {
+    counts: HashMap<(L, L), usize>,
+    /// Examples the classifier errored on; not counted in the matrix
+    pub failures: usize,
+}
+
+impl<L: Label> Default for ConfusionMatrix<L> {
+    fn default() -> Self {
+        Self { counts: HashMap::new(), failures: 0 }
+    }
+}
+
+impl<L: Label> ConfusionMatrix<L> {
+    pub fn record(&mut self, expected: L, predicted: L) {
+        *self.counts.entry((expected, predicted)).or_default() += 1;
+    }
+
+    pub fn count(&self, expected: L, predicted: L) -> usize {
+        self.counts.get(&(expected, predicted)).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        let correct: usize = L::ALL.iter().map(|&l| self.count(l, l)).sum();
+        ratio(correct, self.total())
+    }
+
+    /// Of the texts predicted as `label`, the share that were `label`
+    pub fn precision(&self, label: L) -> f64 {
+        let predicted: usize = L::ALL.iter().map(|&e| self.count(e, label)).sum();
+        ratio(self.count(label, label), predicted)
+    }
+
+    /// Of the texts that were `label`, the share predicted as `label`
+    pub fn recall(&self, label: L) -> f64 {
+        let expected: usize = L::ALL.iter().map(|&p| self.count(label, p)).sum();
+        ratio(self.count(label, label), expected)
+    }
+
+    pub fn f1(&self, label: L) -> f64 {
+        let (p, r) = (self.precision(label), self.recall(label));
+        if p + r == 0.0 { 0.0 } else { 2.0 * p * r / (p + r) }
+    }
+
+    /// Unweighted mean F1, so rare labels count as much as common ones
+    pub fn macro_f1(&self) -> f64 {
+        L::ALL.iter().map(|&l| self.f1(l)).sum::<f64>() / L::ALL.len().max(1) as f64
+    }
+}
+
+fn ratio(n: usize, d: usize) -> f64 {
+    if d == 0 { 0.0 } else { n as f64 / d as f64 }
+}
+
+impl<L: Label> fmt::Display for ConfusionMatrix<L> {
+    /// Rows are expected labels, columns predicted, then per-label P/R/F1
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = L::ALL.iter().map(|l| format!("{l:?}")).collect();
+        let width = names.iter().map(String::len).max().unwrap_or(0).max(6);
+
+        write!(f, "{:width$}", "")?;
+        for name in &names {
+            write!(f, " {name:>width$}")?;
+        }
+        writeln!(f, " {:>6} {:>6} {:>6}", "P", "R", "F1")?;
+        for (&expected, name) in L::ALL.iter().zip(&names) {
+            write!(f, "{name:width$}")?;
+            for &predicted in L::ALL {
+                write!(f, " {:>width$}", self.count(expected, predicted))?;
+            }
+            writeln!(
+                f,
+                " {:>6.3} {:>6.3} {:>6.3}",
+                self.precision(expected),
+                self.recall(expected),
+                self.f1(expected)
+            )?;
+        }
+        write!(
+            f,
+            "accuracy {:.3}  macro-F1 {:.3}  ({} examples, {} failed)",
+            self.accuracy(),
+            self.macro_f1(),
+            self.total(),
+            self.failures
+        )
+    }
+}
+
+/// Classify every example and tally predictions against the labels
+pub async fn evaluate<M, L>(classifier: &Classifier<M, L>, examples: &[LabeledExample<L>]) -> Result<ConfusionMatrix<L>>
+where
+    M: CompletionModel,
+    L: Label,
+{
+    anyhow::ensure!(!examples.is_empty(), "no labeled examples");
+    let texts: Vec<String> = examples.iter().map(|e| e.text.clone()).collect();
+    let mut matrix = ConfusionMatrix::default();
+    for (example, prediction) in examples.iter().zip(classifier.classify_batch(&texts).await) {
+        match prediction {
+            Ok(prediction) => matrix.record(example.label, prediction.label),
+            Err(e) => {
+                tracing::warn!(error = %e, "classification failed");
+                matrix.failures += 1;
+            }
+        }
+    }
+    Ok(matrix)
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SupportTopic {
+    Billing,
+    Outage,
+    HowTo,
+    AccountAccess,
+    Other,
+}
+
+impl Label for SupportTopic {
+    const ALL: &'static [Self] = &[Self::Billing, Self::Outage, Self::HowTo, Self::AccountAccess, Self::Other];
+
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Billing => "charges, invoices, refunds, plan changes",
+            Self::Outage => "the service is down, erroring, or much slower than usual",
+            Self::HowTo => "asks how to do something the product already supports",
+            Self::AccountAccess => "login, password reset, 2FA, or locked accounts",
+            Self::Other => "none of the above",
+        }
+    }
+}
+
+/// Score the classifier on `eval/support.jsonl`, then label the inbox
+pub async fn classify_support_emails() -> Result<()> {
+    let preamble = label_preamble::<SupportTopic>("Classify the customer support email by its main request.");
+    let extractor = CLIENTS.openai().extractor::<Prediction<SupportTopic>>("gpt-4o-mini").preamble(&preamble).build();
+    let classifier = Classifier::new(extractor).concurrency(16);
+
+    let examples = load_labeled::<SupportTopic>("eval/support.jsonl")?;
+    println!("{}", evaluate(&classifier, &examples).await?);
+
+    let inbox = vec![
+        "I can't log in since I turned on two-factor".to_string(),
+        "Your API has returned 503 for the last ten minutes".to_string(),
+    ];
+    for (text, prediction) in inbox.iter().zip(classifier.classify_batch(&inbox).await) {
+        match prediction?.confident(0.7) {
+            Some(topic) => println!("{topic:?}: {text}"),
+            None => println!("needs a human: {text}"),
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,47 @@
 //! Configuration Templates for Rig
+//!
+//! OpenAI and the OpenAI-compatible providers are always available. The rest
+//! sit behind cargo features so you only compile what you use:
+//!
+//! ```toml
+//! [features]
+//! default = ["anthropic"]
+//! anthropic = []
+//! gemini = []
+//! cohere = []
+//! ollama = []
+//! bedrock = ["dep:rig-bedrock"]
+//!
+//! [dependencies]
+//! rig-bedrock = { version = "0.2", optional = true }
+//! ```
 
-use rig::providers::{openai, anthropic, gemini, cohere};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use arc_swap::ArcSwap;
+use futures::future::BoxFuture;
+use rig::agent::{Agent, AgentBuilder};
+use rig::completion::{CompletionError, CompletionModel, Prompt, PromptError};
+use rig::embeddings::EmbeddingError;
+use rig::providers::{azure, mistral, openai};
+#[cfg(feature = "anthropic")]
+use rig::providers::anthropic;
+#[cfg(feature = "cohere")]
+use rig::providers::cohere;
+#[cfg(feature = "gemini")]
+use rig::providers::gemini;
+#[cfg(feature = "ollama")]
+use rig::providers::ollama;
+#[cfg(feature = "bedrock")]
+use rig_bedrock::client as bedrock;
+use serde::Deserialize;
+
+use crate::middleware_template::{Idempotency, RetryTool, ToolTimeout};
 
 // OpenAI Configuration
 pub fn openai_client() -> openai::Client {
@@ -9,34 +50,2480 @@ pub fn openai_client() -> openai::Client {
 }
 
 // Anthropic Configuration
+#[cfg(feature = "anthropic")]
 pub fn anthropic_client() -> anthropic::Client {
     // Uses ANTHROPIC_API_KEY env var
     anthropic::Client::from_env()
 }
 
-// Gemini Configuration  
+// Gemini Configuration
+#[cfg(feature = "gemini")]
 pub fn gemini_client() -> gemini::Client {
     // Uses GEMINI_API_KEY env var
     gemini::Client::from_env()
 }
 
 // Cohere Configuration
+#[cfg(feature = "cohere")]
 pub fn cohere_client() -> cohere::Client {
     // Uses COHERE_API_KEY env var
     cohere::Client::from_env()
 }
 
+// Ollama Configuration (local models, no API key)
+//
+// Streaming works the same as for hosted providers (`agent.stream_prompt(..)`);
+// the first token is delayed while Ollama loads the model into memory.
+#[cfg(feature = "ollama")]
+pub struct OllamaConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[cfg(feature = "ollama")]
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self { host: "localhost".to_string(), port: 11434 }
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl OllamaConfig {
+    /// Reads OLLAMA_HOST as `host` or `host:port`, the same variable the Ollama CLI uses
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(value) = std::env::var("OLLAMA_HOST") {
+            let value = value.trim_start_matches("http://").trim_end_matches('/');
+            match value.rsplit_once(':') {
+                Some((host, port)) => {
+                    config.host = host.to_string();
+                    config.port = port.parse().unwrap_or(config.port);
+                }
+                None => config.host = value.to_string(),
+            }
+        }
+        config
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}:{}", self.host, self.port)
+    }
+
+    pub fn client(&self) -> ollama::Client {
+        ollama::Client::from_url(&self.base_url())
+    }
+
+    /// Models already pulled locally (`ollama pull llama3.2`)
+    pub async fn list_models(&self) -> Result<Vec<String>, reqwest::Error> {
+        #[derive(serde::Deserialize)]
+        struct Tags { models: Vec<Model> }
+        #[derive(serde::Deserialize)]
+        struct Model { name: String }
+
+        let tags: Tags = reqwest::get(format!("{}/api/tags", self.base_url()))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(tags.models.into_iter().map(|m| m.name).collect())
+    }
+}
+
+#[cfg(feature = "ollama")]
+pub fn ollama_client() -> ollama::Client {
+    OllamaConfig::from_env().client()
+}
+
+// Azure OpenAI Configuration
+//
+// Azure addresses models by deployment name, so map the model names used in
+// the templates to your deployments instead of editing every `agent()` call:
+//     let azure = AzureConfig::from_env()?;
+//     let agent = azure.client().agent(azure.deployment("gpt-4o")).build();
+pub struct AzureConfig {
+    pub endpoint: String,
+    pub api_key: String,
+    pub api_version: String,
+    /// Model name -> deployment name
+    pub deployments: HashMap<String, String>,
+}
+
+impl AzureConfig {
+    /// Uses AZURE_OPENAI_ENDPOINT, AZURE_OPENAI_API_KEY, optional
+    /// AZURE_OPENAI_API_VERSION, and AZURE_OPENAI_DEPLOYMENTS
+    /// (`gpt-4o=prod-gpt4o,gpt-4o-mini=cheap-mini`)
+    pub fn from_env() -> Result<Self, std::env::VarError> {
+        let deployments = std::env::var("AZURE_OPENAI_DEPLOYMENTS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(model, deployment)| (model.trim().to_string(), deployment.trim().to_string()))
+            .collect();
+
+        Ok(Self {
+            endpoint: std::env::var("AZURE_OPENAI_ENDPOINT")?,
+            api_key: std::env::var("AZURE_OPENAI_API_KEY")?,
+            api_version: std::env::var("AZURE_OPENAI_API_VERSION")
+                .unwrap_or_else(|_| "2024-10-21".to_string()),
+            deployments,
+        })
+    }
+
+    pub fn deploy(mut self, model: impl Into<String>, deployment: impl Into<String>) -> Self {
+        self.deployments.insert(model.into(), deployment.into());
+        self
+    }
+
+    /// Deployment for a model name; unmapped names are assumed to be deployments already
+    pub fn deployment<'a>(&'a self, model: &'a str) -> &'a str {
+        self.deployments.get(model).map(String::as_str).unwrap_or(model)
+    }
+
+    pub fn client(&self) -> azure::Client {
+        azure::Client::new(&self.api_key, &self.api_version, &self.endpoint)
+    }
+}
+
+pub fn azure_openai_client() -> azure::Client {
+    AzureConfig::from_env()
+        .expect("AZURE_OPENAI_ENDPOINT and AZURE_OPENAI_API_KEY must be set")
+        .client()
+}
+
+// AWS Bedrock Configuration (`bedrock` feature, adds the `rig-bedrock` crate)
+//
+// Credentials come from the standard AWS chain: AWS_ACCESS_KEY_ID /
+// AWS_SECRET_ACCESS_KEY, AWS_PROFILE, SSO, then instance or task roles.
+// Models must be enabled per region in the Bedrock console first.
+pub mod bedrock_models {
+    pub const CLAUDE_3_5_SONNET: &str = "anthropic.claude-3-5-sonnet-20241022-v2:0";
+    pub const CLAUDE_3_HAIKU: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+    pub const TITAN_TEXT_PREMIER: &str = "amazon.titan-text-premier-v1:0";
+    pub const TITAN_EMBED_V2: &str = "amazon.titan-embed-text-v2:0";
+
+    /// Cross-region inference profile (`us.anthropic.claude-*`), required for
+    /// some newer models and spreads load across regions
+    pub fn cross_region(geo: &str, model_id: &str) -> String {
+        format!("{geo}.{model_id}")
+    }
+}
+
+/// Uses AWS_REGION, falling back to us-east-1
+#[cfg(feature = "bedrock")]
+pub async fn bedrock_client() -> bedrock::Client {
+    let region = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    bedrock_client_in(&region).await
+}
+
+#[cfg(feature = "bedrock")]
+pub async fn bedrock_client_in(region: &str) -> bedrock::Client {
+    bedrock::ClientBuilder::new().region(region).build().await
+}
+
+// Groq Configuration (OpenAI-compatible, low latency)
+//
+// Returns an `openai::Client`, so any template swaps over by replacing
+// `openai_client()` and the model name.
+pub mod groq_models {
+    pub const LLAMA_3_3_70B: &str = "llama-3.3-70b-versatile";
+    pub const LLAMA_3_1_8B: &str = "llama-3.1-8b-instant";
+    pub const MIXTRAL_8X7B: &str = "mixtral-8x7b-32768";
+    pub const GEMMA2_9B: &str = "gemma2-9b-it";
+}
+
+const GROQ_BASE_URL: &str = "https://api.groq.com/openai/v1";
+
+pub fn groq_client() -> openai::Client {
+    // Uses GROQ_API_KEY env var
+    let api_key = std::env::var("GROQ_API_KEY").expect("GROQ_API_KEY not set");
+    openai::Client::from_url(&api_key, GROQ_BASE_URL)
+}
+
+// Mistral Configuration
+//
+// `-latest` aliases track the newest release and can change behavior under
+// you; pin a dated id (`mistral-large-2411`) for production.
+//     let chat = mistral_client().agent(mistral_models::LARGE).build();
+//     let embeddings = mistral_client().embedding_model(mistral_models::EMBED);
+pub mod mistral_models {
+    pub const LARGE: &str = "mistral-large-latest";
+    pub const SMALL: &str = "mistral-small-latest";
+    pub const CODESTRAL: &str = "codestral-latest";
+    pub const NEMO: &str = "open-mistral-nemo";
+    /// 1024-dimensional embeddings
+    pub const EMBED: &str = "mistral-embed";
+}
+
+pub fn mistral_client() -> mistral::Client {
+    // Uses MISTRAL_API_KEY env var
+    mistral::Client::from_env()
+}
+
+// DeepSeek Configuration (OpenAI-compatible)
+//
+// `deepseek-chat` works everywhere `openai_client()` does. `deepseek-reasoner`
+// returns its chain of thought in a separate `reasoning_content` field that
+// the OpenAI client drops, ignores temperature, and does not support tool
+// calls; use `deepseek_reason` to keep the reasoning. Never send
+// `reasoning_content` back in chat history. The API rejects it.
+pub mod deepseek_models {
+    pub const CHAT: &str = "deepseek-chat";
+    pub const REASONER: &str = "deepseek-reasoner";
+}
+
+const DEEPSEEK_BASE_URL: &str = "https://api.deepseek.com";
+
+pub fn deepseek_client() -> openai::Client {
+    // Uses DEEPSEEK_API_KEY env var
+    let api_key = std::env::var("DEEPSEEK_API_KEY").expect("DEEPSEEK_API_KEY not set");
+    openai::Client::from_url(&api_key, DEEPSEEK_BASE_URL)
+}
+
+#[derive(Debug, Clone)]
+pub struct ReasonedAnswer {
+    pub reasoning: String,
+    pub answer: String,
+}
+
+/// Single-turn call to `deepseek-reasoner` that keeps the reasoning text
+pub async fn deepseek_reason(prompt: &str) -> anyhow::Result<ReasonedAnswer> {
+    let api_key = std::env::var("DEEPSEEK_API_KEY")?;
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(format!("{DEEPSEEK_BASE_URL}/chat/completions"))
+        .bearer_auth(api_key)
+        .json(&serde_json::json!({
+            "model": deepseek_models::REASONER,
+            "messages": [{ "role": "user", "content": prompt }],
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let message = &response["choices"][0]["message"];
+    Ok(ReasonedAnswer {
+        reasoning: message["reasoning_content"].as_str().unwrap_or_default().to_string(),
+        answer: message["content"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+// OpenRouter Configuration (OpenAI-compatible, one key for many vendors)
+//
+// Model ids are `vendor/model`. HTTP-Referer and X-Title identify your app to
+// OpenRouter for usage attribution and its app rankings.
+const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+
+pub fn openrouter_client() -> openai::Client {
+    // Uses OPENROUTER_API_KEY, and optionally OPENROUTER_APP_URL / OPENROUTER_APP_NAME
+    let api_key = std::env::var("OPENROUTER_API_KEY").expect("OPENROUTER_API_KEY not set");
+    let app_url = std::env::var("OPENROUTER_APP_URL").unwrap_or_else(|_| "https://localhost".to_string());
+    let app_name = std::env::var("OPENROUTER_APP_NAME").unwrap_or_else(|_| "rig-app".to_string());
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("HTTP-Referer", app_url.parse().expect("invalid OPENROUTER_APP_URL"));
+    headers.insert("X-Title", app_name.parse().expect("invalid OPENROUTER_APP_NAME"));
+    let http = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .expect("failed to build HTTP client");
+
+    openai::Client::builder(&api_key)
+        .base_url(OPENROUTER_BASE_URL)
+        .custom_client(http)
+        .build()
+}
+
+/// Routing preferences travel in the request body: `models` lists fallbacks
+/// tried in order, `provider` controls which hosts may serve each model
+pub async fn openrouter_routed_agent() -> anyhow::Result<()> {
+    let agent = openrouter_client()
+        .agent("anthropic/claude-3.5-sonnet")
+        .additional_params(serde_json::json!({
+            "models": ["openai/gpt-4o", "meta-llama/llama-3.3-70b-instruct"],
+            "provider": {
+                "order": ["Anthropic", "Amazon Bedrock"],
+                "allow_fallbacks": true,
+                "data_collection": "deny",
+            },
+        }))
+        .build();
+
+    let response = agent.prompt("Summarize the CAP theorem in two sentences.").await?;
+    println!("{}", response);
+
+    Ok(())
+}
+
+// Together AI Configuration (OpenAI-compatible, open-weight models)
+pub mod together_models {
+    pub const LLAMA_3_3_70B: &str = "meta-llama/Llama-3.3-70B-Instruct-Turbo";
+    pub const LLAMA_3_1_8B: &str = "meta-llama/Meta-Llama-3.1-8B-Instruct-Turbo";
+    pub const QWEN_2_5_72B: &str = "Qwen/Qwen2.5-72B-Instruct-Turbo";
+    pub const QWEN_2_5_CODER_32B: &str = "Qwen/Qwen2.5-Coder-32B-Instruct";
+    pub const M2_BERT_EMBED: &str = "togethercomputer/m2-bert-80M-8k-retrieval";
+}
+
+const TOGETHER_BASE_URL: &str = "https://api.together.xyz/v1";
+
+pub fn together_client() -> openai::Client {
+    // Uses TOGETHER_API_KEY env var
+    let api_key = std::env::var("TOGETHER_API_KEY").expect("TOGETHER_API_KEY not set");
+    openai::Client::from_url(&api_key, TOGETHER_BASE_URL)
+}
+
+// Perplexity Configuration (search-grounded completions)
+//
+// Answers cite sources as `[1]`, `[2]`, ... and the URLs arrive in a
+// top-level `citations` field the OpenAI client drops. `perplexity_client()`
+// is enough for plain answers; use `perplexity_search` to keep the sources.
+pub mod perplexity_models {
+    pub const SONAR: &str = "sonar";
+    pub const SONAR_PRO: &str = "sonar-pro";
+    pub const SONAR_REASONING: &str = "sonar-reasoning";
+}
+
+const PERPLEXITY_BASE_URL: &str = "https://api.perplexity.ai";
+
+pub fn perplexity_client() -> openai::Client {
+    // Uses PERPLEXITY_API_KEY env var
+    let api_key = std::env::var("PERPLEXITY_API_KEY").expect("PERPLEXITY_API_KEY not set");
+    openai::Client::from_url(&api_key, PERPLEXITY_BASE_URL)
+}
+
+#[derive(Debug, Clone)]
+pub struct GroundedAnswer {
+    pub answer: String,
+    /// Source URLs; `citations[0]` is `[1]` in the answer
+    pub citations: Vec<String>,
+}
+
+pub async fn perplexity_search(model: &str, preamble: &str, prompt: &str) -> anyhow::Result<GroundedAnswer> {
+    let api_key = std::env::var("PERPLEXITY_API_KEY")?;
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(format!("{PERPLEXITY_BASE_URL}/chat/completions"))
+        .bearer_auth(api_key)
+        .json(&serde_json::json!({
+            "model": model,
+            "messages": [
+                { "role": "system", "content": preamble },
+                { "role": "user", "content": prompt },
+            ],
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(GroundedAnswer {
+        answer: response["choices"][0]["message"]["content"].as_str().unwrap_or_default().to_string(),
+        citations: response["citations"].as_array()
+            .map(|urls| urls.iter().filter_map(|u| u.as_str().map(String::from)).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// Research query printed with its numbered sources
+pub async fn perplexity_research_agent() -> anyhow::Result<()> {
+    let result = perplexity_search(
+        perplexity_models::SONAR,
+        "Be precise and concise. Cite every factual claim.",
+        "What changed in the latest stable Rust release?",
+    )
+    .await?;
+
+    println!("{}\n", result.answer);
+    for (i, url) in result.citations.iter().enumerate() {
+        println!("[{}] {}", i + 1, url);
+    }
+
+    Ok(())
+}
+
+// Generic OpenAI-Compatible Endpoint
+//
+// vLLM (`http://localhost:8000/v1`), LM Studio (`http://localhost:1234/v1`),
+// LiteLLM proxies (`http://localhost:4000`), llama.cpp server, and anything
+// else speaking the OpenAI chat API:
+//     let local = compatible_client("http://localhost:8000/v1", None, "Qwen/Qwen2.5-7B-Instruct");
+//     let agent = local.agent().preamble("You are a helpful assistant.").build();
+pub struct CompatibleEndpoint {
+    pub client: openai::Client,
+    pub model: String,
+}
+
+impl CompatibleEndpoint {
+    pub fn agent(&self) -> AgentBuilder<openai::CompletionModel> {
+        self.client.agent(&self.model)
+    }
+
+    pub fn completion_model(&self) -> openai::CompletionModel {
+        self.client.completion_model(&self.model)
+    }
+}
+
+/// Servers without auth still expect a bearer token, so `None` sends a placeholder
+pub fn compatible_client(base_url: &str, api_key: Option<&str>, model: &str) -> CompatibleEndpoint {
+    CompatibleEndpoint {
+        client: openai::Client::from_url(api_key.unwrap_or("not-needed"), base_url.trim_end_matches('/')),
+        model: model.to_string(),
+    }
+}
+
 // Multi-provider setup
 pub struct Providers {
     pub openai: openai::Client,
+    #[cfg(feature = "anthropic")]
     pub anthropic: anthropic::Client,
+    #[cfg(feature = "bedrock")]
+    pub bedrock: Option<bedrock::Client>,
+    pub mistral: Option<mistral::Client>,
 }
 
 impl Providers {
     pub fn from_env() -> Self {
         Self {
             openai: openai::Client::from_env(),
+            #[cfg(feature = "anthropic")]
             anthropic: anthropic::Client::from_env(),
+            #[cfg(feature = "bedrock")]
+            bedrock: None,
+            mistral: std::env::var("MISTRAL_API_KEY").is_ok().then(mistral::Client::from_env),
+        }
+    }
+
+    /// Add Bedrock, which resolves AWS credentials asynchronously
+    #[cfg(feature = "bedrock")]
+    pub async fn with_bedrock(mut self) -> Self {
+        self.bedrock = Some(bedrock_client().await);
+        self
+    }
+}
+
+// File-based Configuration
+//
+// Keys never go in the file; each provider names the env var holding its key.
+//
+//     # rig.toml
+//     [providers.openai]
+//     [providers.ollama]
+//     base_url = "http://gpu-box:11434"
+//
+//     [models.default]
+//     provider = "openai"
+//     model = "gpt-4o"
+//     temperature = 0.2
+//
+//     [models.local]
+//     provider = "ollama"
+//     model = "llama3.2"
+//
+//     [tools]
+//     http_request = false
+//
+//     [budgets]
+//     daily_usd = 25.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    OpenAi,
+    Anthropic,
+    Gemini,
+    Cohere,
+    Ollama,
+    Azure,
+    Bedrock,
+    Groq,
+    Mistral,
+    DeepSeek,
+    OpenRouter,
+    Together,
+    Perplexity,
+    Compatible,
+}
+
+impl ProviderKind {
+    /// Env var read for the API key when the config doesn't name one
+    pub fn default_key_env(self) -> Option<&'static str> {
+        match self {
+            Self::OpenAi => Some("OPENAI_API_KEY"),
+            Self::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Self::Gemini => Some("GEMINI_API_KEY"),
+            Self::Cohere => Some("COHERE_API_KEY"),
+            Self::Azure => Some("AZURE_OPENAI_API_KEY"),
+            Self::Groq => Some("GROQ_API_KEY"),
+            Self::Mistral => Some("MISTRAL_API_KEY"),
+            Self::DeepSeek => Some("DEEPSEEK_API_KEY"),
+            Self::OpenRouter => Some("OPENROUTER_API_KEY"),
+            Self::Together => Some("TOGETHER_API_KEY"),
+            Self::Perplexity => Some("PERPLEXITY_API_KEY"),
+            // Local servers and AWS credential chains
+            Self::Ollama | Self::Bedrock | Self::Compatible => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub api_key_env: Option<String>,
+    /// Secret name in a `SecretSource`, used instead of `api_key_env` when set
+    pub api_key_secret: Option<String>,
+    pub base_url: Option<String>,
+    /// Overrides the top-level [proxy] for this provider
+    pub proxy: Option<ProxyConfig>,
+    /// Sent on every request, e.g. tracing ids or gateway routing headers
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// OpenAI organization and project ids (`OpenAI-Organization` / `OpenAI-Project`)
+    pub organization: Option<String>,
+    pub project: Option<String>,
+    #[serde(default)]
+    pub policy: ProviderPolicy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    pub provider: ProviderKind,
+    pub model: String,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    /// Overrides the preamble passed in code, so prompts can be tuned without a rebuild
+    pub preamble: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Budgets {
+    pub daily_usd: Option<f64>,
+    pub max_tokens_per_request: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub providers: HashMap<ProviderKind, ProviderConfig>,
+    /// Role name (`default`, `fast`, `local`, ...) -> model
+    pub models: HashMap<String, ModelConfig>,
+    /// Tool name -> enabled; unlisted tools are enabled
+    #[serde(default)]
+    pub tools: HashMap<String, bool>,
+    #[serde(default)]
+    pub budgets: Budgets,
+    /// Falls back to HTTPS_PROXY / ALL_PROXY / HTTP_PROXY / NO_PROXY when absent
+    pub proxy: Option<ProxyConfig>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Cannot read config {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+
+    #[error("Malformed config {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+
+    #[error("Invalid config {path}:\n  - {}", .issues.join("\n  - "))]
+    Invalid { path: PathBuf, issues: Vec<String> },
+}
+
+impl Config {
+    /// Load `.toml`, `.yaml`, or `.yml`, rejecting unknown fields and invalid values
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+        let parsed = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&raw).map_err(|e| e.to_string()),
+            _ => toml::from_str(&raw).map_err(|e| e.to_string()),
+        };
+        let config: Self = parsed
+            .map_err(|message| ConfigError::Parse { path: path.to_path_buf(), message })?;
+
+        let issues = config.issues();
+        if issues.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError::Invalid { path: path.to_path_buf(), issues })
+        }
+    }
+
+    fn issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if self.models.is_empty() {
+            issues.push("no models configured; add a [models.default] section".to_string());
+        }
+
+        let mut roles: Vec<_> = self.models.iter().collect();
+        roles.sort_by_key(|(role, _)| role.as_str());
+        for (role, model) in roles {
+            let provider = format!("{:?}", model.provider).to_lowercase();
+            if !self.providers.contains_key(&model.provider) {
+                issues.push(format!(
+                    "models.{role} uses provider `{provider}`, which has no [providers.{provider}] section"
+                ));
+            }
+            if model.model.trim().is_empty() {
+                issues.push(format!("models.{role}.model is empty"));
+            }
+            if let Some(t) = model.temperature.filter(|t| !(0.0..=2.0).contains(t)) {
+                issues.push(format!("models.{role}.temperature is {t}; expected 0.0 to 2.0"));
+            }
+            if model.max_tokens == Some(0) {
+                issues.push(format!("models.{role}.max_tokens must be greater than 0"));
+            }
+        }
+
+        for (kind, provider) in &self.providers {
+            let needs_url = matches!(kind, ProviderKind::Azure | ProviderKind::Compatible);
+            if needs_url && provider.base_url.is_none() {
+                let name = format!("{kind:?}").to_lowercase();
+                issues.push(format!("providers.{name}.base_url is required"));
+            }
+        }
+
+        if let Some(usd) = self.budgets.daily_usd.filter(|usd| *usd < 0.0) {
+            issues.push(format!("budgets.daily_usd is {usd}; expected a non-negative amount"));
+        }
+        issues
+    }
+
+    pub fn model(&self, role: &str) -> Option<&ModelConfig> {
+        self.models.get(role)
+    }
+
+    pub fn tool_enabled(&self, name: &str) -> bool {
+        self.tools.get(name).copied().unwrap_or(true)
+    }
+
+    /// Env var holding this provider's key, honoring `api_key_env` overrides
+    pub fn key_env(&self, kind: ProviderKind) -> Option<String> {
+        self.providers.get(&kind)
+            .and_then(|p| p.api_key_env.clone())
+            .or_else(|| kind.default_key_env().map(String::from))
+    }
+
+    /// The provider's env var, falling back to a key saved with `keyring_login`
+    pub fn api_key(&self, kind: ProviderKind) -> anyhow::Result<String> {
+        let var = self.key_env(kind).unwrap_or_default();
+        std::env::var(&var)
+            .ok()
+            .or_else(|| KeyringStore::default().get(kind).ok().flatten())
+            .ok_or_else(|| anyhow::anyhow!("{var} not set; run validate_env() for details"))
+    }
+}
+
+// Environment Validation
+//
+// `from_env()` panics on the first missing key with no hint; call this first
+// to report everything missing at once:
+//     if let Err(report) = validate_env(&[ProviderKind::OpenAi, ProviderKind::Anthropic]) {
+//         eprintln!("{report}");
+//         std::process::exit(1);
+//     }
+impl ProviderKind {
+    /// Where to get a key, shown next to each missing variable
+    fn key_hint(self) -> &'static str {
+        match self {
+            Self::OpenAi => "https://platform.openai.com/api-keys",
+            Self::Anthropic => "https://console.anthropic.com/settings/keys",
+            Self::Gemini => "https://aistudio.google.com/apikey",
+            Self::Cohere => "https://dashboard.cohere.com/api-keys",
+            Self::Azure => "Azure portal > your OpenAI resource > Keys and Endpoint",
+            Self::Groq => "https://console.groq.com/keys",
+            Self::Mistral => "https://console.mistral.ai/api-keys",
+            Self::DeepSeek => "https://platform.deepseek.com/api_keys",
+            Self::OpenRouter => "https://openrouter.ai/keys",
+            Self::Together => "https://api.together.ai/settings/api-keys",
+            Self::Perplexity => "https://www.perplexity.ai/settings/api",
+            Self::Ollama | Self::Bedrock | Self::Compatible => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MissingVar {
+    pub var: String,
+    pub provider: ProviderKind,
+    pub hint: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvReport {
+    /// The `.env` file that was loaded, if any
+    pub dotenv: Option<PathBuf>,
+    pub missing: Vec<MissingVar>,
+}
+
+impl std::fmt::Display for EnvReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Missing {} environment variable(s):", self.missing.len())?;
+        for missing in &self.missing {
+            write!(f, "  - {} ({:?})", missing.var, missing.provider)?;
+            if !missing.hint.is_empty() {
+                write!(f, ": get one at {}", missing.hint)?;
+            }
+            writeln!(f)?;
+        }
+        match &self.dotenv {
+            Some(path) => write!(f, "Add them to {} or export them in your shell.", path.display()),
+            None => write!(f, "Export them in your shell or add them to a .env file."),
+        }
+    }
+}
+
+impl std::error::Error for EnvReport {}
+
+/// Load `.env` (if present) and check the variables the given providers need
+pub fn validate_env(providers: &[ProviderKind]) -> Result<(), EnvReport> {
+    let required = providers.iter()
+        .flat_map(|&kind| required_vars(kind, kind.default_key_env().map(String::from), false))
+        .collect();
+    check_env(required)
+}
+
+impl Config {
+    /// Like `validate_env`, for the providers this config actually uses
+    pub fn validate_env(&self) -> Result<(), EnvReport> {
+        let mut kinds: Vec<_> = self.models.values().map(|m| m.provider).collect();
+        kinds.sort_by_key(|k| format!("{k:?}"));
+        kinds.dedup();
+
+        let required = kinds.into_iter()
+            .flat_map(|kind| {
+                let has_url = self.providers.get(&kind).is_some_and(|p| p.base_url.is_some());
+                required_vars(kind, self.key_env(kind), has_url)
+            })
+            .collect();
+        check_env(required)
+    }
+}
+
+fn required_vars(kind: ProviderKind, key_env: Option<String>, has_base_url: bool) -> Vec<(ProviderKind, String)> {
+    let mut vars: Vec<_> = key_env.into_iter().map(|var| (kind, var)).collect();
+    if kind == ProviderKind::Azure && !has_base_url {
+        vars.push((kind, "AZURE_OPENAI_ENDPOINT".to_string()));
+    }
+    vars
+}
+
+fn check_env(required: Vec<(ProviderKind, String)>) -> Result<(), EnvReport> {
+    let dotenv = dotenvy::dotenv().ok();
+    let missing: Vec<_> = required.into_iter()
+        .filter(|(_, var)| std::env::var(var).map_or(true, |v| v.trim().is_empty()))
+        .map(|(provider, var)| MissingVar { var, provider, hint: provider.key_hint() })
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(EnvReport { dotenv, missing })
+    }
+}
+
+// Provider-agnostic Agent Factory
+//
+// Rig agents are generic over their model, so application code that picks a
+// provider from config needs a boxed, object-safe agent:
+//     let agent = config.agent("default", "You are a helpful assistant.")?;
+//     let answer = agent.prompt("Hello").await?;
+pub trait DynAgent: Send + Sync {
+    fn prompt<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, Result<String, PromptError>>;
+}
+
+/// Boxable wrapper; a separate type so `Agent::prompt` stays unambiguous
+/// wherever both traits are in scope
+pub struct BoxedAgent<M: CompletionModel>(pub Agent<M>);
+
+impl<M: CompletionModel> DynAgent for BoxedAgent<M> {
+    fn prompt<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, Result<String, PromptError>> {
+        Box::pin(self.0.prompt(prompt))
+    }
+}
+
+pub trait AgentFactory: Send + Sync {
+    fn build_agent(&self, model: &ModelConfig, preamble: &str) -> Box<dyn DynAgent>;
+}
+
+fn configure<M: CompletionModel + 'static>(
+    builder: AgentBuilder<M>,
+    model: &ModelConfig,
+    preamble: &str,
+) -> Box<dyn DynAgent> {
+    let mut builder = builder.preamble(preamble);
+    if let Some(temperature) = model.temperature {
+        builder = builder.temperature(temperature);
+    }
+    if let Some(max_tokens) = model.max_tokens {
+        builder = builder.max_tokens(max_tokens);
+    }
+    Box::new(BoxedAgent(builder.build()))
+}
+
+impl AgentFactory for openai::Client {
+    fn build_agent(&self, model: &ModelConfig, preamble: &str) -> Box<dyn DynAgent> {
+        configure(self.agent(&model.model), model, preamble)
+    }
+}
+
+#[cfg(feature = "anthropic")]
+impl AgentFactory for anthropic::Client {
+    fn build_agent(&self, model: &ModelConfig, preamble: &str) -> Box<dyn DynAgent> {
+        // Anthropic rejects requests without max_tokens
+        let builder = self.agent(&model.model).max_tokens(model.max_tokens.unwrap_or(4096));
+        configure(builder, model, preamble)
+    }
+}
+
+#[cfg(feature = "gemini")]
+impl AgentFactory for gemini::Client {
+    fn build_agent(&self, model: &ModelConfig, preamble: &str) -> Box<dyn DynAgent> {
+        configure(self.agent(&model.model), model, preamble)
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl AgentFactory for ollama::Client {
+    fn build_agent(&self, model: &ModelConfig, preamble: &str) -> Box<dyn DynAgent> {
+        configure(self.agent(&model.model), model, preamble)
+    }
+}
+
+impl Config {
+    /// Client for a configured provider, with keys read from the environment
+    pub fn factory(&self, kind: ProviderKind) -> anyhow::Result<Box<dyn AgentFactory>> {
+        self.factory_with_key(kind, None)
+    }
+
+    /// Like `factory`, with an explicit key (e.g. a tenant's own) when given
+    pub fn factory_with_key(&self, kind: ProviderKind, api_key: Option<String>) -> anyhow::Result<Box<dyn AgentFactory>> {
+        let base_url = self.providers.get(&kind).and_then(|p| p.base_url.clone());
+        let http = self.http_client(kind)?;
+        let key = || api_key.clone().map_or_else(|| self.api_key(kind), Ok);
+        let compatible = |default_url: &str, key: String| -> anyhow::Result<Box<dyn AgentFactory>> {
+            let url = base_url.clone().unwrap_or_else(|| default_url.to_string());
+            Ok(Box::new(openai::Client::builder(&key).base_url(&url).custom_client(http.clone()).build()))
+        };
+
+        match kind {
+            ProviderKind::OpenAi => compatible("https://api.openai.com/v1", key()?),
+            #[cfg(feature = "anthropic")]
+            ProviderKind::Anthropic => {
+                Ok(Box::new(anthropic::Client::builder(&key()?).custom_client(http).build()))
+            }
+            #[cfg(feature = "gemini")]
+            ProviderKind::Gemini => Ok(Box::new(gemini::Client::builder(&key()?).custom_client(http).build())),
+            #[cfg(feature = "ollama")]
+            ProviderKind::Ollama => {
+                let url = base_url.clone().unwrap_or_else(|| OllamaConfig::from_env().base_url());
+                Ok(Box::new(ollama::Client::builder().base_url(&url).custom_client(http).build()))
+            }
+            ProviderKind::Groq => compatible(GROQ_BASE_URL, key()?),
+            ProviderKind::DeepSeek => compatible(DEEPSEEK_BASE_URL, key()?),
+            ProviderKind::OpenRouter => compatible(OPENROUTER_BASE_URL, key()?),
+            ProviderKind::Together => compatible(TOGETHER_BASE_URL, key()?),
+            ProviderKind::Perplexity => compatible(PERPLEXITY_BASE_URL, key()?),
+            ProviderKind::Compatible => {
+                anyhow::ensure!(base_url.is_some(), "providers.compatible.base_url is required");
+                compatible("", key().unwrap_or_else(|_| "not-needed".into()))
+            }
+            ProviderKind::Azure | ProviderKind::Mistral | ProviderKind::Cohere | ProviderKind::Bedrock => {
+                anyhow::bail!("{kind:?} has no AgentFactory; build its client directly")
+            }
+            // Anthropic, Gemini, and Ollama with their features off
+            #[allow(unreachable_patterns)]
+            other => anyhow::bail!("{other:?} support is not compiled in; enable its cargo feature"),
+        }
+    }
+
+    /// Agent for a model role, on whichever provider the config names
+    pub fn agent(&self, role: &str, preamble: &str) -> anyhow::Result<Box<dyn DynAgent>> {
+        let model = self.model(role)
+            .ok_or_else(|| anyhow::anyhow!("no [models.{role}] in config"))?;
+        let preamble = model.preamble.as_deref().unwrap_or(preamble);
+        Ok(self.factory(model.provider)?.build_agent(model, preamble))
+    }
+}
+
+// API Key Pool
+//
+// Spreads requests across several keys for one provider. Each key gets its
+// own client (so connection pools are reused); keys that hit a 429 cool off,
+// keys that get a 401 are dropped from rotation.
+//     let pool = KeyPool::from_env("OPENAI_API_KEYS", |key| openai::Client::new(key))?;
+//     let answer = pool.with_key(|client| async move {
+//         let agent = client.agent("gpt-4o").build();
+//         agent.prompt("Hello").await.map_err(anyhow::Error::from)
+//     }).await?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyOutcome {
+    Ok,
+    RateLimited,
+    Unauthorized,
+}
+
+impl KeyOutcome {
+    /// Classify a provider error by HTTP status or the provider's error body,
+    /// walking the source chain the way `ProviderPolicy::is_transient` does
+    pub fn classify(error: &(dyn std::error::Error + 'static)) -> Self {
+        let mut next = Some(error);
+        while let Some(error) = next {
+            if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+                return e.status().map_or(Self::Ok, Self::from_status);
+            }
+            if let Some(CompletionError::ProviderError(body)) = error.downcast_ref::<CompletionError>() {
+                return Self::from_body(body);
+            }
+            if let Some(EmbeddingError::ProviderError(body)) = error.downcast_ref::<EmbeddingError>() {
+                return Self::from_body(body);
+            }
+            next = error.source();
+        }
+        Self::Ok
+    }
+
+    pub fn from_status(status: reqwest::StatusCode) -> Self {
+        match status.as_u16() {
+            429 => Self::RateLimited,
+            401 | 403 => Self::Unauthorized,
+            _ => Self::Ok,
+        }
+    }
+
+    /// The error `type` (Anthropic) or `type`/`code` (OpenAI-compatible)
+    fn from_body(body: &str) -> Self {
+        let Ok(body) = serde_json::from_str::<serde_json::Value>(body) else {
+            return Self::Ok;
+        };
+        let error = &body["error"];
+        for kind in [&error["type"], &error["code"]].into_iter().filter_map(|v| v.as_str()) {
+            match kind {
+                "rate_limit_error" | "rate_limit_exceeded" | "insufficient_quota" => return Self::RateLimited,
+                "authentication_error" | "permission_error" | "invalid_api_key" => return Self::Unauthorized,
+                _ => {}
+            }
+        }
+        Self::Ok
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyUsage {
+    /// Last four characters, for logs
+    pub key_suffix: String,
+    pub requests: u64,
+    pub rate_limited: u64,
+    pub disabled: bool,
+}
+
+struct PooledKey<C> {
+    key: String,
+    client: C,
+    requests: AtomicU64,
+    rate_limited: AtomicU64,
+    disabled: AtomicBool,
+    cooling_until: Mutex<Option<Instant>>,
+}
+
+pub struct KeyPool<C> {
+    keys: Vec<PooledKey<C>>,
+    next: AtomicUsize,
+    cooldown: Duration,
+}
+
+pub struct KeyLease<'a, C> {
+    index: usize,
+    pub client: &'a C,
+}
+
+impl<C> KeyPool<C> {
+    pub fn new(keys: impl IntoIterator<Item = String>, client: impl Fn(&str) -> C) -> Self {
+        let keys = keys.into_iter()
+            .map(|key| PooledKey {
+                client: client(&key),
+                key,
+                requests: AtomicU64::new(0),
+                rate_limited: AtomicU64::new(0),
+                disabled: AtomicBool::new(false),
+                cooling_until: Mutex::new(None),
+            })
+            .collect();
+        Self { keys, next: AtomicUsize::new(0), cooldown: Duration::from_secs(60) }
+    }
+
+    /// Comma-separated keys, e.g. OPENAI_API_KEYS=sk-a,sk-b,sk-c
+    pub fn from_env(var: &str, client: impl Fn(&str) -> C) -> anyhow::Result<Self> {
+        let raw = std::env::var(var).map_err(|_| anyhow::anyhow!("{var} not set"))?;
+        let keys: Vec<_> = raw.split(',').map(str::trim).filter(|k| !k.is_empty()).map(String::from).collect();
+        anyhow::ensure!(!keys.is_empty(), "{var} contains no keys");
+        Ok(Self::new(keys, client))
+    }
+
+    /// How long a rate-limited key sits out (default 60s)
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Next usable key in round-robin order, or `None` if all are cooling or disabled
+    pub fn next(&self) -> Option<KeyLease<'_, C>> {
+        let now = Instant::now();
+        for _ in 0..self.keys.len() {
+            let index = self.next.fetch_add(1, Ordering::Relaxed) % self.keys.len();
+            let entry = &self.keys[index];
+            if entry.disabled.load(Ordering::Relaxed) {
+                continue;
+            }
+            if entry.cooling_until.lock().unwrap().is_some_and(|until| until > now) {
+                continue;
+            }
+            entry.requests.fetch_add(1, Ordering::Relaxed);
+            return Some(KeyLease { index, client: &entry.client });
+        }
+        None
+    }
+
+    pub fn report(&self, lease: &KeyLease<'_, C>, outcome: KeyOutcome) {
+        let entry = &self.keys[lease.index];
+        match outcome {
+            KeyOutcome::Ok => {}
+            KeyOutcome::RateLimited => {
+                entry.rate_limited.fetch_add(1, Ordering::Relaxed);
+                *entry.cooling_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+            }
+            KeyOutcome::Unauthorized => {
+                tracing::warn!(key_suffix = %suffix(&entry.key), "API key rejected; removing from rotation");
+                entry.disabled.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Run a request, moving on to the next key after a 429 or 401
+    pub async fn with_key<'a, F, Fut, T, E>(&'a self, mut request: F) -> Result<T, E>
+    where
+        F: FnMut(&'a C) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: AsRef<dyn std::error::Error + Send + Sync + 'static> + From<anyhow::Error>,
+    {
+        let mut last_error = None;
+        for _ in 0..self.keys.len() {
+            let Some(lease) = self.next() else { break };
+            match request(lease.client).await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    let outcome = KeyOutcome::classify(e.as_ref());
+                    self.report(&lease, outcome);
+                    if outcome == KeyOutcome::Ok {
+                        return Err(e);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("every key is rate limited or disabled").into()))
+    }
+
+    pub fn usage(&self) -> Vec<KeyUsage> {
+        self.keys.iter()
+            .map(|entry| KeyUsage {
+                key_suffix: suffix(&entry.key),
+                requests: entry.requests.load(Ordering::Relaxed),
+                rate_limited: entry.rate_limited.load(Ordering::Relaxed),
+                disabled: entry.disabled.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+fn suffix(key: &str) -> String {
+    let start = key.len().saturating_sub(4);
+    format!("...{}", key.get(start..).unwrap_or_default())
+}
+
+// Proxy Configuration
+//
+// HTTP(S) and SOCKS proxies (`socks5h://` needs reqwest's `socks` feature):
+//     [proxy]
+//     url = "http://proxy.corp.example:3128"
+//     no_proxy = ["localhost", "127.0.0.1", ".internal.example"]
+//
+// Any Rig client accepts the resulting HTTP client:
+//     let http = ProxyConfig::from_env().http_client()?;
+//     let client = openai::Client::builder(&api_key).custom_client(http).build();
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    pub url: Option<String>,
+    /// Hosts, domains (`.example.com`), or CIDRs that bypass the proxy
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Reads HTTPS_PROXY, then ALL_PROXY, then HTTP_PROXY (either case), and NO_PROXY
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            std::env::var(name)
+                .or_else(|_| std::env::var(name.to_lowercase()))
+                .ok()
+                .filter(|v| !v.is_empty())
+        };
+        Self {
+            url: var("HTTPS_PROXY").or_else(|| var("ALL_PROXY")).or_else(|| var("HTTP_PROXY")),
+            no_proxy: var("NO_PROXY")
+                .map(|list| list.split(',').map(|h| h.trim().to_string()).filter(|h| !h.is_empty()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn apply(&self, builder: reqwest::ClientBuilder) -> reqwest::Result<reqwest::ClientBuilder> {
+        let Some(url) = &self.url else {
+            // Nothing configured: leave reqwest's own environment lookup in place
+            return Ok(builder);
+        };
+        let proxy = reqwest::Proxy::all(url)?
+            .no_proxy(reqwest::NoProxy::from_string(&self.no_proxy.join(",")));
+        Ok(builder.proxy(proxy))
+    }
+
+    pub fn http_client(&self) -> reqwest::Result<reqwest::Client> {
+        self.apply(reqwest::Client::builder())?.build()
+    }
+}
+
+impl Config {
+    /// HTTP client for a provider's requests: its own proxy, else [proxy], else
+    /// the environment; plus the provider's custom headers
+    pub fn http_client(&self, kind: ProviderKind) -> anyhow::Result<reqwest::Client> {
+        let provider = self.providers.get(&kind);
+        let proxy = provider
+            .and_then(|p| p.proxy.clone())
+            .or_else(|| self.proxy.clone())
+            .unwrap_or_else(ProxyConfig::from_env);
+
+        let mut builder = proxy.apply(reqwest::Client::builder())?;
+        builder = self.policy(kind).apply(builder);
+        if let Some(provider) = provider {
+            builder = builder.default_headers(provider.header_map()?);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+// Custom Headers and Organization IDs
+//
+//     [providers.openai]
+//     organization = "org-..."
+//     project = "proj_..."
+//     headers = { "X-Org" = "platform", "X-Request-Source" = "batch-jobs" }
+impl ProviderConfig {
+    pub fn header_map(&self) -> anyhow::Result<reqwest::header::HeaderMap> {
+        use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+        let mut map = HeaderMap::new();
+        let named = [("OpenAI-Organization", &self.organization), ("OpenAI-Project", &self.project)];
+        let named = named.into_iter().filter_map(|(name, value)| Some((name, value.as_deref()?)));
+        for (name, value) in self.headers.iter().map(|(n, v)| (n.as_str(), v.as_str())).chain(named) {
+            let header = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| anyhow::anyhow!("invalid header name `{name}`"))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| anyhow::anyhow!("invalid value for header `{name}`"))?;
+            map.insert(header, value);
+        }
+        Ok(map)
+    }
+}
+
+impl Providers {
+    /// Build each client with its configured key, proxy, and headers
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let key = |kind| config.api_key(kind);
+
+        let mistral = match config.providers.contains_key(&ProviderKind::Mistral) {
+            true => Some(
+                mistral::Client::builder(&key(ProviderKind::Mistral)?)
+                    .custom_client(config.http_client(ProviderKind::Mistral)?)
+                    .build(),
+            ),
+            false => None,
+        };
+
+        Ok(Self {
+            openai: openai::Client::builder(&key(ProviderKind::OpenAi)?)
+                .custom_client(config.http_client(ProviderKind::OpenAi)?)
+                .build(),
+            #[cfg(feature = "anthropic")]
+            anthropic: anthropic::Client::builder(&key(ProviderKind::Anthropic)?)
+                .custom_client(config.http_client(ProviderKind::Anthropic)?)
+                .build(),
+            #[cfg(feature = "bedrock")]
+            bedrock: None,
+            mistral,
+        })
+    }
+}
+
+// Timeout and Retry Policy
+//
+//     [providers.anthropic.policy]
+//     connect_timeout = "5s"
+//     request_timeout = "2m"
+//     max_retries = 5
+//     backoff = { exponential = { base = "1s", max = "30s" } }
+//     failure_threshold = 5
+//     cooldown = "30s"
+//
+// Timeouts are applied to the provider's HTTP client; retries wrap a whole
+// request, and `retry_tool` gives a tool's `RetryTool` the same budget:
+//     let policy = config.policy(ProviderKind::Anthropic);
+//     let answer = policy.retry(|| agent.prompt("Hello")).await?;
+// `failure_threshold` and `cooldown` drive the `CircuitBreaker` below.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    Fixed(#[serde(with = "humantime_serde")] Duration),
+    Exponential {
+        #[serde(with = "humantime_serde")]
+        base: Duration,
+        #[serde(with = "humantime_serde")]
+        max: Duration,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderPolicy {
+    #[serde(with = "humantime_serde")]
+    pub connect_timeout: Duration,
+    #[serde(with = "humantime_serde")]
+    pub request_timeout: Duration,
+    pub max_retries: u32,
+    pub backoff: Backoff,
+    /// Consecutive failed requests that open the circuit; 0 disables it
+    pub failure_threshold: u32,
+    /// How long an open circuit fails fast before letting a trial request through
+    #[serde(with = "humantime_serde")]
+    pub cooldown: Duration,
+}
+
+impl Default for ProviderPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            // Long completions legitimately take a minute or more
+            request_timeout: Duration::from_secs(120),
+            max_retries: 3,
+            backoff: Backoff::Exponential { base: Duration::from_millis(500), max: Duration::from_secs(30) },
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ProviderPolicy {
+    pub fn apply(&self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder.connect_timeout(self.connect_timeout).timeout(self.request_timeout)
+    }
+
+    /// Delay before retry number `attempt` (1-based)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { base, max } => base.saturating_mul(2u32.saturating_pow(attempt - 1)).min(max),
+        }
+    }
+
+    /// Rate limits, server errors, timeouts, and dropped connections are worth
+    /// retrying; bad requests and auth failures are not. Walks the source
+    /// chain, so errors wrapped in `PromptError` or `anyhow` context still match
+    pub fn is_transient(error: &(dyn std::error::Error + 'static)) -> bool {
+        let mut next = Some(error);
+        while let Some(error) = next {
+            if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+                return e.is_timeout() || e.is_connect() || e.status().is_some_and(Self::is_transient_status);
+            }
+            // Rig reports non-2xx responses as the provider's error body
+            if let Some(CompletionError::ProviderError(body)) = error.downcast_ref::<CompletionError>() {
+                return is_transient_body(body);
+            }
+            if let Some(EmbeddingError::ProviderError(body)) = error.downcast_ref::<EmbeddingError>() {
+                return is_transient_body(body);
+            }
+            if let Some(e) = error.downcast_ref::<std::io::Error>() {
+                use std::io::ErrorKind::*;
+                return matches!(e.kind(), TimedOut | ConnectionReset | ConnectionAborted | ConnectionRefused);
+            }
+            if error.is::<tokio::time::error::Elapsed>() || error.is::<ToolTimeout>() {
+                return true;
+            }
+            next = error.source();
+        }
+        false
+    }
+
+    /// 429, 529 (Anthropic's "overloaded"), and the 5xx codes a retry can fix
+    pub fn is_transient_status(status: reqwest::StatusCode) -> bool {
+        matches!(status.as_u16(), 429 | 500 | 502 | 503 | 504 | 529)
+    }
+
+    pub async fn retry<F, Fut, T, E>(&self, mut request: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::error::Error + 'static,
+    {
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Err(e) if attempt < self.max_retries && Self::is_transient(&e) => {
+                    attempt += 1;
+                    tracing::warn!(attempt, error = %e, "retrying provider request");
+                    tokio::time::sleep(self.delay(attempt)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// The same retry budget and backoff for a tool that calls this provider's API
+    pub fn retry_tool<T: Idempotency>(&self, tool: T) -> RetryTool<T> {
+        let (base, max) = match self.backoff {
+            Backoff::Fixed(delay) => (delay, delay),
+            Backoff::Exponential { base, max } => (base, max),
+        };
+        RetryTool::new(tool).max_retries(self.max_retries).base_delay(base).max_delay(max)
+    }
+}
+
+/// The error `type` (Anthropic) or `type`/`code` (OpenAI-compatible) in a
+/// provider's JSON error body
+fn is_transient_body(body: &str) -> bool {
+    let Ok(body) = serde_json::from_str::<serde_json::Value>(body) else {
+        return false;
+    };
+    let error = &body["error"];
+    [&error["type"], &error["code"]].into_iter().filter_map(|v| v.as_str()).any(|kind| {
+        matches!(kind, "rate_limit_error" | "rate_limit_exceeded" | "overloaded_error" | "api_error" | "server_error")
+    })
+}
+
+impl Config {
+    pub fn policy(&self, kind: ProviderKind) -> ProviderPolicy {
+        self.providers.get(&kind).map(|p| p.policy.clone()).unwrap_or_default()
+    }
+}
+
+// Circuit Breaker
+//
+// After `failure_threshold` consecutive requests fail transiently (each
+// already retried under the policy), the provider is presumed down and calls
+// fail fast with `CircuitError::Open` for `cooldown`. Then one trial request
+// goes through: success closes the circuit, failure reopens it. Share one
+// breaker per provider across requests:
+//     let breaker = Arc::new(CircuitBreaker::new(config.policy(ProviderKind::Anthropic)));
+//     let answer = breaker.call(|| agent.prompt("Hello")).await?;
+#[derive(Debug, thiserror::Error)]
+pub enum CircuitError<E> {
+    #[error("Circuit open; provider skipped for another {0:?}")]
+    Open(Duration),
+
+    #[error(transparent)]
+    Request(E),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CircuitState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// A trial request is in flight; if it never reports back (its future was
+    /// dropped), another is let through at `until`
+    HalfOpen { until: Instant },
+}
+
+pub struct CircuitBreaker {
+    policy: ProviderPolicy,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(policy: ProviderPolicy) -> Self {
+        Self { policy, state: Mutex::new(CircuitState::Closed { failures: 0 }) }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), CircuitState::Closed { .. })
+    }
+
+    /// Run `request` under the policy's retries, unless the circuit is open
+    pub async fn call<F, Fut, T, E>(&self, request: F) -> Result<T, CircuitError<E>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::error::Error + 'static,
+    {
+        if self.policy.failure_threshold == 0 {
+            return self.policy.retry(request).await.map_err(CircuitError::Request);
+        }
+        self.admit().map_err(CircuitError::Open)?;
+        let result = self.policy.retry(request).await;
+        // A bad request still shows the provider is up
+        let failed = result.as_ref().is_err_and(|e| ProviderPolicy::is_transient(e));
+        self.report(failed);
+        result.map_err(CircuitError::Request)
+    }
+
+    /// `Err` with the time left when the request should fail fast
+    fn admit(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if now < until => Err(until - now),
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => {
+                *state = CircuitState::HalfOpen { until: now + self.policy.cooldown };
+                Ok(())
+            }
+        }
+    }
+
+    fn report(&self, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        *state = match (*state, failed) {
+            (_, false) => CircuitState::Closed { failures: 0 },
+            (CircuitState::Closed { failures }, true) if failures + 1 < self.policy.failure_threshold => {
+                CircuitState::Closed { failures: failures + 1 }
+            }
+            (_, true) => {
+                tracing::warn!(cooldown = ?self.policy.cooldown, "provider failing; opening circuit");
+                CircuitState::Open { until: Instant::now() + self.policy.cooldown }
+            }
+        };
+    }
+}
+
+// Model Catalog
+//
+// Capabilities and list prices (USD per million tokens) for the models the
+// templates use. Check before wiring tools into an agent:
+//     let catalog = ModelCatalog::default();
+//     catalog.ensure_tools("deepseek-reasoner")?; // error: no tool calling
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub provider: ProviderKind,
+    pub id: &'static str,
+    pub context_window: u32,
+    pub vision: bool,
+    pub tools: bool,
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl ModelInfo {
+    pub fn cost_usd(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_mtok + output_tokens as f64 * self.output_per_mtok) / 1_000_000.0
+    }
+}
+
+pub struct ModelCatalog {
+    models: Vec<ModelInfo>,
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        use ProviderKind::*;
+        let model = |provider, id, context_window, vision, tools, input_per_mtok, output_per_mtok| ModelInfo {
+            provider, id, context_window, vision, tools, input_per_mtok, output_per_mtok,
+        };
+        Self {
+            models: vec![
+                model(OpenAi, "gpt-4o", 128_000, true, true, 2.50, 10.00),
+                model(OpenAi, "gpt-4o-mini", 128_000, true, true, 0.15, 0.60),
+                model(Anthropic, "claude-3-5-sonnet-20241022", 200_000, true, true, 3.00, 15.00),
+                model(Anthropic, "claude-3-5-haiku-20241022", 200_000, false, true, 0.80, 4.00),
+                model(Anthropic, "claude-3-opus-20240229", 200_000, true, true, 15.00, 75.00),
+                model(Gemini, "gemini-1.5-pro", 2_000_000, true, true, 1.25, 5.00),
+                model(Gemini, "gemini-1.5-flash", 1_000_000, true, true, 0.075, 0.30),
+                model(Cohere, "command-r-plus", 128_000, false, true, 2.50, 10.00),
+                model(Mistral, mistral_models::LARGE, 128_000, false, true, 2.00, 6.00),
+                model(Mistral, mistral_models::SMALL, 32_000, false, true, 0.20, 0.60),
+                model(Groq, groq_models::LLAMA_3_3_70B, 128_000, false, true, 0.59, 0.79),
+                model(Groq, groq_models::LLAMA_3_1_8B, 128_000, false, true, 0.05, 0.08),
+                model(DeepSeek, deepseek_models::CHAT, 64_000, false, true, 0.27, 1.10),
+                model(DeepSeek, deepseek_models::REASONER, 64_000, false, false, 0.55, 2.19),
+                model(Together, together_models::LLAMA_3_3_70B, 128_000, false, true, 0.88, 0.88),
+                model(Together, together_models::QWEN_2_5_72B, 32_000, false, true, 1.20, 1.20),
+                model(Perplexity, perplexity_models::SONAR, 127_000, false, false, 1.00, 1.00),
+                model(Ollama, "llama3.2", 128_000, false, true, 0.0, 0.0),
+            ],
+        }
+    }
+}
+
+impl ModelCatalog {
+    /// Add a model, replacing any entry with the same provider and id
+    pub fn with(mut self, info: ModelInfo) -> Self {
+        self.models.retain(|m| (m.provider, m.id) != (info.provider, info.id));
+        self.models.push(info);
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|m| m.id == id)
+    }
+
+    pub fn for_provider(&self, provider: ProviderKind) -> impl Iterator<Item = &ModelInfo> {
+        self.models.iter().filter(move |m| m.provider == provider)
+    }
+
+    pub fn all(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    pub fn supports_tools(&self, id: &str) -> bool {
+        self.get(id).is_some_and(|m| m.tools)
+    }
+
+    pub fn supports_vision(&self, id: &str) -> bool {
+        self.get(id).is_some_and(|m| m.vision)
+    }
+
+    /// Fails for unknown models too, so typos surface before the first request
+    pub fn ensure_tools(&self, id: &str) -> anyhow::Result<&ModelInfo> {
+        let info = self.get(id).ok_or_else(|| anyhow::anyhow!("{id} is not in the model catalog"))?;
+        anyhow::ensure!(info.tools, "{id} does not support tool calling");
+        Ok(info)
+    }
+}
+
+// Model Selection
+//
+// Cheapest catalog model that meets the task's needs:
+//     let model = select_model(TaskKind::ToolUse, &Constraints::default().min_context(128_000))?;
+//     let agent = config.factory(model.provider)?.build_agent(&model.into(), preamble);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskKind {
+    Chat,
+    /// Tool calling, including Rig extractors (which use a tool under the hood)
+    ToolUse,
+    Vision,
+    LongDocument,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Constraints {
+    pub min_context: Option<u32>,
+    pub needs_tools: bool,
+    pub needs_vision: bool,
+    /// Empty means any hosted provider; local models are only chosen when listed
+    pub providers: Vec<ProviderKind>,
+    pub max_output_per_mtok: Option<f64>,
+}
+
+impl Constraints {
+    pub fn min_context(mut self, tokens: u32) -> Self {
+        self.min_context = Some(tokens);
+        self
+    }
+
+    pub fn tools(mut self) -> Self {
+        self.needs_tools = true;
+        self
+    }
+
+    pub fn vision(mut self) -> Self {
+        self.needs_vision = true;
+        self
+    }
+
+    pub fn provider(mut self, provider: ProviderKind) -> Self {
+        self.providers.push(provider);
+        self
+    }
+
+    pub fn max_output_price(mut self, usd_per_mtok: f64) -> Self {
+        self.max_output_per_mtok = Some(usd_per_mtok);
+        self
+    }
+
+    fn allows(&self, task: TaskKind, model: &ModelInfo) -> bool {
+        let min_context = match task {
+            TaskKind::LongDocument => self.min_context.unwrap_or(0).max(200_000),
+            _ => self.min_context.unwrap_or(0),
+        };
+        let provider_ok = match self.providers.is_empty() {
+            true => !matches!(model.provider, ProviderKind::Ollama | ProviderKind::Compatible),
+            false => self.providers.contains(&model.provider),
+        };
+
+        provider_ok
+            && model.context_window >= min_context
+            && (model.tools || !(self.needs_tools || task == TaskKind::ToolUse))
+            && (model.vision || !(self.needs_vision || task == TaskKind::Vision))
+            && self.max_output_per_mtok.map_or(true, |max| model.output_per_mtok <= max)
+    }
+}
+
+impl ModelCatalog {
+    pub fn select(&self, task: TaskKind, constraints: &Constraints) -> Option<&ModelInfo> {
+        self.models.iter()
+            .filter(|m| constraints.allows(task, m))
+            .min_by(|a, b| {
+                let price = |m: &ModelInfo| m.input_per_mtok + m.output_per_mtok;
+                price(a).total_cmp(&price(b)).then(b.context_window.cmp(&a.context_window))
+            })
+    }
+}
+
+/// `ModelCatalog::select` on the built-in catalog
+pub fn select_model(task: TaskKind, constraints: &Constraints) -> anyhow::Result<ModelInfo> {
+    ModelCatalog::default()
+        .select(task, constraints)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("no catalog model satisfies {task:?} with {constraints:?}"))
+}
+
+impl From<ModelInfo> for ModelConfig {
+    fn from(info: ModelInfo) -> Self {
+        Self { provider: info.provider, model: info.id.to_string(), temperature: None, max_tokens: None, preamble: None }
+    }
+}
+
+// Failover Chain
+//
+// Ordered links across providers, each with an optional health check, for
+// `FallbackChain` in agent-template.rs:
+//     let links = FailoverBuilder::new(&config, "You are a helpful assistant.")
+//         .link(ProviderKind::OpenAi, "gpt-4o")
+//         .link(ProviderKind::Anthropic, "claude-3-5-sonnet-20241022")
+//         .link(ProviderKind::Ollama, "llama3.2")
+//         .build()?;
+pub type HealthCheck = Box<dyn Fn() -> BoxFuture<'static, bool> + Send + Sync>;
+
+pub struct FailoverLink {
+    pub label: String,
+    pub agent: Box<dyn DynAgent>,
+    health: Option<HealthCheck>,
+}
+
+impl FailoverLink {
+    pub fn new(label: impl Into<String>, agent: Box<dyn DynAgent>) -> Self {
+        Self { label: label.into(), agent, health: None }
+    }
+
+    pub fn health_check(mut self, check: HealthCheck) -> Self {
+        self.health = Some(check);
+        self
+    }
+
+    /// Links without a check are assumed healthy
+    pub async fn healthy(&self) -> bool {
+        match &self.health {
+            Some(check) => check().await,
+            None => true,
+        }
+    }
+}
+
+/// Health check that expects a 2xx from `url` within two seconds
+pub fn http_health_check(url: String) -> HealthCheck {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()
+        .expect("failed to build HTTP client");
+    Box::new(move || {
+        let request = client.get(&url).send();
+        Box::pin(async move { request.await.is_ok_and(|r| r.status().is_success()) })
+    })
+}
+
+pub struct FailoverBuilder<'a> {
+    config: &'a Config,
+    preamble: String,
+    links: Vec<FailoverLink>,
+}
+
+impl<'a> FailoverBuilder<'a> {
+    pub fn new(config: &'a Config, preamble: impl Into<String>) -> Self {
+        Self { config, preamble: preamble.into(), links: Vec::new() }
+    }
+
+    /// Append a link; providers that can't be built (e.g. missing key) are
+    /// skipped with a warning so one bad entry doesn't sink the chain.
+    /// Ollama links get a `/api/tags` health check.
+    pub fn link(mut self, provider: ProviderKind, model: &str) -> Self {
+        let label = format!("{provider:?}/{model}");
+        let factory = match self.config.factory(provider) {
+            Ok(factory) => factory,
+            Err(e) => {
+                tracing::warn!(link = %label, error = %e, "skipping failover link");
+                return self;
+            }
+        };
+
+        let model_config = self.config.models.values()
+            .find(|m| m.provider == provider && m.model == model)
+            .cloned()
+            .unwrap_or_else(|| ModelConfig {
+                provider,
+                model: model.to_string(),
+                temperature: None,
+                max_tokens: None,
+                preamble: None,
+            });
+        let link = FailoverLink::new(label, factory.build_agent(&model_config, &self.preamble));
+
+        #[cfg(feature = "ollama")]
+        let link = match provider {
+            ProviderKind::Ollama => {
+                let base_url = self.config.providers.get(&provider)
+                    .and_then(|p| p.base_url.clone())
+                    .unwrap_or_else(|| OllamaConfig::from_env().base_url());
+                link.health_check(http_health_check(format!("{base_url}/api/tags")))
+            }
+            _ => link,
+        };
+        self.links.push(link);
+        self
+    }
+
+    /// Append a prebuilt link, e.g. one with a custom health check
+    pub fn push(mut self, link: FailoverLink) -> Self {
+        self.links.push(link);
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<Vec<FailoverLink>> {
+        anyhow::ensure!(!self.links.is_empty(), "failover chain has no usable links");
+        Ok(self.links)
+    }
+}
+
+// Secrets Manager Integration
+//
+// For environments that forbid plaintext secrets in env vars:
+//     [providers.openai]
+//     api_key_secret = "prod/llm#openai"   # secret id, then JSON field
+//
+//     let secrets = CachedSecrets::new(AwsSecretsManager::from_env().await, Duration::from_secs(300));
+//     let key = config.resolve_key(ProviderKind::OpenAi, &secrets).await?;
+//
+// After a 401, call `secrets.refresh(name)` to pick up a rotated key.
+pub trait SecretSource: Send + Sync {
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, anyhow::Result<String>>;
+}
+
+/// `name#field` reads one field of a JSON secret; plain `name` returns the whole value
+fn split_field(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('#') {
+        Some((id, field)) => (id, Some(field)),
+        None => (name, None),
+    }
+}
+
+fn select_field(raw: String, field: Option<&str>, name: &str) -> anyhow::Result<String> {
+    let Some(field) = field else { return Ok(raw) };
+    let json: serde_json::Value = serde_json::from_str(&raw)?;
+    json[field].as_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow::anyhow!("secret {name} has no string field `{field}`"))
+}
+
+pub struct AwsSecretsManager {
+    client: aws_sdk_secretsmanager::Client,
+}
+
+impl AwsSecretsManager {
+    /// Region and credentials from the standard AWS chain
+    pub async fn from_env() -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self { client: aws_sdk_secretsmanager::Client::new(&config) }
+    }
+}
+
+impl SecretSource for AwsSecretsManager {
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let (id, field) = split_field(name);
+            let output = self.client.get_secret_value().secret_id(id).send().await?;
+            let raw = output.secret_string()
+                .ok_or_else(|| anyhow::anyhow!("secret {id} is binary, expected a string"))?;
+            select_field(raw.to_string(), field, name)
+        })
+    }
+}
+
+/// HashiCorp Vault KV v2. Names are `path#field` under the mount,
+/// e.g. `llm/openai#api_key` reads `secret/data/llm/openai`
+pub struct VaultSource {
+    addr: String,
+    token: String,
+    mount: String,
+    client: reqwest::Client,
+}
+
+impl VaultSource {
+    /// Uses VAULT_ADDR and VAULT_TOKEN, the same variables as the Vault CLI
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            addr: std::env::var("VAULT_ADDR")?.trim_end_matches('/').to_string(),
+            token: std::env::var("VAULT_TOKEN")?,
+            mount: "secret".to_string(),
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub fn mount(mut self, mount: impl Into<String>) -> Self {
+        self.mount = mount.into();
+        self
+    }
+}
+
+impl SecretSource for VaultSource {
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let (path, field) = split_field(name);
+            let field = field.unwrap_or("value");
+            let response: serde_json::Value = self.client
+                .get(format!("{}/v1/{}/data/{}", self.addr, self.mount, path))
+                .header("X-Vault-Token", &self.token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            // KV v2 nests the secret under data.data
+            response["data"]["data"][field].as_str()
+                .map(String::from)
+                .ok_or_else(|| anyhow::anyhow!("vault secret {path} has no string field `{field}`"))
+        })
+    }
+}
+
+/// Caches fetched secrets for `ttl` so every request doesn't hit the manager;
+/// expiry also picks up scheduled rotations
+pub struct CachedSecrets<S> {
+    source: S,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl<S: SecretSource> CachedSecrets<S> {
+    pub fn new(source: S, ttl: Duration) -> Self {
+        Self { source, ttl, cache: Mutex::new(HashMap::new()) }
+    }
+
+    pub async fn get(&self, name: &str) -> anyhow::Result<String> {
+        if let Some((fetched, value)) = self.cache.lock().unwrap().get(name) {
+            if fetched.elapsed() < self.ttl {
+                return Ok(value.clone());
+            }
+        }
+        // Fetched without the lock, so a slow secrets manager doesn't block
+        // other names; concurrent misses on one name may each fetch it
+        let value = self.source.fetch(name).await?;
+        self.cache.lock().unwrap().insert(name.to_string(), (Instant::now(), value.clone()));
+        Ok(value)
+    }
+
+    /// Drop the cached value and fetch again, e.g. after the provider returns 401
+    pub async fn refresh(&self, name: &str) -> anyhow::Result<String> {
+        self.cache.lock().unwrap().remove(name);
+        self.get(name).await
+    }
+}
+
+impl Config {
+    /// The provider's `api_key_secret` if set, else its env var
+    pub async fn resolve_key<S: SecretSource>(
+        &self,
+        kind: ProviderKind,
+        secrets: &CachedSecrets<S>,
+    ) -> anyhow::Result<String> {
+        match self.providers.get(&kind).and_then(|p| p.api_key_secret.as_deref()) {
+            Some(name) => secrets.get(name).await,
+            None => self.api_key(kind),
+        }
+    }
+}
+
+// OS Keyring Storage
+//
+// For command-line use: `login` once and the key lives in the macOS Keychain,
+// Windows Credential Manager, or Secret Service instead of a shell profile.
+// `Config::api_key` checks the keyring after the env var.
+//     keyring_login(ProviderKind::OpenAi)?;   // prompts without echoing
+pub struct KeyringStore {
+    service: String,
+}
+
+impl Default for KeyringStore {
+    fn default() -> Self {
+        Self::new("rig")
+    }
+}
+
+impl KeyringStore {
+    /// Entries are stored as (service, provider name)
+    pub fn new(service: impl Into<String>) -> Self {
+        Self { service: service.into() }
+    }
+
+    fn entry(&self, kind: ProviderKind) -> keyring::Result<keyring::Entry> {
+        keyring::Entry::new(&self.service, &format!("{kind:?}").to_lowercase())
+    }
+
+    pub fn set(&self, kind: ProviderKind, api_key: &str) -> keyring::Result<()> {
+        self.entry(kind)?.set_password(api_key)
+    }
+
+    /// `Ok(None)` when nothing is stored for the provider
+    pub fn get(&self, kind: ProviderKind) -> keyring::Result<Option<String>> {
+        match self.entry(kind)?.get_password() {
+            Ok(key) => Ok(Some(key)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn delete(&self, kind: ProviderKind) -> keyring::Result<()> {
+        match self.entry(kind)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Prompt for a key on the terminal and store it in the OS keyring
+pub fn keyring_login(kind: ProviderKind) -> anyhow::Result<()> {
+    let api_key = rpassword::prompt_password(format!("{kind:?} API key: "))?;
+    anyhow::ensure!(!api_key.trim().is_empty(), "no key entered");
+    KeyringStore::default().set(kind, api_key.trim())?;
+    println!("Saved {kind:?} key to the OS keyring.");
+    Ok(())
+}
+
+pub fn keyring_logout(kind: ProviderKind) -> anyhow::Result<()> {
+    KeyringStore::default().delete(kind)?;
+    Ok(())
+}
+
+// Hot-reloadable Configuration
+//
+// Long-running services pick up model, temperature, and preamble edits
+// without a restart. Build agents per request from the current snapshot:
+//     let live = LiveConfig::watch("rig.toml")?;
+//     let agent = live.agent("default", "You are a helpful assistant.")?;
+// An edit that fails validation is logged and the previous config stays live.
+pub struct LiveConfig {
+    current: Arc<ArcSwap<Config>>,
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl LiveConfig {
+    pub fn watch(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        use notify::{EventKind, RecursiveMode, Watcher};
+
+        let path = path.as_ref().canonicalize()?;
+        let current = Arc::new(ArcSwap::from_pointee(Config::load(&path)?));
+
+        let target = path.clone();
+        let swap = Arc::clone(&current);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else { return };
+            let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event.paths.iter().any(|p| p == &target);
+            if !relevant {
+                return;
+            }
+            match Config::load(&target) {
+                Ok(config) => {
+                    swap.store(Arc::new(config));
+                    tracing::info!(path = %target.display(), "config reloaded");
+                }
+                Err(e) => tracing::warn!(error = %e, "config reload rejected; keeping previous config"),
+            }
+        })?;
+        // Watch the directory: editors often save by replacing the file
+        let dir = path.parent().ok_or_else(|| anyhow::anyhow!("config path has no parent"))?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Self { current, _watcher: watcher })
+    }
+
+    /// Snapshot of the config as of now
+    pub fn get(&self) -> Arc<Config> {
+        self.current.load_full()
+    }
+
+    pub fn agent(&self, role: &str, preamble: &str) -> anyhow::Result<Box<dyn DynAgent>> {
+        self.get().agent(role, preamble)
+    }
+}
+
+// Price Table
+//
+// List prices (USD per million tokens) seeded from the model catalog, with a
+// file for negotiated rates, new models, or price changes:
+//     # prices.toml
+//     [prices."gpt-4o"]
+//     input = 2.00
+//     output = 8.00
+//
+//     let prices = PriceTable::default().with_overrides("prices.toml")?;
+//     let usd = prices.cost("gpt-4o", usage.input_tokens, usage.output_tokens);
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Price {
+    /// USD per million input tokens
+    pub input: f64,
+    /// USD per million output tokens
+    pub output: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    prices: HashMap<String, Price>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self::from_catalog(&ModelCatalog::default())
+    }
+}
+
+impl PriceTable {
+    pub fn from_catalog(catalog: &ModelCatalog) -> Self {
+        let prices = catalog.all().iter()
+            .map(|m| (m.id.to_string(), Price { input: m.input_per_mtok, output: m.output_per_mtok }))
+            .collect();
+        Self { prices }
+    }
+
+    /// Merge prices from a TOML file; entries replace the built-in ones
+    pub fn with_overrides(mut self, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct PriceFile {
+            prices: HashMap<String, Price>,
+        }
+
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        let file: PriceFile = toml::from_str(&raw)
+            .map_err(|e| anyhow::anyhow!("malformed {}: {e}", path.display()))?;
+        for (model, price) in &file.prices {
+            anyhow::ensure!(
+                price.input >= 0.0 && price.output >= 0.0,
+                "{}: price for {model} must be non-negative",
+                path.display()
+            );
+        }
+        self.prices.extend(file.prices);
+        Ok(self)
+    }
+
+    pub fn set(&mut self, model: impl Into<String>, price: Price) {
+        self.prices.insert(model.into(), price);
+    }
+
+    pub fn get(&self, model: &str) -> Option<Price> {
+        self.prices.get(model).copied()
+    }
+
+    /// `None` for models without a price, so callers can't mistake unknown for free
+    pub fn cost(&self, model: &str, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        let price = self.get(model)?;
+        Some((input_tokens as f64 * price.input + output_tokens as f64 * price.output) / 1_000_000.0)
+    }
+}
+
+// Cost Tracking
+//
+// `CostTracker` prices each completion from the `PriceTable` and keeps
+// today's totals per model; `BudgetGuard` holds them against the config's
+// `[budgets]`, turning away a request whose worst case (prompt plus
+// `max_tokens` of output) would overrun the day:
+//     let guard = BudgetGuard::new(config.budgets.clone(), CostTracker::new(prices));
+//     guard.check("gpt-4o", prompt_tokens, max_tokens)?;
+//     let response = agent.prompt(question).await?;
+//     guard.record("gpt-4o", usage.input_tokens, usage.output_tokens);
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelSpend {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub usd: f64,
+}
+
+pub struct CostTracker {
+    prices: PriceTable,
+    /// UTC day number and model -> spend that day
+    spend: Mutex<(u64, HashMap<String, ModelSpend>)>,
+}
+
+impl CostTracker {
+    pub fn new(prices: PriceTable) -> Self {
+        Self { prices, spend: Mutex::new((utc_day(), HashMap::new())) }
+    }
+
+    pub fn prices(&self) -> &PriceTable {
+        &self.prices
+    }
+
+    /// Charge a completed request and return its cost. Tokens for a model
+    /// without a price are still counted, but cost nothing.
+    pub fn record(&self, model: &str, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        let cost = self.prices.cost(model, input_tokens, output_tokens);
+        if cost.is_none() {
+            tracing::warn!(model, "no price for model; usage not charged");
+        }
+        let mut spend = self.spend.lock().unwrap();
+        roll_over(&mut spend);
+        let entry = spend.1.entry(model.to_string()).or_default();
+        entry.requests += 1;
+        entry.input_tokens += input_tokens;
+        entry.output_tokens += output_tokens;
+        entry.usd += cost.unwrap_or(0.0);
+        cost
+    }
+
+    /// USD spent so far today (UTC)
+    pub fn today(&self) -> f64 {
+        let mut spend = self.spend.lock().unwrap();
+        roll_over(&mut spend);
+        spend.1.values().map(|s| s.usd).sum()
+    }
+
+    /// Today's spend per model, most expensive first
+    pub fn by_model(&self) -> Vec<(String, ModelSpend)> {
+        let mut spend = self.spend.lock().unwrap();
+        roll_over(&mut spend);
+        let mut models: Vec<_> = spend.1.iter().map(|(m, s)| (m.clone(), s.clone())).collect();
+        models.sort_by(|a, b| b.1.usd.total_cmp(&a.1.usd));
+        models
+    }
+}
+
+/// Start a fresh ledger when the UTC day changes
+fn roll_over(spend: &mut (u64, HashMap<String, ModelSpend>)) {
+    let today = utc_day();
+    if spend.0 != today {
+        *spend = (today, HashMap::new());
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BudgetError {
+    #[error("No price for {0}; cannot check it against the budget")]
+    UnknownPrice(String),
+
+    #[error("Request of up to {tokens} tokens exceeds the {limit} token per-request limit")]
+    RequestTooLarge { tokens: u64, limit: u64 },
+
+    #[error("Request estimated at ${estimate:.2} would exceed the daily budget (${spent:.2} of ${limit:.2} spent)")]
+    DailyBudget { spent: f64, estimate: f64, limit: f64 },
+}
+
+pub struct BudgetGuard {
+    budgets: Budgets,
+    tracker: CostTracker,
+}
+
+impl BudgetGuard {
+    pub fn new(budgets: Budgets, tracker: CostTracker) -> Self {
+        Self { budgets, tracker }
+    }
+
+    pub fn tracker(&self) -> &CostTracker {
+        &self.tracker
+    }
+
+    /// Check a request before sending it; returns its worst-case cost.
+    /// Unpriced models are rejected only when a daily budget is set.
+    pub fn check(&self, model: &str, input_tokens: u64, max_output_tokens: u64) -> Result<f64, BudgetError> {
+        let tokens = input_tokens + max_output_tokens;
+        if let Some(limit) = self.budgets.max_tokens_per_request.filter(|limit| tokens > *limit) {
+            return Err(BudgetError::RequestTooLarge { tokens, limit });
+        }
+        let estimate = self.tracker.prices.cost(model, input_tokens, max_output_tokens);
+        let Some(limit) = self.budgets.daily_usd else {
+            return Ok(estimate.unwrap_or(0.0));
+        };
+        let estimate = estimate.ok_or_else(|| BudgetError::UnknownPrice(model.to_string()))?;
+        let spent = self.tracker.today();
+        if spent + estimate > limit {
+            return Err(BudgetError::DailyBudget { spent, estimate, limit });
+        }
+        Ok(estimate)
+    }
+
+    /// Charge a completed request; see `CostTracker::record`
+    pub fn record(&self, model: &str, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        self.tracker.record(model, input_tokens, output_tokens)
+    }
+}
+
+// Shared Clients
+//
+// Each client owns an HTTP connection pool; build it once per process and
+// share it instead of calling `from_env()` in every function:
+//     let agent = CLIENTS.openai().agent("gpt-4o").build();
+// A client is only constructed (and its env var read) on first use.
+pub struct Clients {
+    openai: LazyLock<openai::Client>,
+    #[cfg(feature = "anthropic")]
+    anthropic: LazyLock<anthropic::Client>,
+    #[cfg(feature = "gemini")]
+    gemini: LazyLock<gemini::Client>,
+    #[cfg(feature = "cohere")]
+    cohere: LazyLock<cohere::Client>,
+    mistral: LazyLock<mistral::Client>,
+    #[cfg(feature = "ollama")]
+    ollama: LazyLock<ollama::Client>,
+    #[cfg(feature = "bedrock")]
+    bedrock: tokio::sync::OnceCell<bedrock::Client>,
+}
+
+pub static CLIENTS: Clients = Clients {
+    openai: LazyLock::new(openai_client),
+    #[cfg(feature = "anthropic")]
+    anthropic: LazyLock::new(anthropic_client),
+    #[cfg(feature = "gemini")]
+    gemini: LazyLock::new(gemini_client),
+    #[cfg(feature = "cohere")]
+    cohere: LazyLock::new(cohere_client),
+    mistral: LazyLock::new(mistral_client),
+    #[cfg(feature = "ollama")]
+    ollama: LazyLock::new(ollama_client),
+    #[cfg(feature = "bedrock")]
+    bedrock: tokio::sync::OnceCell::const_new(),
+};
+
+impl Clients {
+    pub fn openai(&self) -> &openai::Client {
+        &self.openai
+    }
+
+    #[cfg(feature = "anthropic")]
+    pub fn anthropic(&self) -> &anthropic::Client {
+        &self.anthropic
+    }
+
+    #[cfg(feature = "gemini")]
+    pub fn gemini(&self) -> &gemini::Client {
+        &self.gemini
+    }
+
+    #[cfg(feature = "cohere")]
+    pub fn cohere(&self) -> &cohere::Client {
+        &self.cohere
+    }
+
+    pub fn mistral(&self) -> &mistral::Client {
+        &self.mistral
+    }
+
+    #[cfg(feature = "ollama")]
+    pub fn ollama(&self) -> &ollama::Client {
+        &self.ollama
+    }
+
+    /// Async because AWS credentials resolve asynchronously
+    #[cfg(feature = "bedrock")]
+    pub async fn bedrock(&self) -> &bedrock::Client {
+        self.bedrock.get_or_init(bedrock_client).await
+    }
+}
+
+// Multi-tenant Configuration
+//
+// Each tenant brings its own keys, model allowlist, and budget on top of the
+// shared base config:
+//     # tenants.toml
+//     [tenants.acme]
+//     allowed_models = ["gpt-4o-mini", "claude-3-5-haiku-20241022"]
+//     budgets = { daily_usd = 50.0 }
+//     keys.openai = { env = "ACME_OPENAI_API_KEY" }
+//     keys.anthropic = { secret = "tenants/acme#anthropic" }
+//
+//     let agent = tenants.agent("acme", &config, "default", preamble, &secrets).await?;
+//     // Before each completion, so one large request can't overrun the budget:
+//     tenants.authorize_request("acme", "gpt-4o-mini", prompt_tokens, max_tokens)?;
+//     // ...and after it:
+//     tenants.record("acme", "gpt-4o-mini", usage.input_tokens, usage.output_tokens)?;
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyRef {
+    Env(String),
+    Secret(String),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tenant {
+    /// Tenants without a key for a provider fall back to the base config's key
+    #[serde(default)]
+    pub keys: HashMap<ProviderKind, KeyRef>,
+    /// Empty means every model in the base config
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    #[serde(default)]
+    pub budgets: Budgets,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    pub tenants: HashMap<String, Tenant>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TenantError {
+    #[error("Unknown tenant: {0}")]
+    UnknownTenant(String),
+
+    #[error("Tenant {tenant} may not use {model}")]
+    ModelNotAllowed { tenant: String, model: String },
+
+    #[error("Tenant {tenant} has spent ${spent:.2} of its ${limit:.2} daily budget")]
+    BudgetExceeded { tenant: String, spent: f64, limit: f64 },
+
+    #[error("Tenant {tenant} has ${remaining:.2} of its daily budget left; request estimated at ${estimate:.2}")]
+    InsufficientBudget { tenant: String, remaining: f64, estimate: f64 },
+
+    #[error("No price for {model}; cannot check it against tenant {tenant}'s budget")]
+    UnknownPrice { tenant: String, model: String },
+}
+
+pub struct Tenants {
+    config: TenantConfig,
+    prices: PriceTable,
+    /// Tenant -> (UTC day number, USD spent that day)
+    spend: Mutex<HashMap<String, (u64, f64)>>,
+}
+
+fn utc_day() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 86_400
+}
+
+impl Tenants {
+    pub fn new(config: TenantConfig, prices: PriceTable) -> Self {
+        Self { config, prices, spend: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn load(path: impl AsRef<Path>, prices: PriceTable) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        let config = toml::from_str(&raw)
+            .map_err(|e| anyhow::anyhow!("malformed {}: {e}", path.display()))?;
+        Ok(Self::new(config, prices))
+    }
+
+    pub fn spent_today(&self, tenant: &str) -> f64 {
+        match self.spend.lock().unwrap().get(tenant) {
+            Some(&(day, usd)) if day == utc_day() => usd,
+            _ => 0.0,
+        }
+    }
+
+    /// Check the allowlist and remaining budget before a request
+    pub fn authorize(&self, tenant_id: &str, model: &str) -> Result<&Tenant, TenantError> {
+        let tenant = self.config.tenants.get(tenant_id)
+            .ok_or_else(|| TenantError::UnknownTenant(tenant_id.to_string()))?;
+        if !tenant.allowed_models.is_empty() && !tenant.allowed_models.iter().any(|m| m == model) {
+            return Err(TenantError::ModelNotAllowed { tenant: tenant_id.to_string(), model: model.to_string() });
+        }
+        if let Some(limit) = tenant.budgets.daily_usd {
+            let spent = self.spent_today(tenant_id);
+            if spent >= limit {
+                return Err(TenantError::BudgetExceeded { tenant: tenant_id.to_string(), spent, limit });
+            }
+        }
+        Ok(tenant)
+    }
+
+    /// `authorize`, plus the request's worst-case cost (prompt plus
+    /// `max_output_tokens`) against what's left of today's budget. Returns
+    /// the estimate; `record` the actual usage afterwards.
+    pub fn authorize_request(
+        &self,
+        tenant_id: &str,
+        model: &str,
+        input_tokens: u64,
+        max_output_tokens: u64,
+    ) -> Result<f64, TenantError> {
+        let tenant = self.authorize(tenant_id, model)?;
+        let estimate = self.prices.cost(model, input_tokens, max_output_tokens);
+        let Some(limit) = tenant.budgets.daily_usd else {
+            return Ok(estimate.unwrap_or(0.0));
+        };
+        let estimate = estimate.ok_or_else(|| TenantError::UnknownPrice {
+            tenant: tenant_id.to_string(),
+            model: model.to_string(),
+        })?;
+        let remaining = limit - self.spent_today(tenant_id);
+        if estimate > remaining {
+            return Err(TenantError::InsufficientBudget { tenant: tenant_id.to_string(), remaining, estimate });
+        }
+        Ok(estimate)
+    }
+
+    /// Charge a completed request to the tenant; returns today's total.
+    /// Always charges, even past the budget: in-flight requests still cost
+    /// money, and `authorize_request` turns away the next one.
+    pub fn record(&self, tenant: &str, model: &str, input_tokens: u64, output_tokens: u64) -> Result<f64, TenantError> {
+        let limits = &self.config.tenants.get(tenant)
+            .ok_or_else(|| TenantError::UnknownTenant(tenant.to_string()))?
+            .budgets;
+        if let Some(limit) = limits.max_tokens_per_request {
+            if input_tokens + output_tokens > limit {
+                tracing::warn!(tenant, model, input_tokens, output_tokens, "request exceeded tenant token limit");
+            }
+        }
+        let cost = self.prices.cost(model, input_tokens, output_tokens).unwrap_or_else(|| {
+            tracing::warn!(tenant, model, "no price for model; usage not charged");
+            0.0
+        });
+
+        let today = utc_day();
+        let mut spend = self.spend.lock().unwrap();
+        let entry = spend.entry(tenant.to_string()).or_insert((today, 0.0));
+        if entry.0 != today {
+            *entry = (today, 0.0);
+        }
+        entry.1 += cost;
+        Ok(entry.1)
+    }
+
+    /// Agent for one of the base config's model roles, billed to the tenant's key
+    pub async fn agent<S: SecretSource>(
+        &self,
+        tenant_id: &str,
+        base: &Config,
+        role: &str,
+        preamble: &str,
+        secrets: &CachedSecrets<S>,
+    ) -> anyhow::Result<Box<dyn DynAgent>> {
+        let model = base.model(role)
+            .ok_or_else(|| anyhow::anyhow!("no [models.{role}] in config"))?;
+        let tenant = self.authorize(tenant_id, &model.model)?;
+
+        let key = match tenant.keys.get(&model.provider) {
+            Some(KeyRef::Env(var)) => Some(std::env::var(var)
+                .map_err(|_| anyhow::anyhow!("{var} not set for tenant {tenant_id}"))?),
+            Some(KeyRef::Secret(name)) => Some(secrets.get(name).await?),
+            None => None,
+        };
+
+        let mut model = model.clone();
+        if let Some(limit) = tenant.budgets.max_tokens_per_request {
+            model.max_tokens = Some(model.max_tokens.map_or(limit, |n| n.min(limit)));
         }
+        let preamble = model.preamble.clone().unwrap_or_else(|| preamble.to_string());
+        Ok(base.factory_with_key(model.provider, key)?.build_agent(&model, &preamble))
     }
 }
@@ -0,0 +1,478 @@
+//! Embedding Templates for Rig
+//!
+//! Single and batch embedding generation with OpenAI and Cohere, plus the
+//! vector helpers (normalization, cosine similarity) the RAG templates use.
+//!
+//! The persistent embedding cache and local models sit behind cargo features:
+//!
+//! ```toml
+//! [features]
+//! embedding-cache = ["dep:rusqlite"]
+//! fastembed = ["dep:rig-fastembed"]
+//!
+//! [dependencies]
+//! rusqlite = { version = "0.32", features = ["bundled"], optional = true }
+//! rig-fastembed = { version = "0.2", optional = true }
+//! ```
+
+#[cfg(feature = "embedding-cache")]
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU32;
+#[cfg(feature = "embedding-cache")]
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use rig::embeddings::{EmbeddingError, EmbeddingModel};
+use rig::providers::openai;
+#[cfg(feature = "cohere")]
+use rig::providers::cohere;
+#[cfg(feature = "fastembed")]
+use rig_fastembed::FastembedModel;
+#[cfg(feature = "embedding-cache")]
+use rusqlite::{params, Connection, OptionalExtension};
+#[cfg(feature = "embedding-cache")]
+use sha2::{Digest, Sha256};
+
+use crate::config_template::{ProviderPolicy, CLIENTS};
+use crate::middleware_template::CallLimiter;
+
+// =============================================================================
+// GENERATION
+// =============================================================================
+
+/// Embed one text, e.g. a user query at search time
+pub async fn embed_one<M: EmbeddingModel>(model: &M, text: &str) -> Result<Vec<f64>, EmbeddingError> {
+    Ok(model.embed_text(text).await?.vec)
+}
+
+/// Embed many texts, split into requests of at most `M::MAX_DOCUMENTS`.
+/// Vectors come back in input order.
+pub async fn embed_batch<M: EmbeddingModel>(
+    model: &M,
+    texts: &[String],
+) -> Result<Vec<Vec<f64>>, EmbeddingError> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(M::MAX_DOCUMENTS) {
+        let embeddings = model.embed_texts(batch.to_vec()).await?;
+        vectors.extend(embeddings.into_iter().map(|e| e.vec));
+    }
+    Ok(vectors)
+}
+
+// =============================================================================
+// BATCH EMBEDDING
+// =============================================================================
+
+// `embed_batch` sends one request at a time and gives up on the first error,
+// which is fine for a few hundred texts. For a full corpus, `BatchEmbedder`
+// keeps several requests in flight under the provider's rate limit and retries
+// each failed batch on its own, so one 429 doesn't restart the whole run:
+//     let embedder = BatchEmbedder::new(model)
+//         .concurrency(8)
+//         .requests_per_minute(NonZeroU32::new(500).unwrap());
+//     let vectors = embedder.embed(&texts).await?;
+pub struct BatchEmbedder<M> {
+    model: M,
+    batch_size: usize,
+    concurrency: usize,
+    limiter: Option<Arc<CallLimiter>>,
+    policy: ProviderPolicy,
+}
+
+impl<M: EmbeddingModel> BatchEmbedder<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            batch_size: M::MAX_DOCUMENTS,
+            concurrency: 4,
+            limiter: None,
+            policy: ProviderPolicy::default(),
+        }
+    }
+
+    /// Texts per request, capped at the provider maximum
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, M::MAX_DOCUMENTS);
+        self
+    }
+
+    /// Requests in flight at once
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn requests_per_minute(self, requests: NonZeroU32) -> Self {
+        self.limiter(Arc::new(governor::RateLimiter::direct(governor::Quota::per_minute(requests))))
+    }
+
+    /// Share one quota with other callers of the same API key
+    pub fn limiter(mut self, limiter: Arc<CallLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Retry budget per batch; transient errors (429s, 5xx, timeouts) only
+    pub fn policy(mut self, policy: ProviderPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Vectors in input order. Fails only if some batch still fails after its
+    /// retries, naming how many did.
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f64>>> {
+        let results: Vec<_> = stream::iter(texts.chunks(self.batch_size))
+            .map(|batch| self.embed_one_batch(batch))
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+
+        let batches = results.len();
+        let mut vectors = Vec::with_capacity(texts.len());
+        let mut failures = Vec::new();
+        for result in results {
+            match result {
+                Ok(batch) => vectors.extend(batch),
+                Err(e) => failures.push(e),
+            }
+        }
+        match failures.first() {
+            None => Ok(vectors),
+            Some(first) => Err(anyhow::anyhow!("{} of {batches} embedding batches failed: {first}", failures.len())),
+        }
+    }
+
+    async fn embed_one_batch(&self, batch: &[String]) -> Result<Vec<Vec<f64>>, EmbeddingError> {
+        self.policy.retry(|| async move {
+            if let Some(limiter) = &self.limiter {
+                limiter.until_ready().await;
+            }
+            let embeddings = self.model.embed_texts(batch.to_vec()).await?;
+            if embeddings.len() != batch.len() {
+                return Err(EmbeddingError::ResponseError(format!(
+                    "expected {} embeddings, got {}", batch.len(), embeddings.len()
+                )));
+            }
+            Ok(embeddings.into_iter().map(|e| e.vec).collect())
+        })
+        .await
+    }
+}
+
+// =============================================================================
+// EMBEDDING CACHE
+// =============================================================================
+
+// Re-indexing mostly re-embeds text that hasn't changed, and chunked corpora
+// repeat boilerplate (licenses, footers) across documents. The cache maps
+// (model, SHA-256 of the text) to the vector in a SQLite file, so each
+// distinct text is paid for once per model:
+//     let cache = EmbeddingCache::open(".cache/embeddings.db")?;
+//     let embedder = CachedEmbedder::new(BatchEmbedder::new(model), "text-embedding-3-small", cache);
+//     let vectors = embedder.embed(&texts).await?;
+#[cfg(feature = "embedding-cache")]
+pub struct EmbeddingCache {
+    conn: Arc<std::sync::Mutex<Connection>>,
+}
+
+#[cfg(feature = "embedding-cache")]
+impl EmbeddingCache {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS embeddings (
+                 model TEXT NOT NULL,
+                 hash TEXT NOT NULL,
+                 vector BLOB NOT NULL,
+                 PRIMARY KEY (model, hash)
+             ) WITHOUT ROWID;",
+        )?;
+        Ok(Self { conn: Arc::new(std::sync::Mutex::new(conn)) })
+    }
+
+    /// Cached vectors for `texts`, `None` where missing, in input order
+    pub async fn get_many(&self, model: &str, texts: &[String]) -> Result<Vec<Option<Vec<f64>>>> {
+        let model = model.to_string();
+        let hashes: Vec<String> = texts.iter().map(|text| text_hash(text)).collect();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare_cached("SELECT vector FROM embeddings WHERE model = ?1 AND hash = ?2")?;
+            hashes.iter()
+                .map(|hash| {
+                    let blob: Option<Vec<u8>> = stmt.query_row(params![model, hash], |row| row.get(0)).optional()?;
+                    Ok(blob.map(|b| from_bytes(&b)))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Store `(text, vector)` pairs, replacing any existing entry
+    pub async fn put_many(&self, model: &str, entries: Vec<(String, Vec<f64>)>) -> Result<()> {
+        let model = model.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "INSERT OR REPLACE INTO embeddings (model, hash, vector) VALUES (?1, ?2, ?3)",
+                )?;
+                for (text, vector) in &entries {
+                    stmt.execute(params![model, text_hash(text), to_bytes(vector)])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run blocking SQLite work off the async runtime
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+}
+
+#[cfg(feature = "embedding-cache")]
+fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+#[cfg(feature = "embedding-cache")]
+fn to_bytes(vector: &[f64]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+#[cfg(feature = "embedding-cache")]
+fn from_bytes(bytes: &[u8]) -> Vec<f64> {
+    bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().expect("8-byte chunk"))).collect()
+}
+
+/// `BatchEmbedder` behind the cache: only texts not seen before for this
+/// model reach the provider, each distinct text once
+#[cfg(feature = "embedding-cache")]
+pub struct CachedEmbedder<M> {
+    embedder: BatchEmbedder<M>,
+    model_id: String,
+    cache: EmbeddingCache,
+}
+
+#[cfg(feature = "embedding-cache")]
+impl<M: EmbeddingModel> CachedEmbedder<M> {
+    /// `model_id` keys the cache; change it whenever the vectors would change
+    /// (another model, or reduced dimensions)
+    pub fn new(embedder: BatchEmbedder<M>, model_id: impl Into<String>, cache: EmbeddingCache) -> Self {
+        Self { embedder, model_id: model_id.into(), cache }
+    }
+
+    /// Vectors in input order
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f64>>> {
+        let cached = self.cache.get_many(&self.model_id, texts).await?;
+
+        let mut seen = HashSet::new();
+        let misses: Vec<String> = texts.iter().zip(&cached)
+            .filter(|(text, vector)| vector.is_none() && seen.insert(text.as_str()))
+            .map(|(text, _)| text.clone())
+            .collect();
+        tracing::debug!(cached = texts.len() - misses.len(), embedding = misses.len(), "embedding cache lookup");
+        if misses.is_empty() {
+            return Ok(cached.into_iter().flatten().collect());
+        }
+
+        let fresh = self.embedder.embed(&misses).await?;
+        let entries: Vec<(String, Vec<f64>)> = misses.into_iter().zip(fresh).collect();
+        let lookup: HashMap<&str, &Vec<f64>> = entries.iter().map(|(t, v)| (t.as_str(), v)).collect();
+        let vectors = texts.iter().zip(cached)
+            .map(|(text, vector)| vector.unwrap_or_else(|| lookup[text.as_str()].clone()))
+            .collect();
+        self.cache.put_many(&self.model_id, entries).await?;
+        Ok(vectors)
+    }
+}
+
+/// Embed the same corpus twice; the second run is served from the cache
+#[cfg(feature = "embedding-cache")]
+pub async fn cached_embeddings() -> Result<()> {
+    let model = CLIENTS.openai().embedding_model(openai::TEXT_EMBEDDING_3_SMALL);
+    let cache = EmbeddingCache::open(".cache/embeddings.db")?;
+    let embedder = CachedEmbedder::new(BatchEmbedder::new(model), openai::TEXT_EMBEDDING_3_SMALL, cache);
+
+    let texts = vec![
+        "Licensed under the MIT license.".to_string(),
+        "Cargo builds Rust code and downloads dependencies.".to_string(),
+        "Licensed under the MIT license.".to_string(),
+    ];
+    for run in 1..=2 {
+        let started = std::time::Instant::now();
+        embedder.embed(&texts).await?;
+        tracing::info!(run, elapsed = ?started.elapsed(), "embedded batch");
+    }
+    Ok(())
+}
+
+// =============================================================================
+// VECTOR MATH
+// =============================================================================
+
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+    debug_assert_eq!(a.len(), b.len(), "vectors must have the same dimensions");
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub fn norm(v: &[f64]) -> f64 {
+    dot(v, v).sqrt()
+}
+
+/// Scale to unit length in place; zero vectors are left untouched
+pub fn normalize(v: &mut [f64]) {
+    let n = norm(v);
+    if n > 0.0 {
+        v.iter_mut().for_each(|x| *x /= n);
+    }
+}
+
+pub fn normalized(mut v: Vec<f64>) -> Vec<f64> {
+    normalize(&mut v);
+    v
+}
+
+/// Cosine similarity in [-1, 1]; 0 when either vector is all zeros.
+/// For vectors already normalized, `dot` gives the same answer cheaper.
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot(a, b) / denom }
+}
+
+/// Indices of the `k` candidates most similar to `query`, best first
+pub fn top_k(query: &[f64], candidates: &[Vec<f64>], k: usize) -> Vec<(usize, f64)> {
+    let mut scored: Vec<_> = candidates.iter()
+        .enumerate()
+        .map(|(i, c)| (i, cosine_similarity(query, c)))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(k);
+    scored
+}
+
+// =============================================================================
+// OPENAI
+// =============================================================================
+
+/// text-embedding-3-small (1536 dims): embed a query and a handful of
+/// documents, then rank the documents against the query
+pub async fn openai_embeddings() -> Result<()> {
+    let model = CLIENTS.openai().embedding_model(openai::TEXT_EMBEDDING_3_SMALL);
+
+    let docs = vec![
+        "Rust guarantees memory safety without a garbage collector.".to_string(),
+        "Python is popular for data science.".to_string(),
+        "Cargo is Rust's build tool and package manager.".to_string(),
+    ];
+    let vectors = embed_batch(&model, &docs).await?;
+    let query = embed_one(&model, "How does Rust manage memory?").await?;
+
+    for (i, score) in top_k(&query, &vectors, 2) {
+        println!("{score:.4}  {}", docs[i]);
+    }
+    Ok(())
+}
+
+/// Embed a few thousand texts with 8 requests in flight, under 500 requests/minute
+pub async fn bulk_embeddings() -> Result<()> {
+    let model = CLIENTS.openai().embedding_model(openai::TEXT_EMBEDDING_3_SMALL);
+    let embedder = BatchEmbedder::new(model)
+        .concurrency(8)
+        .requests_per_minute(NonZeroU32::new(500).unwrap());
+
+    let texts: Vec<String> = (0..5_000)
+        .map(|i| format!("Ticket #{i}: customer reports a failed payment on checkout."))
+        .collect();
+    let vectors = embedder.embed(&texts).await?;
+    tracing::info!(texts = vectors.len(), dims = vectors[0].len(), "embedded corpus");
+    Ok(())
+}
+
+// =============================================================================
+// COHERE
+// =============================================================================
+
+// Cohere embeds documents and queries differently; pass the matching input
+// type on each side or retrieval quality drops noticeably.
+#[cfg(feature = "cohere")]
+pub mod cohere_input {
+    pub const DOCUMENT: &str = "search_document";
+    pub const QUERY: &str = "search_query";
+}
+
+#[cfg(feature = "cohere")]
+pub async fn cohere_embeddings() -> Result<()> {
+    let client = CLIENTS.cohere();
+    let doc_model = client.embedding_model(cohere::EMBED_ENGLISH_V3, cohere_input::DOCUMENT);
+    let query_model = client.embedding_model(cohere::EMBED_ENGLISH_V3, cohere_input::QUERY);
+
+    let docs = vec![
+        "The Eiffel Tower is in Paris.".to_string(),
+        "Mount Fuji is the tallest peak in Japan.".to_string(),
+    ];
+    let vectors = embed_batch(&doc_model, &docs).await?;
+    let query = embed_one(&query_model, "Where is the Eiffel Tower?").await?;
+
+    for (i, score) in top_k(&query, &vectors, 1) {
+        println!("{score:.4}  {}", docs[i]);
+    }
+    Ok(())
+}
+
+// =============================================================================
+// LOCAL (FASTEMBED)
+// =============================================================================
+
+// An ONNX model run in-process: downloaded once to `.fastembed_cache`, then
+// no API key, no server, no network. Paired with `SqliteVecStore` and Ollama
+// for chat, the whole RAG stack runs offline (`local_rag` in rag-template.rs).
+//
+// bge-small-en-v1.5 (384 dims) is a strong small English model; use
+// `FastembedModel::MultilingualE5Small` for other languages. Embedding is
+// CPU-bound, so index in moderate batches rather than all at once.
+#[cfg(feature = "fastembed")]
+pub fn local_embedding_model() -> rig_fastembed::EmbeddingModel {
+    rig_fastembed::Client::new().embedding_model(&FastembedModel::BGESmallENV15)
+}
+
+/// BGE models retrieve best when queries (not documents) carry this prefix
+#[cfg(feature = "fastembed")]
+pub const BGE_QUERY_PREFIX: &str = "Represent this sentence for searching relevant passages: ";
+
+#[cfg(feature = "fastembed")]
+pub async fn local_embeddings() -> Result<()> {
+    let model = local_embedding_model();
+
+    let docs = vec![
+        "SQLite stores a whole database in a single file.".to_string(),
+        "Postgres is a client-server relational database.".to_string(),
+    ];
+    let vectors = embed_batch(&model, &docs).await?;
+    let query = embed_one(&model, &format!("{BGE_QUERY_PREFIX}Which database is a single file?")).await?;
+
+    for (i, score) in top_k(&query, &vectors, 1) {
+        println!("{score:.4}  {}", docs[i]);
+    }
+    Ok(())
+}
@@ -0,0 +1,724 @@
+//! Document Loader Templates for Rig
+//!
+//! Loaders read a file, extract its text and metadata, and split it with the
+//! `chunking` module into `Document`s ready for `index_documents`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use rig::embeddings::EmbeddingModel;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::chunking_template::{
+    ChunkConfig, Chunker, CodeChunker, CodeLanguage, MarkdownChunker, RecursiveChunker,
+};
+use crate::rag_template::{index_documents, index_documents_dedup, Document, VectorStore};
+
+// =============================================================================
+// SHARED
+// =============================================================================
+
+pub trait Loader: Send + Sync {
+    /// File extensions handled, lowercase and without the dot
+    fn extensions(&self) -> &[&'static str];
+
+    /// Chunked documents for one file; ids are `{path}#{chunk}`
+    fn load(&self, path: &Path) -> Result<Vec<Document>>;
+}
+
+fn read_text(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))
+}
+
+// =============================================================================
+// MARKDOWN
+// =============================================================================
+
+// Chunks never span sections; each carries its heading path as `headings`
+// metadata, plus the document `title` (first H1, else the file name):
+//     let docs = MarkdownLoader::default().strip_code(true).load(Path::new("README.md"))?;
+//     index_documents(&store, &model, docs).await?;
+pub struct MarkdownLoader {
+    config: ChunkConfig,
+    strip_code: bool,
+}
+
+impl Default for MarkdownLoader {
+    fn default() -> Self {
+        Self { config: ChunkConfig::default(), strip_code: false }
+    }
+}
+
+impl MarkdownLoader {
+    pub fn chunking(mut self, config: ChunkConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Drop fenced code blocks, e.g. when indexing prose for Q&A where code
+    /// samples would dominate the embeddings
+    pub fn strip_code(mut self, strip: bool) -> Self {
+        self.strip_code = strip;
+        self
+    }
+
+    /// Markdown text with YAML front matter removed and, if configured, code fences
+    fn clean(&self, raw: &str) -> String {
+        // Files saved on Windows end lines with \r\n
+        let raw = raw.replace("\r\n", "\n");
+        let body = raw.strip_prefix("---\n")
+            .and_then(|rest| rest.split_once("\n---\n").map(|(_, body)| body))
+            .unwrap_or(&raw);
+
+        let mut out = String::with_capacity(body.len());
+        let mut in_fence = false;
+        for line in body.lines() {
+            let fence = line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~");
+            if fence {
+                in_fence = !in_fence;
+            }
+            if self.strip_code && (fence || in_fence) {
+                continue;
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn markdown_title(text: &str) -> Option<String> {
+    text.lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|title| title.trim().to_string())
+}
+
+impl Loader for MarkdownLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["md", "markdown"]
+    }
+
+    fn load(&self, path: &Path) -> Result<Vec<Document>> {
+        let text = self.clean(&read_text(path)?);
+        let source = path.display().to_string();
+        let title = markdown_title(&text)
+            .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        let chunker = MarkdownChunker { config: self.config.clone() };
+        Ok(chunker.chunk(&text)
+            .into_iter()
+            .map(|chunk| chunk.into_document(&source).with("title", title.as_str()))
+            .collect())
+    }
+}
+
+// =============================================================================
+// PDF
+// =============================================================================
+
+// Text is extracted page by page (`lopdf`), so every chunk carries a `page`
+// number for citations. Scanned pages have no text layer; they are skipped and
+// reported rather than indexed as empty chunks. OCR them first if they matter.
+pub struct PdfLoader {
+    config: ChunkConfig,
+    /// Pages with fewer non-whitespace characters are treated as empty
+    min_chars: usize,
+}
+
+impl Default for PdfLoader {
+    fn default() -> Self {
+        Self { config: ChunkConfig::default(), min_chars: 20 }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PdfLoad {
+    pub documents: Vec<Document>,
+    /// Pages skipped as empty or scanned (1-based)
+    pub empty_pages: Vec<u32>,
+    /// Pages whose text could not be extracted, with the reason
+    pub failed_pages: Vec<(u32, String)>,
+}
+
+impl PdfLoader {
+    pub fn chunking(mut self, config: ChunkConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn min_chars(mut self, min_chars: usize) -> Self {
+        self.min_chars = min_chars;
+        self
+    }
+
+    /// Like `load`, but also returns which pages were skipped and why
+    pub fn load_report(&self, path: &Path) -> Result<PdfLoad> {
+        let pdf = lopdf::Document::load(path)
+            .map_err(|e| anyhow::anyhow!("cannot open PDF {}: {e}", path.display()))?;
+        let source = path.display().to_string();
+        let chunker = RecursiveChunker::new(self.config.clone());
+
+        let mut report = PdfLoad::default();
+        for page in pdf.get_pages().into_keys() {
+            let text = match pdf.extract_text(&[page]) {
+                Ok(text) => text,
+                Err(e) => {
+                    report.failed_pages.push((page, e.to_string()));
+                    continue;
+                }
+            };
+            if text.chars().filter(|c| !c.is_whitespace()).count() < self.min_chars {
+                report.empty_pages.push(page);
+                continue;
+            }
+            for mut chunk in chunker.chunk(&text) {
+                // Number chunks across the whole file so ids stay unique
+                chunk.index = report.documents.len();
+                report.documents.push(chunk.into_document(&source).with("page", page));
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl Loader for PdfLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["pdf"]
+    }
+
+    fn load(&self, path: &Path) -> Result<Vec<Document>> {
+        let report = self.load_report(path)?;
+        if !report.empty_pages.is_empty() {
+            tracing::warn!(path = %path.display(), pages = ?report.empty_pages, "skipped pages with no text layer");
+        }
+        for (page, error) in &report.failed_pages {
+            tracing::warn!(path = %path.display(), page, %error, "could not extract page text");
+        }
+        Ok(report.documents)
+    }
+}
+
+// =============================================================================
+// HTML
+// =============================================================================
+
+// Saved pages and crawled docs: scripts, navigation, footers, and other page
+// chrome are dropped, and the remaining content is rendered as markdown-ish
+// text so `MarkdownChunker` can split on its headings. Every chunk keeps the
+// page `url` (canonical link if present) and `title` for citations.
+//     let docs = HtmlLoader::default().load_html(&body, "https://docs.rs/rig-core")?;
+pub struct HtmlLoader {
+    config: ChunkConfig,
+    /// Elements dropped with everything inside them
+    skip: Vec<&'static str>,
+}
+
+impl Default for HtmlLoader {
+    fn default() -> Self {
+        Self {
+            config: ChunkConfig::default(),
+            skip: vec![
+                "script", "style", "noscript", "template", "svg", "iframe", "form", "button",
+                "nav", "header", "footer", "aside",
+            ],
+        }
+    }
+}
+
+impl HtmlLoader {
+    pub fn chunking(mut self, config: ChunkConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Also drop elements with this tag name
+    pub fn skip(mut self, tag: &'static str) -> Self {
+        self.skip.push(tag);
+        self
+    }
+
+    /// Chunk an HTML string fetched from `url`
+    pub fn load_html(&self, html: &str, url: &str) -> Result<Vec<Document>> {
+        let page = Html::parse_document(html);
+        let select = |css: &str, attr: &str| {
+            let selector = Selector::parse(css).expect("static selector");
+            page.select(&selector).next().and_then(|el| el.value().attr(attr)).map(str::to_string)
+        };
+        let url = select(r#"link[rel="canonical"]"#, "href").unwrap_or_else(|| url.to_string());
+        let title = Selector::parse("title").ok()
+            .and_then(|s| page.select(&s).next())
+            .map(|el| el.text().collect::<String>().trim().to_string())
+            .or_else(|| select(r#"meta[property="og:title"]"#, "content"))
+            .unwrap_or_default();
+
+        // Prefer the main content region when the page marks one
+        let main = Selector::parse("main, article, [role=main]").expect("static selector");
+        let root = page.select(&main).next().unwrap_or_else(|| page.root_element());
+        let mut text = String::new();
+        self.render(root, &mut text);
+        // Block elements each add blank lines; keep at most one between blocks
+        while text.contains("\n\n\n") {
+            text = text.replace("\n\n\n", "\n\n");
+        }
+
+        let chunker = MarkdownChunker { config: self.config.clone() };
+        Ok(chunker.chunk(&text)
+            .into_iter()
+            .map(|chunk| chunk.into_document(&url).with("url", url.as_str()).with("title", title.as_str()))
+            .collect())
+    }
+
+    fn render(&self, element: ElementRef, out: &mut String) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => {
+                    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if !collapsed.is_empty() {
+                        if !out.is_empty() && !out.ends_with(['\n', ' ']) {
+                            out.push(' ');
+                        }
+                        out.push_str(&collapsed);
+                    }
+                }
+                Node::Element(el) => {
+                    let Some(child) = ElementRef::wrap(child) else { continue };
+                    let tag = el.name();
+                    if self.skip.contains(&tag) {
+                        continue;
+                    }
+                    match tag {
+                        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                            let level = tag[1..].parse().unwrap_or(1);
+                            let heading = child.text().collect::<String>();
+                            out.push_str(&format!("\n\n{} {}\n\n", "#".repeat(level), heading.trim()));
+                        }
+                        "pre" => {
+                            out.push_str(&format!("\n\n```\n{}\n```\n\n", child.text().collect::<String>().trim_end()));
+                        }
+                        "li" => {
+                            out.push_str("\n- ");
+                            self.render(child, out);
+                        }
+                        "br" => out.push('\n'),
+                        "p" | "div" | "section" | "ul" | "ol" | "table" | "tr" | "blockquote" => {
+                            out.push_str("\n\n");
+                            self.render(child, out);
+                            out.push_str("\n\n");
+                        }
+                        _ => self.render(child, out),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Loader for HtmlLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["html", "htm"]
+    }
+
+    fn load(&self, path: &Path) -> Result<Vec<Document>> {
+        self.load_html(&read_text(path)?, &path.display().to_string())
+    }
+}
+
+// =============================================================================
+// SOURCE CODE
+// =============================================================================
+
+// For "chat with my codebase": Rust, TypeScript, and Python files are split
+// along function/impl/class boundaries by `CodeChunker`, and every chunk keeps
+// its `path` and defining `symbols`, so answers can point at real code:
+//     let docs = CodeLoader::default().load(Path::new("src/indexer.rs"))?;
+//     // docs[0].metadata["symbols"] == ["Indexer", "Indexer::new"]
+pub struct CodeLoader {
+    config: ChunkConfig,
+}
+
+impl Default for CodeLoader {
+    /// Smaller chunks than prose: one or two functions each
+    fn default() -> Self {
+        Self { config: ChunkConfig::tokens(384, 0).expect("valid chunk config") }
+    }
+}
+
+impl CodeLoader {
+    pub fn chunking(mut self, config: ChunkConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl Loader for CodeLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["rs", "ts", "tsx", "mts", "cts", "py", "pyi"]
+    }
+
+    fn load(&self, path: &Path) -> Result<Vec<Document>> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        let language = CodeLanguage::from_extension(ext)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a supported source file", path.display()))?;
+        let source = read_text(path)?;
+        let path_str = path.display().to_string();
+
+        let chunker = CodeChunker { config: self.config.clone(), language };
+        Ok(chunker.chunk(&source)
+            .into_iter()
+            .map(|chunk| chunk.into_document(&path_str).with("path", path_str.as_str()))
+            .collect())
+    }
+}
+
+// =============================================================================
+// DIRECTORY INDEXER
+// =============================================================================
+
+// Walks a tree the way `git` sees it (.gitignore, .ignore, and hidden files
+// skipped), hands each file to the loader registered for its extension, and
+// embeds chunks in batches as it goes rather than holding the whole corpus:
+//     let stats = Indexer::new(store.clone(), model)
+//         .exclude("target/**")
+//         .exclude("*.min.js")
+//         .on_progress(|p| tracing::debug!(done = p.files_done, total = p.files_total, file = %p.current.display(), "indexing"))
+//         .run(Path::new("."))
+//         .await?;
+//
+// With `.manifest("index.manifest.json")`, a content hash per file is kept
+// next to the index: later runs embed only new or changed files and delete
+// the vectors of files that were removed.
+#[derive(Debug, Clone)]
+pub struct IndexProgress {
+    pub files_done: usize,
+    pub files_total: usize,
+    pub chunks: usize,
+    pub current: PathBuf,
+}
+
+#[derive(Debug, Default)]
+pub struct IndexStats {
+    pub files: usize,
+    pub chunks: usize,
+    /// Files with no loader for their extension
+    pub skipped: usize,
+    /// Files a loader rejected; indexing continues past them
+    pub failed: Vec<(PathBuf, String)>,
+    /// Files whose hash matched the manifest and were not re-embedded
+    pub unchanged: usize,
+    /// Files in the manifest that no longer exist; their vectors were deleted
+    pub removed: usize,
+    /// Chunks dropped as near-duplicates (with `Indexer::dedup`)
+    pub duplicates: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileEntry {
+    /// SHA-256 of the file contents
+    hash: String,
+    /// Ids of the documents this file produced and the store holds
+    chunks: Vec<String>,
+    /// (dropped id, id it duplicated) for chunks `Indexer::dedup` left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    duplicates: Vec<(String, String)>,
+}
+
+/// What was indexed from each file, persisted between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    files: BTreeMap<PathBuf, FileEntry>,
+}
+
+impl Manifest {
+    /// An empty manifest if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(raw) => serde_json::from_slice(&raw)
+                .map_err(|e| anyhow::anyhow!("malformed manifest {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::anyhow!("cannot read {}: {e}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+fn content_hash(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+    Ok(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+pub type ProgressFn = Box<dyn Fn(&IndexProgress) + Send + Sync>;
+
+pub struct Indexer<M> {
+    store: Arc<dyn VectorStore>,
+    model: M,
+    loaders: HashMap<&'static str, Arc<dyn Loader>>,
+    excludes: Vec<String>,
+    /// Chunks per embed-and-upsert round trip
+    batch_size: usize,
+    on_progress: Option<ProgressFn>,
+    manifest: Option<PathBuf>,
+    dedup: Option<f64>,
+}
+
+impl<M: EmbeddingModel> Indexer<M> {
+    /// Markdown, PDF, HTML, and source-code loaders with their defaults
+    pub fn new(store: Arc<dyn VectorStore>, model: M) -> Self {
+        let indexer = Self {
+            store,
+            model,
+            loaders: HashMap::new(),
+            excludes: Vec::new(),
+            batch_size: 256,
+            on_progress: None,
+            manifest: None,
+            dedup: None,
+        };
+        indexer
+            .loader(MarkdownLoader::default())
+            .loader(PdfLoader::default())
+            .loader(HtmlLoader::default())
+            .loader(CodeLoader::default())
+    }
+
+    /// Register a loader for its extensions, replacing any earlier one
+    pub fn loader(mut self, loader: impl Loader + 'static) -> Self {
+        let loader: Arc<dyn Loader> = Arc::new(loader);
+        for ext in loader.extensions() {
+            self.loaders.insert(*ext, loader.clone());
+        }
+        self
+    }
+
+    /// Skip paths matching a gitignore-style glob, relative to the root
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.excludes.push(glob.into());
+        self
+    }
+
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Re-index incrementally, tracking file hashes in this file
+    pub fn manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest = Some(path.into());
+        self
+    }
+
+    pub fn on_progress(mut self, f: impl Fn(&IndexProgress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+
+    /// Skip chunks within `threshold` cosine similarity of one already indexed
+    pub fn dedup(mut self, threshold: f64) -> Self {
+        self.dedup = Some(threshold);
+        self
+    }
+
+    /// Files under `root` that would be indexed, after ignore rules and excludes
+    pub fn files(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        let mut overrides = OverrideBuilder::new(root);
+        for glob in &self.excludes {
+            // In override syntax, `!` means exclude
+            overrides.add(&format!("!{glob}"))?;
+        }
+        let walker = WalkBuilder::new(root).overrides(overrides.build()?).build();
+
+        let mut files = Vec::new();
+        for entry in walker {
+            let entry = entry?;
+            if entry.file_type().is_some_and(|t| t.is_file()) {
+                files.push(entry.into_path());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn loader_for(&self, path: &Path) -> Option<Arc<dyn Loader>> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.loaders.get(ext.as_str()).cloned()
+    }
+
+    /// Embed and store `batch`; returns the (dropped id, kept id) pairs
+    async fn flush(&self, batch: Vec<Document>, stats: &mut IndexStats) -> Result<Vec<(String, String)>> {
+        match self.dedup {
+            Some(threshold) => {
+                let report = index_documents_dedup(self.store.as_ref(), &self.model, batch, threshold).await?;
+                stats.duplicates += report.dropped.len();
+                Ok(report.dropped)
+            }
+            None => {
+                index_documents(self.store.as_ref(), &self.model, batch).await?;
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    pub async fn run(&self, root: &Path) -> Result<IndexStats> {
+        let files = self.files(root)?;
+        let mut stats = IndexStats::default();
+        let mut pending: Vec<Document> = Vec::new();
+        let previous = match &self.manifest {
+            Some(path) => Manifest::load(path)?,
+            None => Manifest::default(),
+        };
+        let mut manifest = Manifest::default();
+        // Chunk id -> file, for chunks embedded this run
+        let mut owners: HashMap<String, PathBuf> = HashMap::new();
+        // Files whose previous entry was kept as is
+        let mut carried: Vec<PathBuf> = Vec::new();
+        let mut deleted: HashSet<String> = HashSet::new();
+
+        for (i, path) in files.iter().enumerate() {
+            let Some(loader) = self.loader_for(path) else {
+                stats.skipped += 1;
+                continue;
+            };
+
+            let hash = if self.manifest.is_some() { Some(content_hash(path)?) } else { None };
+            let old = previous.files.get(path);
+            if let (Some(hash), Some(old)) = (&hash, old) {
+                if &old.hash == hash {
+                    stats.unchanged += 1;
+                    manifest.files.insert(path.clone(), old.clone());
+                    carried.push(path.clone());
+                    continue;
+                }
+            }
+
+            // Parsing PDFs and source files is CPU-bound; keep it off the runtime
+            let owned = path.clone();
+            match tokio::task::spawn_blocking(move || loader.load(&owned)).await? {
+                Ok(docs) => {
+                    stats.files += 1;
+                    stats.chunks += docs.len();
+                    let chunks: Vec<String> = docs.iter().map(|d| d.id.clone()).collect();
+                    // Upserts replace surviving ids; drop the ones the new version no longer has
+                    if let Some(old) = old {
+                        let stale: Vec<String> = old.chunks.iter().filter(|id| !chunks.contains(id)).cloned().collect();
+                        if !stale.is_empty() {
+                            self.store.delete(&stale).await?;
+                            deleted.extend(stale);
+                        }
+                    }
+                    if let Some(hash) = hash {
+                        owners.extend(chunks.iter().map(|id| (id.clone(), path.clone())));
+                        manifest.files.insert(path.clone(), FileEntry { hash, chunks, duplicates: Vec::new() });
+                    }
+                    pending.extend(docs);
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "failed to load");
+                    stats.failed.push((path.clone(), e.to_string()));
+                    // Keep the old entry: its chunks are still indexed, and the
+                    // stale hash makes the next run retry the file
+                    if let Some(old) = old {
+                        manifest.files.insert(path.clone(), old.clone());
+                        carried.push(path.clone());
+                    }
+                }
+            }
+
+            while pending.len() >= self.batch_size {
+                let batch: Vec<Document> = pending.drain(..self.batch_size).collect();
+                let dropped = self.flush(batch, &mut stats).await?;
+                note_duplicates(&mut manifest, &owners, dropped);
+            }
+
+            if let Some(report) = &self.on_progress {
+                report(&IndexProgress {
+                    files_done: i + 1,
+                    files_total: files.len(),
+                    chunks: stats.chunks,
+                    current: path.clone(),
+                });
+            }
+        }
+        if !pending.is_empty() {
+            let dropped = self.flush(pending, &mut stats).await?;
+            note_duplicates(&mut manifest, &owners, dropped);
+        }
+
+        if let Some(path) = &self.manifest {
+            for (file, entry) in &previous.files {
+                if !manifest.files.contains_key(file) && !files.contains(file) {
+                    self.store.delete(&entry.chunks).await?;
+                    deleted.extend(entry.chunks.iter().cloned());
+                    stats.removed += 1;
+                }
+            }
+            // An unchanged file is skipped, so a chunk dropped as a duplicate
+            // would stay missing after the chunk it duplicated was deleted or
+            // rewritten; clearing the hash makes the next run re-embed it
+            for file in &carried {
+                if let Some(entry) = manifest.files.get_mut(file) {
+                    let gone = |kept: &String| deleted.contains(kept) || owners.contains_key(kept);
+                    if entry.duplicates.iter().any(|(_, kept)| gone(kept)) {
+                        entry.hash.clear();
+                    }
+                }
+            }
+            // Saved last, so an interrupted run re-embeds rather than skips
+            manifest.save(path)?;
+        }
+        Ok(stats)
+    }
+}
+
+/// Move dropped chunks from their file's `chunks` (what the store holds) to
+/// its `duplicates`
+fn note_duplicates(manifest: &mut Manifest, owners: &HashMap<String, PathBuf>, dropped: Vec<(String, String)>) {
+    for (id, kept) in dropped {
+        if let Some(entry) = owners.get(&id).and_then(|file| manifest.files.get_mut(file)) {
+            entry.chunks.retain(|chunk| *chunk != id);
+            entry.duplicates.push((id, kept));
+        }
+    }
+}
+
+/// Index the current directory into a local JSON index
+pub async fn index_directory() -> Result<()> {
+    use crate::config_template::CLIENTS;
+    use crate::rag_template::{InMemoryVectorStore, Metric};
+
+    let model = CLIENTS.openai().embedding_model(rig::providers::openai::TEXT_EMBEDDING_3_SMALL);
+    let store = Arc::new(InMemoryVectorStore::load_or_new("index.json", Metric::Cosine)?);
+
+    let stats = Indexer::new(store.clone(), model)
+        .manifest("index.manifest.json")
+        .exclude("target/**")
+        .exclude("node_modules/**")
+        .dedup(0.97)
+        .on_progress(|p| tracing::debug!(done = p.files_done, total = p.files_total, file = %p.current.display(), "indexing"))
+        .run(Path::new("."))
+        .await?;
+    store.save("index.json")?;
+
+    tracing::info!(
+        files = stats.files,
+        unchanged = stats.unchanged,
+        removed = stats.removed,
+        chunks = stats.chunks,
+        duplicates = stats.duplicates,
+        skipped = stats.skipped,
+        failed = stats.failed.len(),
+        "indexed directory"
+    );
+    Ok(())
+}
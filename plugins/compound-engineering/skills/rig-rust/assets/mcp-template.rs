@@ -0,0 +1,215 @@
+//! MCP Templates for Rig
+//!
+//! Bridges Model Context Protocol servers and Rig tools using the `rmcp` SDK.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use rig::completion::{Prompt, ToolDefinition};
+use rig::tool::Tool;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ErrorData, ListToolsResult,
+    PaginatedRequestParam, RawContent, ServerCapabilities, ServerInfo,
+};
+use rmcp::service::{Peer, RequestContext, RoleClient, RoleServer, RunningService};
+use rmcp::transport::{SseClientTransport, TokioChildProcess};
+use rmcp::{ServerHandler, ServiceExt};
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::config_template::CLIENTS;
+use crate::registry_template::{RegistryError, ToolRegistry};
+use crate::tool_template::{Calculator, HttpClient, RedisTool};
+
+// =============================================================================
+// CLIENT: CONNECTING TO A SERVER
+// =============================================================================
+
+pub type McpClient = RunningService<RoleClient, ()>;
+
+/// Spawn a local server and talk to it over stdin/stdout
+pub async fn connect_stdio(program: &str, args: &[&str]) -> Result<McpClient> {
+    let mut command = Command::new(program);
+    command.args(args);
+    Ok(().serve(TokioChildProcess::new(command)?).await?)
+}
+
+/// Connect to a remote server over Server-Sent Events
+pub async fn connect_sse(url: &str) -> Result<McpClient> {
+    let transport = SseClientTransport::start(url.to_owned()).await?;
+    Ok(().serve(transport).await?)
+}
+
+// =============================================================================
+// CLIENT: MCP TOOLS AS RIG TOOLS
+// =============================================================================
+
+#[derive(Debug, thiserror::Error)]
+#[error("MCP error: {0}")]
+pub struct McpError(String);
+
+pub struct McpTool {
+    definition: ToolDefinition,
+    peer: Peer<RoleClient>,
+}
+
+/// Enumerate the server's tools and adapt each one
+pub async fn mcp_tools(client: &McpClient) -> Result<Vec<McpTool>> {
+    let tools = client.list_all_tools().await?;
+    Ok(tools
+        .into_iter()
+        .map(|tool| McpTool {
+            definition: ToolDefinition {
+                name: tool.name.to_string(),
+                description: tool.description.map(|d| d.to_string()).unwrap_or_default(),
+                parameters: Value::Object(tool.input_schema.as_ref().clone()),
+            },
+            peer: client.peer().clone(),
+        })
+        .collect())
+}
+
+impl Tool for McpTool {
+    // Registered under `name()`, the name the server reported
+    const NAME: &'static str = "mcp_tool";
+    type Args = Value;
+    type Output = String;
+    type Error = McpError;
+
+    fn name(&self) -> String {
+        self.definition.name.clone()
+    }
+
+    async fn definition(&self, _: String) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    async fn call(&self, args: Value) -> Result<String, McpError> {
+        let arguments = match args {
+            Value::Object(map) => Some(map),
+            Value::Null => None,
+            other => return Err(McpError(format!("Arguments must be an object, got {other}"))),
+        };
+
+        let result = self.peer
+            .call_tool(CallToolRequestParam {
+                name: self.definition.name.clone().into(),
+                arguments,
+            })
+            .await
+            .map_err(|e| McpError(e.to_string()))?;
+
+        let text = result.content.iter()
+            .filter_map(|content| match &content.raw {
+                RawContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if result.is_error.unwrap_or(false) {
+            Err(McpError(text))
+        } else {
+            Ok(text)
+        }
+    }
+}
+
+/// Agent using every tool exposed by a stdio MCP server
+pub async fn mcp_agent() -> Result<()> {
+    let mcp = connect_stdio("npx", &["-y", "@modelcontextprotocol/server-everything"]).await?;
+
+    let client = CLIENTS.openai();
+    let mut builder = client
+        .agent("gpt-4o")
+        .preamble("You are a helpful assistant.");
+    for tool in mcp_tools(&mcp).await? {
+        builder = builder.tool(tool);
+    }
+    let agent = builder.build();
+
+    let response = agent.prompt("Add 2 and 3 using the available tools.").await?;
+    println!("{}", response);
+
+    mcp.cancel().await?;
+    Ok(())
+}
+
+// =============================================================================
+// SERVER: EXPOSING RIG TOOLS OVER MCP
+// =============================================================================
+
+/// Serves every tool in a registry, so editors and other MCP hosts can call
+/// them without embedding Rig
+#[derive(Clone)]
+pub struct McpToolServer {
+    registry: ToolRegistry,
+}
+
+impl McpToolServer {
+    pub fn new(registry: ToolRegistry) -> Self {
+        Self { registry }
+    }
+}
+
+impl ServerHandler for McpToolServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        let tools = self.registry.definitions("").await
+            .into_iter()
+            .map(|def| {
+                let schema = match def.parameters {
+                    Value::Object(map) => map,
+                    _ => Default::default(),
+                };
+                rmcp::model::Tool::new(def.name, def.description, Arc::new(schema))
+            })
+            .collect();
+
+        Ok(ListToolsResult { tools, next_cursor: None })
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let args = Value::Object(request.arguments.unwrap_or_default());
+        match self.registry.call(&request.name, args).await {
+            Ok(output) => Ok(CallToolResult::success(vec![Content::text(output)])),
+            Err(RegistryError::UnknownTool(name)) => {
+                Err(ErrorData::invalid_params(format!("Unknown tool: {name}"), None))
+            }
+            // Tool failures are results, so the calling model can see and react to them
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(e.to_string())])),
+        }
+    }
+}
+
+/// Serve the template tools over stdio, e.g. `"command": "my-tools-server"` in an editor's MCP config
+pub async fn serve_template_tools() -> Result<()> {
+    let registry = ToolRegistry::new();
+    registry.register(Calculator::default());
+    registry.register(HttpClient::default());
+    if let Ok(url) = std::env::var("REDIS_URL") {
+        registry.register(RedisTool::new(&url, "mcp").await?);
+    }
+
+    McpToolServer::new(registry)
+        .serve(rmcp::transport::stdio())
+        .await?
+        .waiting()
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,1064 @@
+//! Tool Middleware Templates for Rig
+//!
+//! Wrappers that add cross-cutting behavior to any `Tool` without modifying it.
+//! Each wrapper is itself a `Tool` taking raw JSON arguments, so wrappers stack
+//! and can still be passed straight to `.tool(...)` on an agent builder.
+
+use std::collections::HashMap;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use lru::LruCache;
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+use tracing::Instrument;
+
+use crate::config_template::ProviderPolicy;
+
+// =============================================================================
+// SHARED
+// =============================================================================
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayerError<E> {
+    #[error("Invalid arguments: {0}")]
+    InvalidArgs(String),
+
+    // A source rather than transparent, so a wrapper further out can still
+    // find the `ToolTimeout` in the chain
+    #[error("{0}")]
+    Timeout(#[source] ToolTimeout),
+
+    #[error("The user declined to run {0}; ask how they would like to proceed")]
+    Declined(&'static str),
+
+    #[error(transparent)]
+    Tool(E),
+}
+
+/// Deserialize raw JSON into the wrapped tool's arguments and call it
+async fn call_inner<T: Tool>(tool: &T, args: Value) -> Result<T::Output, LayerError<T::Error>> {
+    let args = serde_json::from_value(args).map_err(|e| LayerError::InvalidArgs(e.to_string()))?;
+    tool.call(args).await.map_err(LayerError::Tool)
+}
+
+// =============================================================================
+// REDACTION
+// =============================================================================
+
+const DEFAULT_REDACTED_KEYS: &[&str] = &["password", "token", "api_key", "secret", "authorization"];
+
+/// Decides which argument values are replaced with `***` before they reach
+/// traces, logs, metrics, approval prompts, or anything else that records them.
+///
+/// Tools mark sensitive fields in their own parameters schema with the
+/// standard `"writeOnly": true` keyword, so every layer that redacts picks them
+/// up without per-tool configuration. Keys named like secrets (`password`,
+/// `token`, ...) are hidden at any depth regardless of the schema.
+#[derive(Clone)]
+pub struct Redactor {
+    keys: Vec<String>,
+    /// Argument paths to hide; `*` matches any object key or array index
+    paths: Vec<Vec<String>>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self {
+            keys: DEFAULT_REDACTED_KEYS.iter().map(|k| k.to_string()).collect(),
+            paths: Vec::new(),
+        }
+    }
+}
+
+impl Redactor {
+    /// Defaults plus every `writeOnly` field in the tool's declared schema
+    pub async fn for_tool<T: Tool>(tool: &T) -> Self {
+        Self::default().schema(&tool.definition(String::new()).await.parameters)
+    }
+
+    pub fn schema(mut self, schema: &Value) -> Self {
+        collect_write_only(schema, &mut Vec::new(), &mut self.paths);
+        self
+    }
+
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.keys.push(key.into());
+        self
+    }
+
+    /// Hide a field by JSON pointer, e.g. `/auth/token` or `/headers/*`
+    pub fn path(mut self, pointer: &str) -> Self {
+        self.paths.push(pointer.trim_start_matches('/').split('/').map(str::to_string).collect());
+        self
+    }
+
+    pub fn redact(&self, value: &Value) -> Value {
+        self.walk(value, &mut Vec::new())
+    }
+
+    fn walk(&self, value: &Value, path: &mut Vec<String>) -> Value {
+        let hidden = self.paths.iter().any(|pattern| {
+            pattern.len() == path.len() && pattern.iter().zip(path.iter()).all(|(p, s)| p == "*" || p == s)
+        });
+        if hidden {
+            return Value::from("***");
+        }
+
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| {
+                        if self.keys.iter().any(|key| k.eq_ignore_ascii_case(key)) {
+                            return (k.clone(), Value::from("***"));
+                        }
+                        path.push(k.clone());
+                        let redacted = self.walk(v, path);
+                        path.pop();
+                        (k.clone(), redacted)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(
+                items.iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        path.push(i.to_string());
+                        let redacted = self.walk(v, path);
+                        path.pop();
+                        redacted
+                    })
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+fn collect_write_only(schema: &Value, prefix: &mut Vec<String>, out: &mut Vec<Vec<String>>) {
+    if schema.get("writeOnly") == Some(&Value::Bool(true)) {
+        out.push(prefix.clone());
+        return;
+    }
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        for (name, property) in properties {
+            prefix.push(name.clone());
+            collect_write_only(property, prefix, out);
+            prefix.pop();
+        }
+    }
+    for nested in ["additionalProperties", "items"] {
+        if let Some(sub) = schema.get(nested).filter(|s| s.is_object()) {
+            prefix.push("*".to_string());
+            collect_write_only(sub, prefix, out);
+            prefix.pop();
+        }
+    }
+}
+
+// =============================================================================
+// LOGGING, METRICS, TRACING
+// =============================================================================
+
+pub struct ToolCallRecord {
+    pub tool: &'static str,
+    /// Redacted arguments
+    pub args: Value,
+    /// Size of the arguments as sent by the model
+    pub args_bytes: usize,
+    pub duration: Duration,
+    pub error: Option<String>,
+}
+
+impl ToolCallRecord {
+    pub fn success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, record: &ToolCallRecord);
+}
+
+/// Sink backed by the `metrics` crate facade (Prometheus, StatsD, ...)
+pub struct MetricsCrateSink;
+
+impl MetricsSink for MetricsCrateSink {
+    fn record(&self, record: &ToolCallRecord) {
+        let outcome = if record.success() { "ok" } else { "error" };
+        metrics::counter!("tool_calls_total", "tool" => record.tool, "outcome" => outcome).increment(1);
+        metrics::histogram!("tool_call_duration_seconds", "tool" => record.tool)
+            .record(record.duration.as_secs_f64());
+    }
+}
+
+/// Fan out to several sinks
+impl MetricsSink for Vec<Arc<dyn MetricsSink>> {
+    fn record(&self, record: &ToolCallRecord) {
+        for sink in self {
+            sink.record(record);
+        }
+    }
+}
+
+pub struct ToolMiddleware<T> {
+    inner: T,
+    sink: Option<Arc<dyn MetricsSink>>,
+    extra_redactions: Redactor,
+    redactor: OnceCell<Redactor>,
+}
+
+impl<T: Tool> ToolMiddleware<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            sink: None,
+            extra_redactions: Redactor::default(),
+            redactor: OnceCell::new(),
+        }
+    }
+
+    pub fn sink(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Hide a key the tool's schema doesn't mark as `writeOnly`
+    pub fn redact_key(mut self, key: impl Into<String>) -> Self {
+        self.extra_redactions = self.extra_redactions.key(key);
+        self
+    }
+
+    async fn redactor(&self) -> &Redactor {
+        self.redactor
+            .get_or_init(|| async {
+                let schema = self.inner.definition(String::new()).await.parameters;
+                self.extra_redactions.clone().schema(&schema)
+            })
+            .await
+    }
+}
+
+impl<T: Tool> Tool for ToolMiddleware<T> {
+    const NAME: &'static str = T::NAME;
+    type Args = Value;
+    type Output = T::Output;
+    type Error = LayerError<T::Error>;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Value) -> Result<T::Output, Self::Error> {
+        let redacted = self.redactor().await.redact(&args);
+        let args_bytes = args.to_string().len();
+        let span = tracing::info_span!(
+            "tool_call",
+            tool = T::NAME,
+            args = %redacted,
+            latency_ms = tracing::field::Empty,
+            outcome = tracing::field::Empty,
+        );
+        let recorded = span.clone();
+
+        async move {
+            let start = Instant::now();
+            let result = call_inner(&self.inner, args).await;
+            let duration = start.elapsed();
+            recorded.record("latency_ms", duration.as_millis() as u64);
+            recorded.record("outcome", if result.is_ok() { "ok" } else { "error" });
+
+            match &result {
+                Ok(_) => tracing::info!(duration_ms = duration.as_millis() as u64, "tool call succeeded"),
+                Err(e) => tracing::warn!(duration_ms = duration.as_millis() as u64, error = %e, "tool call failed"),
+            }
+            if let Some(sink) = &self.sink {
+                sink.record(&ToolCallRecord {
+                    tool: T::NAME,
+                    args: redacted,
+                    args_bytes,
+                    duration,
+                    error: result.as_ref().err().map(|e| e.to_string()),
+                });
+            }
+
+            result
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+// =============================================================================
+// RESULT CACHING
+// =============================================================================
+
+/// Sort object keys recursively so `{"a":1,"b":2}` and `{"b":2,"a":1}` share a cache entry
+pub fn canonicalize(value: &Value) -> String {
+    fn sorted(value: &Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                Value::Object(entries.into_iter().map(|(k, v)| (k.clone(), sorted(v))).collect())
+            }
+            Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+            other => other.clone(),
+        }
+    }
+    sorted(value).to_string()
+}
+
+/// Declares which calls are safe reads whose result can be reused. Narrower
+/// than `Idempotency`: a repeated PUT leaves the same state, but serving it
+/// from cache skips a write that a PUT in between may have undone.
+pub trait Cacheable: Tool {
+    fn is_cacheable(&self, args: &Self::Args) -> bool;
+}
+
+/// Check cacheability from raw JSON; arguments that don't parse are never cached
+fn cacheable_json<T: Cacheable>(tool: &T, args: &Value) -> bool {
+    serde_json::from_value::<T::Args>(args.clone()).is_ok_and(|args| tool.is_cacheable(&args))
+}
+
+impl<T: Cacheable> Cacheable for ToolMiddleware<T> {
+    fn is_cacheable(&self, args: &Value) -> bool {
+        cacheable_json(&self.inner, args)
+    }
+}
+
+/// Caches successful results of cacheable calls. Everything else (writes,
+/// reads of state other calls change) always runs and is never stored.
+pub struct CachedTool<T: Tool> {
+    inner: T,
+    ttl: Duration,
+    entries: Mutex<LruCache<String, (Instant, T::Output)>>,
+}
+
+impl<T: Tool> CachedTool<T>
+where
+    T::Output: Clone + Send,
+{
+    pub fn new(inner: T, capacity: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    fn lookup(&self, key: &str) -> Option<T::Output> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((stored, output)) if stored.elapsed() < self.ttl => Some(output.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+impl<T: Cacheable> Tool for CachedTool<T>
+where
+    T::Output: Clone + Send,
+{
+    const NAME: &'static str = T::NAME;
+    type Args = Value;
+    type Output = T::Output;
+    type Error = LayerError<T::Error>;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Value) -> Result<T::Output, Self::Error> {
+        if !cacheable_json(&self.inner, &args) {
+            return call_inner(&self.inner, args).await;
+        }
+
+        let key = format!("{}:{}", T::NAME, canonicalize(&args));
+        if let Some(hit) = self.lookup(&key) {
+            tracing::debug!(tool = T::NAME, "tool cache hit");
+            return Ok(hit);
+        }
+
+        // Only successful results are cached; errors always reach the model fresh
+        let output = call_inner(&self.inner, args).await?;
+        self.entries.lock().unwrap().put(key, (Instant::now(), output.clone()));
+        Ok(output)
+    }
+}
+
+// =============================================================================
+// RETRY (IDEMPOTENT TOOLS ONLY)
+// =============================================================================
+
+/// Declares whether repeating a call is safe. Retrying a non-idempotent call
+/// (sending an email, POSTing an order) can duplicate its side effects.
+pub trait Idempotency: Tool {
+    fn is_idempotent(&self, args: &Self::Args) -> bool;
+}
+
+/// Check idempotency from raw JSON; arguments that don't parse are never retried
+fn idempotent_json<T: Idempotency>(tool: &T, args: &Value) -> bool {
+    serde_json::from_value::<T::Args>(args.clone()).is_ok_and(|args| tool.is_idempotent(&args))
+}
+
+impl<T: Idempotency> Idempotency for ToolMiddleware<T> {
+    fn is_idempotent(&self, args: &Value) -> bool {
+        idempotent_json(&self.inner, args)
+    }
+}
+
+impl<T: Idempotency + Cacheable> Idempotency for CachedTool<T>
+where
+    T::Output: Clone + Send,
+{
+    fn is_idempotent(&self, args: &Value) -> bool {
+        idempotent_json(&self.inner, args)
+    }
+}
+
+pub struct RetryTool<T: Tool> {
+    inner: T,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    is_transient: Box<dyn Fn(&T::Error) -> bool + Send + Sync>,
+}
+
+impl<T: Idempotency> RetryTool<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            max_retries: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(30),
+            is_transient: Box::new(|e| ProviderPolicy::is_transient(e)),
+        }
+    }
+
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Cap on the doubling delay between attempts
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Only errors matching the predicate are retried (default: timeouts and
+    /// the transient failures `ProviderPolicy::is_transient` recognizes, so
+    /// bad arguments and 4xx responses fail fast)
+    pub fn retry_if(mut self, predicate: impl Fn(&T::Error) -> bool + Send + Sync + 'static) -> Self {
+        self.is_transient = Box::new(predicate);
+        self
+    }
+}
+
+impl<T: Idempotency> Tool for RetryTool<T> {
+    const NAME: &'static str = T::NAME;
+    type Args = Value;
+    type Output = T::Output;
+    type Error = LayerError<T::Error>;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Value) -> Result<T::Output, Self::Error> {
+        if !idempotent_json(&self.inner, &args) {
+            return call_inner(&self.inner, args).await;
+        }
+
+        let mut attempt = 0;
+        loop {
+            match call_inner(&self.inner, args.clone()).await {
+                Err(LayerError::Tool(e)) if attempt < self.max_retries && (self.is_transient)(&e) => {
+                    attempt += 1;
+                    tracing::warn!(tool = T::NAME, attempt, error = %e, "retrying tool call");
+                    let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt - 1)).min(self.max_delay);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// =============================================================================
+// SCHEMA VALIDATION
+// =============================================================================
+
+/// Checks arguments against the tool's declared `parameters` schema before
+/// deserializing, so the model gets every problem listed by path and can fix
+/// its call instead of seeing a bare serde error.
+pub struct ValidatedTool<T> {
+    inner: T,
+    validator: OnceCell<Result<jsonschema::Validator, String>>,
+}
+
+impl<T: Tool> ValidatedTool<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner, validator: OnceCell::new() }
+    }
+
+    async fn validate(&self, args: &Value) -> Result<(), String> {
+        let validator = self.validator
+            .get_or_init(|| async {
+                let schema = self.inner.definition(String::new()).await.parameters;
+                jsonschema::validator_for(&schema)
+                    .map_err(|e| format!("Tool {} declares an invalid schema: {e}", T::NAME))
+            })
+            .await
+            .as_ref()
+            .map_err(Clone::clone)?;
+
+        let problems: Vec<String> = validator
+            .iter_errors(args)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                format!("- {}: {e}", if path.is_empty() { "(root)" } else { &path })
+            })
+            .collect();
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "arguments for `{}` do not match its schema:\n{}\nFix these fields and call the tool again.",
+                T::NAME,
+                problems.join("\n")
+            ))
+        }
+    }
+}
+
+impl<T: Idempotency> Idempotency for ValidatedTool<T> {
+    fn is_idempotent(&self, args: &Value) -> bool {
+        idempotent_json(&self.inner, args)
+    }
+}
+
+impl<T: Cacheable> Cacheable for ValidatedTool<T> {
+    fn is_cacheable(&self, args: &Value) -> bool {
+        cacheable_json(&self.inner, args)
+    }
+}
+
+impl<T: Tool> Tool for ValidatedTool<T> {
+    const NAME: &'static str = T::NAME;
+    type Args = Value;
+    type Output = T::Output;
+    type Error = LayerError<T::Error>;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Value) -> Result<T::Output, Self::Error> {
+        self.validate(&args).await.map_err(LayerError::InvalidArgs)?;
+        call_inner(&self.inner, args).await
+    }
+}
+
+// =============================================================================
+// TIMEOUTS
+// =============================================================================
+
+#[derive(Debug, thiserror::Error)]
+#[error("Tool {tool} timed out after {after:?}")]
+pub struct ToolTimeout {
+    pub tool: &'static str,
+    pub after: Duration,
+}
+
+pub struct TimeoutTool<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T: Tool> TimeoutTool<T> {
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+impl<T: Idempotency> Idempotency for TimeoutTool<T> {
+    fn is_idempotent(&self, args: &Value) -> bool {
+        idempotent_json(&self.inner, args)
+    }
+}
+
+impl<T: Cacheable> Cacheable for TimeoutTool<T> {
+    fn is_cacheable(&self, args: &Value) -> bool {
+        cacheable_json(&self.inner, args)
+    }
+}
+
+impl<T: Tool> Tool for TimeoutTool<T> {
+    const NAME: &'static str = T::NAME;
+    type Args = Value;
+    type Output = T::Output;
+    type Error = LayerError<T::Error>;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Value) -> Result<T::Output, Self::Error> {
+        tokio::time::timeout(self.timeout, call_inner(&self.inner, args))
+            .await
+            .map_err(|_| LayerError::Timeout(ToolTimeout { tool: T::NAME, after: self.timeout }))?
+    }
+}
+
+// =============================================================================
+// CONFIRMATION FOR DESTRUCTIVE CALLS
+// =============================================================================
+
+/// Declares which calls need a human's approval before they run
+pub trait Confirmation: Tool {
+    fn requires_confirmation(&self, args: &Self::Args) -> bool;
+}
+
+fn confirmation_json<T: Confirmation>(tool: &T, args: &Value) -> bool {
+    // Unparseable arguments fail in the tool anyway; asking first is the safe default
+    serde_json::from_value::<T::Args>(args.clone()).map_or(true, |args| tool.requires_confirmation(&args))
+}
+
+pub struct ApprovalRequest {
+    pub tool: &'static str,
+    /// Arguments with secrets redacted
+    pub args: Value,
+}
+
+pub trait Approver: Send + Sync {
+    fn approve<'a>(&'a self, request: &'a ApprovalRequest) -> BoxFuture<'a, bool>;
+}
+
+/// Asks on stdin; anything other than `y`/`yes` declines
+pub struct TerminalApprover;
+
+impl Approver for TerminalApprover {
+    fn approve<'a>(&'a self, request: &'a ApprovalRequest) -> BoxFuture<'a, bool> {
+        let prompt = format!(
+            "Agent wants to call `{}` with:\n{}\nAllow? [y/N] ",
+            request.tool,
+            serde_json::to_string_pretty(&request.args).unwrap_or_default()
+        );
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                use std::io::Write;
+                print!("{}", prompt);
+                std::io::stdout().flush().ok();
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer).ok();
+                matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+            })
+            .await
+            .unwrap_or(false)
+        })
+    }
+}
+
+pub struct ConfirmTool<T> {
+    inner: T,
+    approver: Arc<dyn Approver>,
+    redactor: OnceCell<Redactor>,
+}
+
+impl<T: Confirmation> ConfirmTool<T> {
+    pub fn new(inner: T, approver: Arc<dyn Approver>) -> Self {
+        Self { inner, approver, redactor: OnceCell::new() }
+    }
+}
+
+impl<T: Confirmation> Confirmation for ToolMiddleware<T> {
+    fn requires_confirmation(&self, args: &Value) -> bool {
+        confirmation_json(&self.inner, args)
+    }
+}
+
+impl<T: Cacheable + Confirmation> Confirmation for CachedTool<T>
+where
+    T::Output: Clone + Send,
+{
+    fn requires_confirmation(&self, args: &Value) -> bool {
+        confirmation_json(&self.inner, args)
+    }
+}
+
+impl<T: Idempotency + Confirmation> Confirmation for RetryTool<T> {
+    fn requires_confirmation(&self, args: &Value) -> bool {
+        confirmation_json(&self.inner, args)
+    }
+}
+
+impl<T: Confirmation> Confirmation for ValidatedTool<T> {
+    fn requires_confirmation(&self, args: &Value) -> bool {
+        confirmation_json(&self.inner, args)
+    }
+}
+
+impl<T: Confirmation> Confirmation for TimeoutTool<T> {
+    fn requires_confirmation(&self, args: &Value) -> bool {
+        confirmation_json(&self.inner, args)
+    }
+}
+
+impl<T: Idempotency + Confirmation> Idempotency for ConfirmTool<T> {
+    fn is_idempotent(&self, args: &Value) -> bool {
+        idempotent_json(&self.inner, args)
+    }
+}
+
+impl<T: Confirmation> Tool for ConfirmTool<T> {
+    const NAME: &'static str = T::NAME;
+    type Args = Value;
+    type Output = T::Output;
+    type Error = LayerError<T::Error>;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Value) -> Result<T::Output, Self::Error> {
+        if confirmation_json(&self.inner, &args) {
+            let redactor = self.redactor.get_or_init(|| Redactor::for_tool(&self.inner)).await;
+            let request = ApprovalRequest { tool: T::NAME, args: redactor.redact(&args) };
+            if !self.approver.approve(&request).await {
+                return Err(LayerError::Declined(T::NAME));
+            }
+        }
+        call_inner(&self.inner, args).await
+    }
+}
+
+// =============================================================================
+// COMPOSITION
+// =============================================================================
+
+#[derive(Debug, thiserror::Error)]
+pub enum PipeError<A, B> {
+    #[error(transparent)]
+    First(A),
+
+    #[error(transparent)]
+    Second(LayerError<B>),
+}
+
+/// Runs `first`, maps its output into `second`'s arguments, and exposes the
+/// pair to the model as one tool so it doesn't have to chain the calls itself
+pub struct Pipe<A, B, F> {
+    name: String,
+    description: String,
+    first: A,
+    second: B,
+    map: F,
+}
+
+impl<A, B, F> Pipe<A, B, F>
+where
+    A: Tool,
+    B: Tool,
+    F: Fn(A::Output) -> Value + Send + Sync,
+{
+    pub fn new(name: impl Into<String>, description: impl Into<String>, first: A, second: B, map: F) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            first,
+            second,
+            map,
+        }
+    }
+}
+
+impl<A, B, F> Tool for Pipe<A, B, F>
+where
+    A: Tool,
+    B: Tool,
+    F: Fn(A::Output) -> Value + Send + Sync,
+{
+    // Registered under `name()`, which returns the composite's configured name
+    const NAME: &'static str = "pipe";
+    type Args = A::Args;
+    type Output = B::Output;
+    type Error = PipeError<A::Error, B::Error>;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: self.first.definition(prompt).await.parameters,
+        }
+    }
+
+    async fn call(&self, args: A::Args) -> Result<B::Output, Self::Error> {
+        let intermediate = self.first.call(args).await.map_err(PipeError::First)?;
+        call_inner(&self.second, (self.map)(intermediate)).await.map_err(PipeError::Second)
+    }
+}
+
+// =============================================================================
+// RATE LIMITING
+// =============================================================================
+
+pub type CallLimiter = governor::DefaultDirectRateLimiter;
+
+/// Waits for a slot before each call so agents stay inside third-party quotas.
+/// Wrap in `TimeoutTool` to bound how long a call may queue.
+pub struct ThrottledTool<T> {
+    inner: T,
+    limiter: Arc<CallLimiter>,
+}
+
+impl<T: Tool> ThrottledTool<T> {
+    pub fn per_minute(inner: T, calls: NonZeroU32) -> Self {
+        Self::shared(inner, Arc::new(governor::RateLimiter::direct(governor::Quota::per_minute(calls))))
+    }
+
+    /// Share one quota between tools that hit the same API (e.g. several GitHub tools)
+    pub fn shared(inner: T, limiter: Arc<CallLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl<T: Idempotency> Idempotency for ThrottledTool<T> {
+    fn is_idempotent(&self, args: &Value) -> bool {
+        idempotent_json(&self.inner, args)
+    }
+}
+
+impl<T: Cacheable> Cacheable for ThrottledTool<T> {
+    fn is_cacheable(&self, args: &Value) -> bool {
+        cacheable_json(&self.inner, args)
+    }
+}
+
+impl<T: Confirmation> Confirmation for ThrottledTool<T> {
+    fn requires_confirmation(&self, args: &Value) -> bool {
+        confirmation_json(&self.inner, args)
+    }
+}
+
+impl<T: Tool> Tool for ThrottledTool<T> {
+    const NAME: &'static str = T::NAME;
+    type Args = Value;
+    type Output = T::Output;
+    type Error = LayerError<T::Error>;
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Value) -> Result<T::Output, Self::Error> {
+        if self.limiter.check().is_err() {
+            tracing::debug!(tool = T::NAME, "rate limited, waiting for a slot");
+            self.limiter.until_ready().await;
+        }
+        call_inner(&self.inner, args).await
+    }
+}
+
+// =============================================================================
+// USAGE ANALYTICS
+// =============================================================================
+
+#[derive(Default)]
+struct ToolSamples {
+    errors: usize,
+    latencies: Vec<Duration>,
+    arg_sizes: Vec<usize>,
+}
+
+/// Session-wide sink: attach to every `ToolMiddleware` and call `report()`
+/// to see which tools earn their place in a production agent
+#[derive(Default)]
+pub struct UsageAnalytics {
+    samples: Mutex<HashMap<&'static str, ToolSamples>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolUsage {
+    pub tool: &'static str,
+    pub calls: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+    pub latency_p50_ms: u64,
+    pub latency_p95_ms: u64,
+    pub latency_max_ms: u64,
+    pub args_bytes_p50: usize,
+    pub args_bytes_p95: usize,
+    pub args_bytes_max: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    /// Most-called tools first
+    pub tools: Vec<ToolUsage>,
+}
+
+fn percentile<T: Copy + Ord + Default>(sorted: &[T], p: f64) -> T {
+    if sorted.is_empty() {
+        return T::default();
+    }
+    let rank = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[rank]
+}
+
+impl MetricsSink for UsageAnalytics {
+    fn record(&self, record: &ToolCallRecord) {
+        let mut samples = self.samples.lock().unwrap();
+        let entry = samples.entry(record.tool).or_default();
+        entry.latencies.push(record.duration);
+        entry.arg_sizes.push(record.args_bytes);
+        if !record.success() {
+            entry.errors += 1;
+        }
+    }
+}
+
+impl UsageAnalytics {
+    pub fn report(&self) -> UsageReport {
+        let samples = self.samples.lock().unwrap();
+        let mut tools: Vec<ToolUsage> = samples.iter()
+            .map(|(&tool, s)| {
+                let mut latencies = s.latencies.clone();
+                latencies.sort();
+                let mut sizes = s.arg_sizes.clone();
+                sizes.sort();
+                let ms = |d: Duration| d.as_millis() as u64;
+                ToolUsage {
+                    tool,
+                    calls: latencies.len(),
+                    errors: s.errors,
+                    error_rate: s.errors as f64 / latencies.len().max(1) as f64,
+                    latency_p50_ms: ms(percentile(&latencies, 0.50)),
+                    latency_p95_ms: ms(percentile(&latencies, 0.95)),
+                    latency_max_ms: ms(latencies.last().copied().unwrap_or_default()),
+                    args_bytes_p50: percentile(&sizes, 0.50),
+                    args_bytes_p95: percentile(&sizes, 0.95),
+                    args_bytes_max: sizes.last().copied().unwrap_or_default(),
+                }
+            })
+            .collect();
+        tools.sort_by(|a, b| b.calls.cmp(&a.calls).then(a.tool.cmp(b.tool)));
+        UsageReport { tools }
+    }
+
+    pub fn reset(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+}
+
+impl std::fmt::Display for UsageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<24} {:>6} {:>7} {:>8} {:>8} {:>10}", "tool", "calls", "errors", "p50 ms", "p95 ms", "p95 bytes")?;
+        for t in &self.tools {
+            writeln!(
+                f,
+                "{:<24} {:>6} {:>6.1}% {:>8} {:>8} {:>10}",
+                t.tool, t.calls, t.error_rate * 100.0, t.latency_p50_ms, t.latency_p95_ms, t.args_bytes_p95
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// =============================================================================
+// USAGE
+// =============================================================================
+
+use anyhow::Result;
+use rig::completion::Prompt;
+
+use crate::config_template::CLIENTS;
+use crate::tool_template::{Calculator, HttpClient, HttpOutput};
+
+/// Agent whose tool calls are traced and counted
+pub async fn observed_tools_agent() -> Result<()> {
+    let client = CLIENTS.openai();
+    let analytics = Arc::new(UsageAnalytics::default());
+    let sink: Arc<dyn MetricsSink> = Arc::new(vec![
+        Arc::new(MetricsCrateSink) as Arc<dyn MetricsSink>,
+        analytics.clone(),
+    ]);
+
+    // Repeated GETs and HEADs within five minutes are served from the cache;
+    // every other method always goes out
+    let http = CachedTool::new(HttpClient::default(), NonZeroUsize::new(256).unwrap(), Duration::from_secs(300));
+    // Each attempt gets 10s; GETs that time out are retried with backoff, POSTs run once
+    let http = RetryTool::new(TimeoutTool::new(http, Duration::from_secs(10))).max_retries(2);
+    // POST/PUT/PATCH/DELETE wait for a y/N on the terminal
+    let http = ConfirmTool::new(http, Arc::new(TerminalApprover));
+
+    let agent = client
+        .agent("gpt-4o")
+        .preamble("You are a helpful assistant.")
+        .tool(ToolMiddleware::new(ValidatedTool::new(Calculator::default())).sink(sink.clone()))
+        .tool(ToolMiddleware::new(http).sink(sink))
+        .build();
+
+    let response = agent.prompt("What is 42 * 17?").await?;
+    println!("{}", response);
+    for t in analytics.report().tools {
+        tracing::info!(
+            tool = t.tool,
+            calls = t.calls,
+            error_rate = t.error_rate,
+            latency_p50_ms = t.latency_p50_ms,
+            latency_p95_ms = t.latency_p95_ms,
+            args_bytes_p95 = t.args_bytes_p95,
+            "tool usage"
+        );
+    }
+
+    Ok(())
+}
+
+/// Two tools exposed as one composite tool
+pub async fn piped_tools_agent() -> Result<()> {
+    let client = CLIENTS.openai();
+
+    let fetch_and_evaluate = Pipe::new(
+        "fetch_and_evaluate",
+        "Fetch a URL whose body is a math expression and return its value",
+        HttpClient::default(),
+        Calculator::default(),
+        |response: HttpOutput| serde_json::json!({ "expression": response.body.trim() }),
+    );
+
+    let agent = client
+        .agent("gpt-4o")
+        .preamble("You are a helpful assistant.")
+        .tool(fetch_and_evaluate)
+        .build();
+
+    let response = agent.prompt("Evaluate the expression at https://example.com/expr.txt").await?;
+    println!("{}", response);
+
+    Ok(())
+}
@@ -0,0 +1,307 @@
+//! Multi-Agent Templates for Rig
+//!
+//! A supervisor that splits a goal into subtasks, workers that each run one
+//! agent, and observers that log or display what's happening, all talking
+//! over a typed event bus instead of awaiting each other directly. The
+//! supervisor doesn't hold worker handles and observers don't hook into
+//! either side: each just publishes and subscribes, so adding a UI, an audit
+//! log, or another worker doesn't touch the existing code.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::config_template::{BoxedAgent, DynAgent, CLIENTS};
+
+// =============================================================================
+// EVENT BUS
+// =============================================================================
+
+// Usage:
+//     let bus = EventBus::<AgentEvent>::new(256);
+//     let mut events = bus.subscribe();          // subscribe before anything is published
+//     bus.publish("supervisor", AgentEvent::Shutdown);
+//     while let Some(envelope) = events.recv().await { ... }
+//
+// Every subscriber sees every event. A subscriber that falls more than
+// `capacity` events behind skips the oldest ones (counted in `missed`) rather
+// than slowing the publishers down, so observers can never stall the agents.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Envelope<E> {
+    /// Increases by one per event on this bus
+    pub seq: u64,
+    pub at: SystemTime,
+    /// Who published it: "supervisor", a worker's name, ...
+    pub source: String,
+    pub event: E,
+}
+
+pub struct EventBus<E> {
+    sender: broadcast::Sender<Envelope<E>>,
+    seq: Arc<AtomicU64>,
+}
+
+impl<E> Clone for EventBus<E> {
+    fn clone(&self) -> Self {
+        Self { sender: self.sender.clone(), seq: self.seq.clone() }
+    }
+}
+
+impl<E: Clone + Send + 'static> EventBus<E> {
+    /// `capacity` events are buffered per subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender, seq: Arc::new(AtomicU64::new(0)) }
+    }
+
+    /// Returns the event's sequence number; publishing with no subscribers is not an error
+    pub fn publish(&self, source: impl Into<String>, event: E) -> u64 {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let _ = self.sender.send(Envelope { seq, at: SystemTime::now(), source: source.into(), event });
+        seq
+    }
+
+    /// Sees events published from now on
+    pub fn subscribe(&self) -> Subscription<E> {
+        Subscription { receiver: self.sender.subscribe(), missed: 0 }
+    }
+
+    /// Run `f` on every event in a background task, for logging and UI
+    /// updates; ends when every publisher has dropped its bus
+    pub fn observe(&self, mut f: impl FnMut(&Envelope<E>) + Send + 'static) -> JoinHandle<u64> {
+        let mut events = self.subscribe();
+        tokio::spawn(async move {
+            while let Some(envelope) = events.recv().await {
+                f(&envelope);
+            }
+            events.missed
+        })
+    }
+}
+
+pub struct Subscription<E> {
+    receiver: broadcast::Receiver<Envelope<E>>,
+    /// Events skipped because this subscriber fell behind
+    pub missed: u64,
+}
+
+impl<E: Clone> Subscription<E> {
+    /// The next event, or `None` once every publisher is gone
+    pub async fn recv(&mut self) -> Option<Envelope<E>> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(envelope) => return Some(envelope),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "event subscriber fell behind");
+                    self.missed += skipped;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+// =============================================================================
+// AGENT EVENTS
+// =============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    GoalReceived { goal: String },
+    /// The assignment's `seq` is the task id the other task events carry; it's
+    /// unique on the bus, so results from earlier runs can't be mistaken for this one's
+    TaskAssigned { worker: String, task: String },
+    TaskStarted { task_id: u64, worker: String },
+    TaskFinished { task_id: u64, worker: String, output: String, elapsed_ms: u64 },
+    TaskFailed { task_id: u64, worker: String, error: String },
+    GoalCompleted { answer: String },
+    /// Workers exit when they see this
+    Shutdown,
+}
+
+// =============================================================================
+// WORKERS
+// =============================================================================
+
+pub struct AgentWorker {
+    name: String,
+    agent: Arc<dyn DynAgent>,
+}
+
+impl AgentWorker {
+    pub fn new(name: impl Into<String>, agent: Box<dyn DynAgent>) -> Self {
+        Self { name: name.into(), agent: agent.into() }
+    }
+
+    /// Handle tasks assigned to this worker, one at a time, until `Shutdown`.
+    /// Subscribes before returning, so no assignment published afterwards is missed
+    pub fn spawn(self, bus: &EventBus<AgentEvent>) -> JoinHandle<()> {
+        let (bus, mut events) = (bus.clone(), bus.subscribe());
+        tokio::spawn(async move {
+            while let Some(envelope) = events.recv().await {
+                let (task_id, task) = match envelope.event {
+                    AgentEvent::TaskAssigned { worker, task } if worker == self.name => (envelope.seq, task),
+                    AgentEvent::Shutdown => break,
+                    _ => continue,
+                };
+
+                bus.publish(&self.name, AgentEvent::TaskStarted { task_id, worker: self.name.clone() });
+                let started = Instant::now();
+                let event = match self.agent.prompt(&task).await {
+                    Ok(output) => AgentEvent::TaskFinished {
+                        task_id,
+                        worker: self.name.clone(),
+                        output,
+                        elapsed_ms: started.elapsed().as_millis() as u64,
+                    },
+                    Err(e) => AgentEvent::TaskFailed { task_id, worker: self.name.clone(), error: e.to_string() },
+                };
+                bus.publish(&self.name, event);
+            }
+        })
+    }
+}
+
+// =============================================================================
+// SUPERVISOR
+// =============================================================================
+
+const PLAN_INSTRUCTIONS: &str = "Split the goal into 2-6 independent research subtasks that can \
+be done in parallel. Reply with one subtask per line and nothing else.";
+
+const SYNTHESIZE_INSTRUCTIONS: &str = "Combine the findings below into one answer to the goal. \
+Note which subtasks failed, if any, and what that leaves uncertain.";
+
+pub struct Supervisor {
+    planner: Arc<dyn DynAgent>,
+    workers: Vec<String>,
+    timeout: Duration,
+}
+
+impl Supervisor {
+    /// `workers` are the names of the `AgentWorker`s on the bus
+    pub fn new(planner: Box<dyn DynAgent>, workers: &[&str]) -> Self {
+        Self {
+            planner: planner.into(),
+            workers: workers.iter().map(|w| w.to_string()).collect(),
+            timeout: Duration::from_secs(300),
+        }
+    }
+
+    /// How long to wait for all subtasks before synthesizing what came back
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Plan, assign subtasks round-robin, collect results from the bus, and
+    /// synthesize an answer
+    pub async fn run(&self, bus: &EventBus<AgentEvent>, goal: &str) -> Result<String> {
+        anyhow::ensure!(!self.workers.is_empty(), "supervisor has no workers");
+        let mut events = bus.subscribe();
+        bus.publish("supervisor", AgentEvent::GoalReceived { goal: goal.to_string() });
+
+        let plan = self.planner.prompt(&format!("{PLAN_INSTRUCTIONS}\n\nGoal: {goal}")).await?;
+        let tasks: Vec<&str> = plan.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        anyhow::ensure!(!tasks.is_empty(), "planner returned no subtasks");
+
+        let mut pending: HashMap<u64, &str> = HashMap::new();
+        for (i, task) in tasks.iter().enumerate() {
+            let worker = self.workers[i % self.workers.len()].clone();
+            let task_id = bus.publish("supervisor", AgentEvent::TaskAssigned { worker, task: task.to_string() });
+            pending.insert(task_id, task);
+        }
+
+        let mut findings = Vec::new();
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        while !pending.is_empty() {
+            let envelope = match tokio::time::timeout_at(deadline, events.recv()).await {
+                Ok(Some(envelope)) => envelope,
+                Ok(None) => anyhow::bail!("event bus closed"),
+                Err(_) => break,
+            };
+            match envelope.event {
+                AgentEvent::TaskFinished { task_id, output, .. } => {
+                    if let Some(task) = pending.remove(&task_id) {
+                        findings.push(format!("Subtask: {task}\nFindings: {output}"));
+                    }
+                }
+                AgentEvent::TaskFailed { task_id, error, .. } => {
+                    if let Some(task) = pending.remove(&task_id) {
+                        findings.push(format!("Subtask: {task}\nFAILED: {error}"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for task in pending.values() {
+            findings.push(format!("Subtask: {task}\nFAILED: no result within {:?}", self.timeout));
+        }
+        if events.missed > 0 {
+            tracing::warn!(missed = events.missed, "supervisor missed events; raise the bus capacity");
+        }
+
+        let prompt = format!("{SYNTHESIZE_INSTRUCTIONS}\n\nGoal: {goal}\n\n{}", findings.join("\n\n"));
+        let answer = self.planner.prompt(&prompt).await?;
+        bus.publish("supervisor", AgentEvent::GoalCompleted { answer: answer.clone() });
+        Ok(answer)
+    }
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// Two researchers and a supervisor, with a log observer and a JSONL audit
+/// trail that neither side knows about
+pub async fn research_team() -> Result<()> {
+    let client = CLIENTS.openai();
+    let bus = EventBus::<AgentEvent>::new(256);
+
+    let log = bus.observe(|envelope| match &envelope.event {
+        AgentEvent::TaskAssigned { worker, task } => tracing::info!(task_id = envelope.seq, worker = %worker, task = %task, "assigned"),
+        AgentEvent::TaskFinished { task_id, worker, elapsed_ms, .. } => tracing::info!(task_id, worker = %worker, elapsed_ms, "finished"),
+        AgentEvent::TaskFailed { task_id, worker, error } => tracing::warn!(task_id, worker = %worker, error = %error, "failed"),
+        _ => {}
+    });
+    let mut audit = std::fs::OpenOptions::new().create(true).append(true).open("events.jsonl")?;
+    let trail = bus.observe(move |envelope| {
+        if let Ok(line) = serde_json::to_string(envelope) {
+            let _ = writeln!(audit, "{line}");
+        }
+    });
+
+    let workers: Vec<JoinHandle<()>> = ["researcher-1", "researcher-2"]
+        .into_iter()
+        .map(|name| {
+            let agent = client.agent("gpt-4o-mini").preamble("You research one narrow question and report concise findings.").build();
+            AgentWorker::new(name, Box::new(BoxedAgent(agent))).spawn(&bus)
+        })
+        .collect();
+
+    let planner = client.agent("gpt-4o").preamble("You coordinate a small research team.").build();
+    let supervisor = Supervisor::new(Box::new(BoxedAgent(planner)), &["researcher-1", "researcher-2"]);
+    let answer = supervisor.run(&bus, "How do Rust async runtimes differ in scheduling strategy?").await?;
+    println!("{answer}");
+
+    bus.publish("supervisor", AgentEvent::Shutdown);
+    for worker in workers {
+        worker.await?;
+    }
+    // Observers end once the last publisher is gone
+    drop(bus);
+    let (missed_log, missed_trail) = (log.await?, trail.await?);
+    if missed_log + missed_trail > 0 {
+        tracing::warn!(missed_log, missed_trail, "observers fell behind");
+    }
+    Ok(())
+}
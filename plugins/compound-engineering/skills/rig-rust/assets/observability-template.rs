@@ -0,0 +1,231 @@
+//! Observability Templates for Rig
+//!
+//! One `init_tracing` call at startup, and the same span names and fields
+//! for every kind of work, so a slow request can be followed from pipeline
+//! stage to agent call to tool call in the logs (or in any backend that
+//! reads `tracing` spans):
+//!
+//! ```text
+//! pipeline_stage  stage, latency_ms, outcome            pipeline_template::observe
+//! agent_call      model, prompt_tokens, completion_tokens, latency_ms, outcome
+//! tool_call       tool, latency_ms, outcome             call_tool, middleware_template::ToolMiddleware
+//! ```
+//!
+//! Token counts are cl100k estimates: `prompt` returns only text, not the
+//! provider's usage. Logs go to stderr, leaving stdout for program output.
+//!
+//! ```toml
+//! [dependencies]
+//! tracing = "0.1"
+//! tracing-subscriber = { version = "0.3", features = ["env-filter", "json"] }
+//! ```
+
+use std::time::Instant;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use rig::completion::PromptError;
+use serde::Deserialize;
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::chunking_template::{Sizer, TokenSizer};
+use crate::config_template::{BoxedAgent, Config, DynAgent, PriceTable, CLIENTS};
+use crate::pipeline_template::{observe, record_usage, with_report};
+use crate::registry_template::{RegistryError, ToolRegistry};
+use crate::tool_template::WikiLookup;
+
+// =============================================================================
+// SUBSCRIBER
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Multi-line and colored, for a terminal
+    #[default]
+    Pretty,
+    /// One line per event
+    Compact,
+    /// One JSON object per event with its span fields, for log shippers
+    Json,
+}
+
+/// The `[telemetry]` table in config, or `TelemetryConfig::from_env()`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub format: LogFormat,
+    /// `EnvFilter` directives; `RUST_LOG` overrides them when set
+    pub filter: String,
+    /// Also log each span as it closes, with its total time
+    pub span_close: bool,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self { format: LogFormat::Pretty, filter: "info".to_string(), span_close: false }
+    }
+}
+
+impl TelemetryConfig {
+    /// `LOG_FORMAT` (pretty, compact, json) and `RUST_LOG`
+    pub fn from_env() -> Self {
+        let format = match std::env::var("LOG_FORMAT").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "json" => LogFormat::Json,
+            "compact" => LogFormat::Compact,
+            _ => LogFormat::Pretty,
+        };
+        Self { format, ..Self::default() }
+    }
+}
+
+/// Install the global subscriber; call once, before anything logs
+pub fn init_tracing(config: &TelemetryConfig) -> Result<()> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.filter)
+            .map_err(|e| anyhow::anyhow!("invalid log filter {:?}: {e}", config.filter))?,
+    };
+    let span_events = if config.span_close { FmtSpan::CLOSE } else { FmtSpan::NONE };
+    let fmt = tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_span_events(span_events);
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match config.format {
+        LogFormat::Pretty => registry.with(fmt.pretty()).try_init()?,
+        LogFormat::Compact => registry.with(fmt.compact()).try_init()?,
+        LogFormat::Json => registry.with(fmt.json().with_current_span(true).with_span_list(true)).try_init()?,
+    }
+    Ok(())
+}
+
+// =============================================================================
+// AGENT CALLS
+// =============================================================================
+
+// Wrap any `DynAgent`; `TracedAgent` is a `DynAgent` itself, so it drops into
+// `MapReduce`, `Workflow`, and anything else that takes one:
+//     let agent = TracedAgent::new("gpt-4o", Box::new(BoxedAgent(client.agent("gpt-4o").build())));
+//     let agent = TracedAgent::from_config(&config, "writer", WRITER_PREAMBLE)?;
+//
+// Inside `pipeline_template::with_report`, the token estimates are also
+// added to the running stage's report.
+
+pub struct TracedAgent {
+    model: String,
+    agent: Box<dyn DynAgent>,
+}
+
+impl TracedAgent {
+    pub fn new(model: impl Into<String>, agent: Box<dyn DynAgent>) -> Self {
+        Self { model: model.into(), agent }
+    }
+
+    /// The agent for a config role, labeled with the role's model
+    pub fn from_config(config: &Config, role: &str, preamble: &str) -> Result<Self> {
+        let model = config.model(role).ok_or_else(|| anyhow::anyhow!("no [models.{role}] in config"))?.model.clone();
+        Ok(Self::new(model, config.agent(role, preamble)?))
+    }
+}
+
+impl DynAgent for TracedAgent {
+    fn prompt<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, Result<String, PromptError>> {
+        let span = tracing::info_span!(
+            "agent_call",
+            model = %self.model,
+            prompt_tokens = Empty,
+            completion_tokens = Empty,
+            latency_ms = Empty,
+            outcome = Empty,
+        );
+        let recorded = span.clone();
+        Box::pin(
+            async move {
+                let started = Instant::now();
+                let result = self.agent.prompt(prompt).await;
+                let prompt_tokens = TokenSizer.size(prompt) as u64;
+                recorded.record("prompt_tokens", prompt_tokens);
+                recorded.record("latency_ms", started.elapsed().as_millis() as u64);
+
+                match &result {
+                    Ok(response) => {
+                        let completion_tokens = TokenSizer.size(response) as u64;
+                        recorded.record("completion_tokens", completion_tokens);
+                        recorded.record("outcome", "ok");
+                        record_usage(&self.model, prompt_tokens, completion_tokens);
+                        tracing::debug!("agent call finished");
+                    }
+                    Err(e) => {
+                        recorded.record("outcome", "error");
+                        tracing::warn!(error = %e, "agent call failed");
+                    }
+                }
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
+
+// =============================================================================
+// TOOL CALLS
+// =============================================================================
+
+/// `registry.call` inside a `tool_call` span. Tools attached to an agent with
+/// `.tool(...)` get the same span from `ToolMiddleware`
+pub async fn call_tool(registry: &ToolRegistry, name: &str, args: serde_json::Value) -> Result<String, RegistryError> {
+    let span = tracing::info_span!("tool_call", tool = name, latency_ms = Empty, outcome = Empty);
+    let recorded = span.clone();
+    async move {
+        let started = Instant::now();
+        let result = registry.call(name, args).await;
+        recorded.record("latency_ms", started.elapsed().as_millis() as u64);
+        recorded.record("outcome", if result.is_ok() { "ok" } else { "error" });
+        if let Err(e) = &result {
+            tracing::warn!(error = %e, "tool call failed");
+        }
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// Look a topic up and summarize it, with every step in the trace:
+///
+/// ```text
+/// LOG_FORMAT=json RUST_LOG=info,rig=warn cargo run
+/// ```
+pub async fn traced_lookup() -> Result<()> {
+    init_tracing(&TelemetryConfig::from_env())?;
+
+    let registry = ToolRegistry::new();
+    registry.register(WikiLookup::default());
+    let agent = TracedAgent::new(
+        "gpt-4o-mini",
+        Box::new(BoxedAgent(CLIENTS.openai().agent("gpt-4o-mini").preamble("Summarize in three sentences.").build())),
+    );
+
+    let summarize = observe(
+        "summarize",
+        rig::pipeline::new().then(|topic: String| {
+            let (registry, agent) = (registry.clone(), &agent);
+            async move {
+                let facts = call_tool(&registry, "wikipedia", serde_json::json!({ "topic": topic })).await?;
+                Ok::<_, anyhow::Error>(agent.prompt(&facts).await?)
+            }
+        }),
+    );
+
+    let (summary, report) = with_report(&summarize, "Borrow checker".to_string(), &PriceTable::default()).await;
+    tracing::info!(elapsed = ?report.elapsed, cost_usd = ?report.cost_usd(), "lookup finished");
+    println!("{}", summary?);
+    Ok(())
+}
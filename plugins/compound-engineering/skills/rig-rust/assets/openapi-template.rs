@@ -0,0 +1,292 @@
+//! OpenAPI Tool Templates for Rig
+//!
+//! Turns every operation in an OpenAPI 3 spec into a Rig `Tool`, with the
+//! parameters schema derived from the operation's parameters and JSON body.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use openapiv3::{OpenAPI, Parameter, ParameterSchemaOrContent, ReferenceOr};
+use rig::completion::{Prompt, ToolDefinition};
+use rig::tool::Tool;
+use serde_json::{json, Map, Value};
+
+use crate::config_template::CLIENTS;
+
+// =============================================================================
+// LOADING
+// =============================================================================
+
+pub fn load_spec(path: impl AsRef<Path>) -> Result<OpenAPI> {
+    let path = path.as_ref();
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let spec = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&raw)?,
+        _ => serde_json::from_str(&raw)?,
+    };
+    Ok(spec)
+}
+
+// =============================================================================
+// GENERATION
+// =============================================================================
+
+#[derive(Clone, Copy)]
+enum Location {
+    Path,
+    Query,
+    Header,
+}
+
+pub struct OpenApiTool {
+    definition: ToolDefinition,
+    method: reqwest::Method,
+    base_url: String,
+    path: String,
+    params: Vec<(String, Location)>,
+    has_body: bool,
+    headers: HashMap<String, String>,
+    client: reqwest::Client,
+}
+
+pub struct OpenApiToolset {
+    spec: OpenAPI,
+    base_url: Option<String>,
+    headers: HashMap<String, String>,
+    client: reqwest::Client,
+}
+
+impl OpenApiToolset {
+    pub fn new(spec: OpenAPI) -> Self {
+        Self {
+            spec,
+            base_url: None,
+            headers: HashMap::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Override the spec's first `servers` entry
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Header sent on every request (auth tokens, API keys)
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn tools(&self) -> Result<Vec<OpenApiTool>> {
+        let base_url = match &self.base_url {
+            Some(url) => url.clone(),
+            None => self.spec.servers.first()
+                .map(|s| s.url.clone())
+                .context("spec has no servers; set base_url")?,
+        };
+        let components = self.spec.components.as_ref()
+            .map(|c| serde_json::to_value(&c.schemas))
+            .transpose()?
+            .unwrap_or_default();
+
+        let mut tools = Vec::new();
+        for (path, item) in self.spec.paths.iter() {
+            let Some(item) = item.as_item() else { continue };
+            for (method, op) in item.iter() {
+                let mut properties = Map::new();
+                let mut required = Vec::new();
+                let mut params = Vec::new();
+
+                for param in item.parameters.iter().chain(&op.parameters) {
+                    let ReferenceOr::Item(param) = param else { continue };
+                    let (data, location) = match param {
+                        Parameter::Path { parameter_data, .. } => (parameter_data, Location::Path),
+                        Parameter::Query { parameter_data, .. } => (parameter_data, Location::Query),
+                        Parameter::Header { parameter_data, .. } => (parameter_data, Location::Header),
+                        Parameter::Cookie { .. } => continue,
+                    };
+                    let mut schema = match &data.format {
+                        ParameterSchemaOrContent::Schema(schema) => {
+                            resolve_refs(serde_json::to_value(schema)?, &components, 0)
+                        }
+                        ParameterSchemaOrContent::Content(_) => json!({ "type": "string" }),
+                    };
+                    if let (Some(description), Value::Object(map)) = (&data.description, &mut schema) {
+                        map.insert("description".into(), description.clone().into());
+                    }
+                    properties.insert(data.name.clone(), schema);
+                    if data.required {
+                        required.push(data.name.clone());
+                    }
+                    params.push((data.name.clone(), location));
+                }
+
+                let body_schema = op.request_body.as_ref()
+                    .and_then(|body| body.as_item())
+                    .and_then(|body| body.content.get("application/json").map(|m| (body.required, m)))
+                    .and_then(|(required, media)| media.schema.as_ref().map(|s| (required, s)));
+                if let Some((body_required, schema)) = body_schema {
+                    properties.insert("body".into(), resolve_refs(serde_json::to_value(schema)?, &components, 0));
+                    if body_required {
+                        required.push("body".into());
+                    }
+                }
+
+                let name = op.operation_id.clone()
+                    .unwrap_or_else(|| format!("{method}_{path}"));
+                let description = op.summary.clone()
+                    .or_else(|| op.description.clone())
+                    .unwrap_or_else(|| format!("{} {}", method.to_uppercase(), path));
+
+                tools.push(OpenApiTool {
+                    definition: ToolDefinition {
+                        name: tool_name(&name),
+                        description,
+                        parameters: json!({
+                            "type": "object",
+                            "properties": properties,
+                            "required": required,
+                        }),
+                    },
+                    method: method.to_uppercase().parse()?,
+                    base_url: base_url.trim_end_matches('/').to_string(),
+                    path: path.clone(),
+                    params,
+                    has_body: body_schema.is_some(),
+                    headers: self.headers.clone(),
+                    client: self.client.clone(),
+                });
+            }
+        }
+        Ok(tools)
+    }
+}
+
+/// Tool names must match `^[a-zA-Z0-9_-]{1,64}$`
+fn tool_name(raw: &str) -> String {
+    let name: String = raw.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    name.trim_matches('_').chars().take(64).collect()
+}
+
+/// Inline `#/components/schemas/*` references so the model sees the full shape
+fn resolve_refs(value: Value, components: &Value, depth: usize) -> Value {
+    const MAX_DEPTH: usize = 8;
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get("$ref") {
+                let target = reference.strip_prefix("#/components/schemas/")
+                    .and_then(|name| components.get(name));
+                return match target {
+                    Some(schema) if depth < MAX_DEPTH => resolve_refs(schema.clone(), components, depth + 1),
+                    _ => json!({ "type": "object" }),
+                };
+            }
+            Value::Object(map.into_iter().map(|(k, v)| (k, resolve_refs(v, components, depth))).collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(|v| resolve_refs(v, components, depth)).collect()),
+        other => other,
+    }
+}
+
+// =============================================================================
+// CALLING
+// =============================================================================
+
+#[derive(Debug, thiserror::Error)]
+#[error("API error: {0}")]
+pub struct ApiError(String);
+
+impl Tool for OpenApiTool {
+    // Registered under `name()`, the operation's (sanitized) id
+    const NAME: &'static str = "openapi_operation";
+    type Args = Value;
+    type Output = String;
+    type Error = ApiError;
+
+    fn name(&self) -> String {
+        self.definition.name.clone()
+    }
+
+    async fn definition(&self, _: String) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    async fn call(&self, args: Value) -> Result<String, ApiError> {
+        let as_string = |v: &Value| match v {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+
+        let mut path = self.path.clone();
+        let mut query = Vec::new();
+        let mut headers = self.headers.clone();
+        for (name, location) in &self.params {
+            let Some(value) = args.get(name) else { continue };
+            match location {
+                Location::Path => {
+                    let encoded = urlencoding::encode(&as_string(value)).into_owned();
+                    path = path.replace(&format!("{{{name}}}"), &encoded);
+                }
+                Location::Query => query.push((name.clone(), as_string(value))),
+                Location::Header => {
+                    headers.insert(name.clone(), as_string(value));
+                }
+            }
+        }
+        if path.contains('{') {
+            return Err(ApiError(format!("Missing path parameter in {path}")));
+        }
+
+        let mut req = self.client
+            .request(self.method.clone(), format!("{}{}", self.base_url, path))
+            .query(&query);
+        for (name, value) in &headers {
+            req = req.header(name, value);
+        }
+        if self.has_body {
+            if let Some(body) = args.get("body") {
+                req = req.json(body);
+            }
+        }
+
+        let response = req.send().await.map_err(|e| ApiError(e.to_string()))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| ApiError(e.to_string()))?;
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(ApiError(format!("{status}: {body}")))
+        }
+    }
+}
+
+// =============================================================================
+// USAGE
+// =============================================================================
+
+/// Agent that can call every operation of an internal API
+pub async fn openapi_agent() -> Result<()> {
+    let spec = load_spec("openapi.yaml")?;
+    let tools = OpenApiToolset::new(spec)
+        .header("Authorization", format!("Bearer {}", std::env::var("INTERNAL_API_TOKEN")?))
+        .tools()?;
+
+    let client = CLIENTS.openai();
+    let mut builder = client
+        .agent("gpt-4o")
+        .preamble("You operate the internal inventory API. Prefer read operations.");
+    for tool in tools {
+        builder = builder.tool(tool);
+    }
+    let agent = builder.build();
+
+    let response = agent.prompt("How many units of SKU-1042 are in stock?").await?;
+    println!("{}", response);
+
+    Ok(())
+}
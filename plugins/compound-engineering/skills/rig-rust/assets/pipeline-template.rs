@@ -0,0 +1,1589 @@
+//! Pipeline Templates for Rig
+//!
+//! Multi-step LLM workflows composed from `rig::pipeline` ops instead of one
+//! large prompt. Every stage has a typed input and output: the outline stage
+//! hands the next stage an `Outline`, not a string to re-parse, and plain
+//! Rust runs between model calls to check or reshape what came back.
+//!
+//! `Op` is anything with `async fn call(&self, input) -> output`; `TryOp` is
+//! an `Op` whose output is a `Result` and adds `map_ok`, `map_err`, and
+//! `and_then`. Build a pipeline once, then `call` it (or `try_batch_call` it)
+//! as often as needed.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, Stream, StreamExt, TryStreamExt};
+use rig::agent::Agent;
+use rig::completion::{CompletionModel, Prompt, PromptError};
+use rig::extractor::Extractor;
+use rig::parallel;
+use rig::pipeline::{self, agent_ops, Op, TryOp};
+use rig::streaming::StreamingPrompt;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tracing::Instrument;
+
+use crate::chunking_template::{ChunkConfig, Chunker, RecursiveChunker, Sizer, TokenSizer};
+use crate::config_template::{BoxedAgent, DynAgent, PriceTable, ProviderPolicy, CLIENTS};
+use crate::rag_template::{InMemoryVectorStore, Retriever, StoreIndex};
+
+// =============================================================================
+// STAGE ERRORS
+// =============================================================================
+
+/// A pipeline failure, tagged with the stage that produced it
+#[derive(Debug, thiserror::Error)]
+#[error("stage `{stage}` failed: {error:#}")]
+pub struct StageError {
+    pub stage: &'static str,
+    pub error: anyhow::Error,
+}
+
+/// A type-erased op, so arms built from different combinators fit in one list
+type BoxedOp<I, O> = Box<dyn Fn(I) -> BoxFuture<'static, Result<O, StageError>> + Send + Sync>;
+
+fn boxed<P, I, O>(op: P) -> BoxedOp<I, O>
+where
+    P: Op<Input = I, Output = Result<O, StageError>> + 'static,
+{
+    let op = Arc::new(op);
+    Box::new(move |input| {
+        let op = op.clone();
+        Box::pin(async move { op.call(input).await })
+    })
+}
+
+/// `map_err` adapter that names the failing stage: `.map_err(at("outline"))`
+pub fn at<E: Into<anyhow::Error>>(stage: &'static str) -> impl Fn(E) -> StageError + Clone + Send + Sync {
+    move |error| StageError { stage, error: error.into() }
+}
+
+// =============================================================================
+// SEQUENTIAL PIPELINE
+// =============================================================================
+
+// Brief -> outline request -> Outline (extracted) -> Outline (normalized) -> Article
+//
+//     let pipeline = article_pipeline(outliner, writer);
+//     let article = pipeline.call(brief).await?;
+//     let articles = pipeline.try_batch_call(4, briefs).await?;
+
+pub const OUTLINE_PREAMBLE: &str = "You plan technical articles. Produce a title and 3-6 sections, \
+each with a heading and the key points it must cover. Record the intended audience.";
+
+pub const WRITER_PREAMBLE: &str = "You write clear technical articles from an outline. Follow the \
+outline's sections in order, use its headings as Markdown `##` headings, and write for the \
+stated audience.";
+
+/// Most sections an outline may keep; the model sometimes produces a dozen
+const MAX_SECTIONS: usize = 6;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Brief {
+    pub topic: String,
+    pub audience: String,
+}
+
+impl Brief {
+    fn outline_request(&self) -> String {
+        format!("Topic: {}\nAudience: {}", self.topic, self.audience)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Outline {
+    pub title: String,
+    pub audience: String,
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Section {
+    pub heading: String,
+    pub points: Vec<String>,
+}
+
+impl Outline {
+    /// Drops sections without points and caps the section count
+    fn normalize(mut self) -> Self {
+        self.sections.retain(|s| !s.heading.trim().is_empty() && !s.points.is_empty());
+        self.sections.truncate(MAX_SECTIONS);
+        self
+    }
+
+    fn draft_request(&self) -> String {
+        let mut request = format!("Title: {}\nAudience: {}\n", self.title, self.audience);
+        for section in &self.sections {
+            request.push_str(&format!("\n## {}\n", section.heading));
+            for point in &section.points {
+                request.push_str(&format!("- {point}\n"));
+            }
+        }
+        request
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Article {
+    pub outline: Outline,
+    pub body: String,
+}
+
+/// Prompt (extract an outline) -> map (normalize it) -> prompt (write it)
+pub fn article_pipeline<M>(
+    outliner: Extractor<M, Outline>,
+    writer: Agent<M>,
+) -> impl Op<Input = Brief, Output = Result<Article, StageError>>
+where
+    M: CompletionModel + 'static,
+{
+    let writer = Arc::new(writer);
+    pipeline::new()
+        .map(|brief: Brief| brief.outline_request())
+        .chain(agent_ops::extract(outliner))
+        .map_err(at("outline"))
+        .map_ok(Outline::normalize)
+        .and_then(move |outline: Outline| {
+            let writer = writer.clone();
+            async move {
+                if outline.sections.is_empty() {
+                    return Err(at("outline")(anyhow::anyhow!("outline has no usable sections")));
+                }
+                let body = writer.prompt(outline.draft_request()).await.map_err(at("draft"))?;
+                Ok(Article { outline, body })
+            }
+        })
+}
+
+/// Outline with a small model, write with a large one
+pub async fn write_article() -> Result<()> {
+    let client = CLIENTS.openai();
+    let outliner = client.extractor::<Outline>("gpt-4o-mini").preamble(OUTLINE_PREAMBLE).build();
+    let writer = client.agent("gpt-4o").preamble(WRITER_PREAMBLE).build();
+    let pipeline = article_pipeline(outliner, writer);
+
+    let article = pipeline
+        .call(Brief {
+            topic: "Structured concurrency in async Rust".into(),
+            audience: "backend engineers new to Tokio".into(),
+        })
+        .await?;
+    println!("# {}\n\n{}", article.outline.title, article.body);
+
+    // Up to 4 briefs in flight; the first failing stage aborts the batch
+    let briefs = vec![
+        Brief { topic: "Cancellation safety".into(), audience: "Rust library authors".into() },
+        Brief { topic: "Backpressure with bounded channels".into(), audience: "SREs".into() },
+    ];
+    for article in pipeline.try_batch_call(4, briefs).await? {
+        println!("{}: {} sections", article.outline.title, article.outline.sections.len());
+    }
+    Ok(())
+}
+
+// =============================================================================
+// CONDITIONAL BRANCHING
+// =============================================================================
+
+// A classifier op picks a key; the arm registered for that key gets the
+// original input. Every arm returns the same output type, so the branch is
+// itself an `Op` and composes like any other stage.
+//
+//     let route = Branch::new(classifier)
+//         .arm(TicketKind::Bug, bug_arm)
+//         .arm(TicketKind::Feature, feature_arm)
+//         .otherwise(fallback_arm);
+//     let reply = route.call(ticket).await?;
+//
+// The classifier can be a model call (an extractor returning an enum) or a
+// plain rule: `pipeline::new().map(|t: Ticket| Ok(rule(&t)))`.
+
+pub struct Branch<K, I, O> {
+    classify: BoxedOp<I, K>,
+    arms: Vec<(K, BoxedOp<I, O>)>,
+    fallback: Option<BoxedOp<I, O>>,
+}
+
+impl<K, I, O> Branch<K, I, O>
+where
+    K: PartialEq + fmt::Debug + Send + Sync + 'static,
+    I: Clone + Send + Sync + 'static,
+    O: Send + Sync + 'static,
+{
+    pub fn new(classify: impl Op<Input = I, Output = Result<K, StageError>> + 'static) -> Self {
+        Self { classify: boxed(classify), arms: Vec::new(), fallback: None }
+    }
+
+    pub fn arm(mut self, key: K, op: impl Op<Input = I, Output = Result<O, StageError>> + 'static) -> Self {
+        self.arms.push((key, boxed(op)));
+        self
+    }
+
+    /// Arm for keys without their own; without it an unmatched key is an error
+    pub fn otherwise(mut self, op: impl Op<Input = I, Output = Result<O, StageError>> + 'static) -> Self {
+        self.fallback = Some(boxed(op));
+        self
+    }
+}
+
+impl<K, I, O> Op for Branch<K, I, O>
+where
+    K: PartialEq + fmt::Debug + Send + Sync + 'static,
+    I: Clone + Send + Sync + 'static,
+    O: Send + Sync + 'static,
+{
+    type Input = I;
+    type Output = Result<O, StageError>;
+
+    async fn call(&self, input: I) -> Result<O, StageError> {
+        let key = (self.classify)(input.clone()).await?;
+        let arm = self.arms.iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, op)| op)
+            .or(self.fallback.as_ref())
+            .ok_or_else(|| at("route")(anyhow::anyhow!("no arm for {key:?}")))?;
+        tracing::debug!(route = ?key, "pipeline branch");
+        arm(input).await
+    }
+}
+
+// Example: triage incoming tickets, then handle bugs and feature requests
+// differently, ending with the same `Reply` either way
+
+const TRIAGE_PREAMBLE: &str = "Classify the ticket as a bug (something that worked or should \
+work is broken), a feature request (new behavior), or a question.";
+
+const BUG_PREAMBLE: &str = "Extract a bug report from the ticket. Severity is critical for data \
+loss or outages, high when there is no workaround, low otherwise.";
+
+const FEATURE_PREAMBLE: &str = "You reply to feature requests. Thank the reporter, restate the \
+request in one sentence, and ask what problem it would solve for them. Do not promise delivery.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ticket {
+    pub title: String,
+    pub body: String,
+}
+
+impl fmt::Display for Ticket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Title: {}\n\n{}", self.title, self.body)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TicketKind {
+    Bug,
+    Feature,
+    Question,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Triage {
+    pub kind: TicketKind,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct BugReport {
+    pub summary: String,
+    pub steps_to_reproduce: Vec<String>,
+    /// "critical", "high", or "low"
+    pub severity: String,
+}
+
+/// What every arm produces
+#[derive(Debug, Clone, Serialize)]
+pub struct Reply {
+    pub kind: TicketKind,
+    pub labels: Vec<String>,
+    pub body: String,
+}
+
+impl Reply {
+    fn from_bug(report: BugReport) -> Self {
+        let mut body = format!("Thanks for the report. We've logged it as: {}", report.summary);
+        if report.steps_to_reproduce.is_empty() {
+            body.push_str("\n\nCould you add the steps that reproduce it?");
+        }
+        Self { kind: TicketKind::Bug, labels: vec!["bug".into(), format!("severity:{}", report.severity)], body }
+    }
+}
+
+/// Triage -> bug arm (extract a report) | feature arm (draft a reply) | fallback
+pub fn ticket_router<M>(
+    triager: Extractor<M, Triage>,
+    bug_extractor: Extractor<M, BugReport>,
+    feature_writer: Agent<M>,
+) -> Branch<TicketKind, Ticket, Reply>
+where
+    M: CompletionModel + 'static,
+{
+    let classify = pipeline::new()
+        .map(|ticket: Ticket| ticket.to_string())
+        .chain(agent_ops::extract(triager))
+        .map_err(at("triage"))
+        .map_ok(|triage: Triage| triage.kind);
+
+    let bug_arm = pipeline::new()
+        .map(|ticket: Ticket| ticket.to_string())
+        .chain(agent_ops::extract(bug_extractor))
+        .map_err(at("bug"))
+        .map_ok(Reply::from_bug);
+
+    let feature_arm = pipeline::new()
+        .map(|ticket: Ticket| ticket.to_string())
+        .chain(agent_ops::prompt(feature_writer))
+        .map_err(at("feature"))
+        .map_ok(|body: String| Reply { kind: TicketKind::Feature, labels: vec!["enhancement".into()], body });
+
+    // Questions (and anything added to TicketKind later) go to a human
+    let fallback = pipeline::new().map(|_: Ticket| {
+        Ok(Reply { kind: TicketKind::Question, labels: vec!["needs-triage".into()], body: String::new() })
+    });
+
+    Branch::new(classify)
+        .arm(TicketKind::Bug, bug_arm)
+        .arm(TicketKind::Feature, feature_arm)
+        .otherwise(fallback)
+}
+
+pub async fn triage_tickets() -> Result<()> {
+    let client = CLIENTS.openai();
+    let router = ticket_router(
+        client.extractor::<Triage>("gpt-4o-mini").preamble(TRIAGE_PREAMBLE).build(),
+        client.extractor::<BugReport>("gpt-4o-mini").preamble(BUG_PREAMBLE).build(),
+        client.agent("gpt-4o").preamble(FEATURE_PREAMBLE).build(),
+    );
+
+    let reply = router
+        .call(Ticket {
+            title: "Export crashes on empty project".into(),
+            body: "Clicking Export with no files open closes the app. Happens every time on 2.3.1.".into(),
+        })
+        .await?;
+    println!("{:?} {:?}\n{}", reply.kind, reply.labels, reply.body);
+    Ok(())
+}
+
+// =============================================================================
+// PARALLEL STAGES
+// =============================================================================
+
+// `parallel!` hands a clone of the input to each op, runs them concurrently,
+// and yields a tuple of their outputs. Each branch returns its own `Result`,
+// so one failure doesn't cancel the others; the join step decides which
+// failures are fatal and which just leave a field empty.
+//
+//     let analyze = analysis_pipeline(sentiment, summarizer, entities);
+//     let analysis = analyze.call(review_text).await?;
+//     if !analysis.degraded.is_empty() { /* partial result */ }
+
+const SENTIMENT_PREAMBLE: &str = "Rate the overall sentiment of the text, with your confidence \
+from 0 to 1.";
+
+pub const SUMMARY_PREAMBLE: &str = "Summarize the text in at most two sentences. Reply with the \
+summary only.";
+
+const ENTITIES_PREAMBLE: &str = "List the named entities in the text: people, organizations, \
+products, and places. Use the name as written.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Sentiment {
+    Positive,
+    Neutral,
+    Negative,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SentimentScore {
+    pub label: Sentiment,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Entity {
+    pub name: String,
+    /// "person", "organization", "product", or "place"
+    pub kind: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Entities {
+    pub entities: Vec<Entity>,
+}
+
+/// The joined branches; `degraded` names the optional ones that failed
+#[derive(Debug, Clone, Serialize)]
+pub struct Analysis {
+    pub sentiment: SentimentScore,
+    pub summary: Option<String>,
+    pub entities: Vec<Entity>,
+    pub degraded: Vec<&'static str>,
+}
+
+impl Analysis {
+    /// Sentiment is required; summary and entities degrade to empty
+    fn join(
+        sentiment: Result<SentimentScore, StageError>,
+        summary: Result<String, StageError>,
+        entities: Result<Vec<Entity>, StageError>,
+    ) -> Result<Self, StageError> {
+        let mut degraded = Vec::new();
+        let summary = soft(summary, &mut degraded);
+        let entities = soft(entities, &mut degraded).unwrap_or_default();
+        Ok(Self { sentiment: sentiment?, summary, entities, degraded })
+    }
+}
+
+/// An optional branch's value, or `None` with the stage logged and recorded
+fn soft<T>(result: Result<T, StageError>, degraded: &mut Vec<&'static str>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::warn!(stage = e.stage, error = %e.error, "optional pipeline branch failed");
+            degraded.push(e.stage);
+            None
+        }
+    }
+}
+
+/// Fan a text out to sentiment, summary, and entity stages, then join them
+pub fn analysis_pipeline<M>(
+    sentiment: Extractor<M, SentimentScore>,
+    summarizer: Agent<M>,
+    entities: Extractor<M, Entities>,
+) -> impl Op<Input = String, Output = Result<Analysis, StageError>>
+where
+    M: CompletionModel + 'static,
+{
+    let sentiment = agent_ops::extract(sentiment).map_err(at("sentiment"));
+    let summary = agent_ops::prompt(summarizer).map_err(at("summary"));
+    let entities = agent_ops::extract(entities)
+        .map_err(at("entities"))
+        .map_ok(|found: Entities| found.entities);
+
+    pipeline::new()
+        .chain(parallel!(sentiment, summary, entities))
+        .map(|(sentiment, summary, entities)| Analysis::join(sentiment, summary, entities))
+}
+
+pub async fn analyze_reviews() -> Result<()> {
+    let client = CLIENTS.openai();
+    let analyze = analysis_pipeline(
+        client.extractor::<SentimentScore>("gpt-4o-mini").preamble(SENTIMENT_PREAMBLE).build(),
+        client.agent("gpt-4o-mini").preamble(SUMMARY_PREAMBLE).build(),
+        client.extractor::<Entities>("gpt-4o-mini").preamble(ENTITIES_PREAMBLE).build(),
+    );
+
+    let reviews = vec![
+        "The Acme X2 kettle boils fast, but support in Berlin never answered my emails.".to_string(),
+        "Fine for the price. Lid is a bit loose.".to_string(),
+    ];
+    // Three model calls per review, two reviews at a time
+    for analysis in analyze.try_batch_call(2, reviews).await? {
+        println!(
+            "{:?} ({:.2}) {:?} entities={}{}",
+            analysis.sentiment.label,
+            analysis.sentiment.confidence,
+            analysis.summary,
+            analysis.entities.len(),
+            if analysis.degraded.is_empty() { String::new() } else { format!(" degraded={:?}", analysis.degraded) },
+        );
+    }
+    Ok(())
+}
+
+// =============================================================================
+// RETRY AND RECOVERY
+// =============================================================================
+
+// Wrappers for any op that fails with `StageError`, so one flaky stage
+// doesn't abort the run:
+//
+//     retry(op, policy)        // run again on transient errors, with the policy's backoff
+//     or_else(op, fallback)    // on failure, run `fallback` (say, a smaller model) on the same input
+//     recover(op, |input, e|)  // on failure, substitute a value or keep the error
+//
+// They nest: `recover(or_else(retry(primary, policy), fallback), default)`.
+// These are free functions rather than methods so they don't collide with
+// `TryOp::or_else`.
+
+pub struct Retry<P> {
+    op: P,
+    policy: ProviderPolicy,
+    any_error: bool,
+}
+
+/// Retry `op` on transient errors (rate limits, timeouts, 5xx) per `policy`
+pub fn retry<P>(op: P, policy: ProviderPolicy) -> Retry<P> {
+    Retry { op, policy, any_error: false }
+}
+
+impl<P> Retry<P> {
+    /// Also retry parse and extraction failures; a second sample often succeeds
+    pub fn on_any_error(mut self) -> Self {
+        self.any_error = true;
+        self
+    }
+}
+
+impl<P, T> Op for Retry<P>
+where
+    P: Op<Output = Result<T, StageError>>,
+    P::Input: Clone,
+    T: Send + Sync,
+{
+    type Input = P::Input;
+    type Output = Result<T, StageError>;
+
+    async fn call(&self, input: P::Input) -> Result<T, StageError> {
+        let mut attempt = 0;
+        loop {
+            match self.op.call(input.clone()).await {
+                Err(e) if attempt < self.policy.max_retries && (self.any_error || ProviderPolicy::is_transient(&*e.error)) => {
+                    attempt += 1;
+                    tracing::warn!(stage = e.stage, attempt, error = %e.error, "retrying pipeline stage");
+                    record_current(|stats| stats.retries += 1);
+                    tokio::time::sleep(self.policy.delay(attempt)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+pub struct OrElse<P, Q> {
+    op: P,
+    fallback: Q,
+}
+
+/// Run `fallback` on the same input when `op` fails
+pub fn or_else<P, Q>(op: P, fallback: Q) -> OrElse<P, Q> {
+    OrElse { op, fallback }
+}
+
+impl<P, Q, T> Op for OrElse<P, Q>
+where
+    P: Op<Output = Result<T, StageError>>,
+    Q: Op<Input = P::Input, Output = Result<T, StageError>>,
+    P::Input: Clone,
+    T: Send + Sync,
+{
+    type Input = P::Input;
+    type Output = Result<T, StageError>;
+
+    async fn call(&self, input: P::Input) -> Result<T, StageError> {
+        match self.op.call(input.clone()).await {
+            Err(e) => {
+                tracing::warn!(stage = e.stage, error = %e.error, "pipeline stage failed; running fallback");
+                self.fallback.call(input).await
+            }
+            ok => ok,
+        }
+    }
+}
+
+pub struct Recover<P, F> {
+    op: P,
+    recover: F,
+}
+
+/// Turn a failure into a value; return the error from `recover` to keep it
+pub fn recover<P, F>(op: P, recover: F) -> Recover<P, F> {
+    Recover { op, recover }
+}
+
+impl<P, F, T> Op for Recover<P, F>
+where
+    P: Op<Output = Result<T, StageError>>,
+    P::Input: Clone,
+    F: Fn(P::Input, StageError) -> Result<T, StageError> + Send + Sync,
+    T: Send + Sync,
+{
+    type Input = P::Input;
+    type Output = Result<T, StageError>;
+
+    async fn call(&self, input: P::Input) -> Result<T, StageError> {
+        match self.op.call(input.clone()).await {
+            Err(e) => (self.recover)(input, e),
+            ok => ok,
+        }
+    }
+}
+
+/// gpt-4o with retries, then gpt-4o-mini, then the opening of the text itself
+pub async fn resilient_summary() -> Result<()> {
+    let client = CLIENTS.openai();
+    let primary = pipeline::new()
+        .chain(agent_ops::prompt(client.agent("gpt-4o").preamble(SUMMARY_PREAMBLE).build()))
+        .map_err(at("summary"));
+    let smaller = pipeline::new()
+        .chain(agent_ops::prompt(client.agent("gpt-4o-mini").preamble(SUMMARY_PREAMBLE).build()))
+        .map_err(at("summary-fallback"));
+
+    let summarize = recover(
+        or_else(retry(primary, ProviderPolicy::default()), smaller),
+        |text: String, e| {
+            tracing::warn!(stage = e.stage, "no model could summarize; using the opening instead");
+            Ok(text.chars().take(280).collect())
+        },
+    );
+
+    let summary = summarize.call(std::fs::read_to_string("README.md")?).await?;
+    println!("{summary}");
+    Ok(())
+}
+
+// =============================================================================
+// STRUCTURED EXTRACTION
+// =============================================================================
+
+// Raw documents (invoices, tickets, resumes) in, validated records out. The
+// extractor guarantees the JSON shape; `Validate` checks what a schema
+// can't (totals add up, dates parse). A record that fails validation goes
+// back to the model with the list of problems, up to `repairs` times, and
+// then becomes a `Reject` instead of a silently wrong row:
+//
+//     let records = RecordPipeline::new(extractor).repairs(2);
+//     let mut outcomes = records.run(documents);
+//     while let Some(outcome) = outcomes.next().await {
+//         match outcome {
+//             Ok(record) => save(record),
+//             Err(reject) => reject.append_to("rejects.jsonl")?,
+//         }
+//     }
+
+/// Business rules the JSON schema can't express
+pub trait Validate {
+    /// Everything wrong with the record; empty when it's usable
+    fn problems(&self) -> Vec<String>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawDocument {
+    pub id: String,
+    pub text: String,
+}
+
+impl RawDocument {
+    /// Every `.txt` and `.md` file in `dir`, with the file name as id
+    pub fn read_dir(dir: impl AsRef<Path>) -> Result<Vec<Self>> {
+        let dir = dir.as_ref();
+        let mut docs = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", dir.display()))? {
+            let path = entry?.path();
+            if !matches!(path.extension().and_then(|e| e.to_str()), Some("txt" | "md")) {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+            let id = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            docs.push(Self { id, text });
+        }
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(docs)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Record<T> {
+    pub id: String,
+    pub value: T,
+    /// 1 when the first extraction was valid
+    pub attempts: usize,
+}
+
+/// A document that never produced a valid record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reject {
+    pub id: String,
+    pub attempts: usize,
+    /// Problems with the last attempt
+    pub problems: Vec<String>,
+    /// The last record extracted, if any got that far
+    pub last_attempt: Option<serde_json::Value>,
+}
+
+impl Reject {
+    /// Append as one JSON line, for review or a later re-run
+    pub fn append_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow::anyhow!("cannot open {}: {e}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+fn repair_request(text: &str, last: Option<&serde_json::Value>, problems: &[String]) -> String {
+    let mut request = format!("{text}\n\n---\nYour previous extraction from this document was rejected:\n");
+    for problem in problems {
+        request.push_str(&format!("- {problem}\n"));
+    }
+    if let Some(last) = last {
+        request.push_str(&format!("\nPrevious extraction:\n{last}\n"));
+    }
+    request.push_str("\nExtract again from the document above, fixing these problems. Use only values the document states.");
+    request
+}
+
+pub struct RecordPipeline<M: CompletionModel, T> {
+    extractor: Extractor<M, T>,
+    repairs: usize,
+    concurrency: usize,
+}
+
+impl<M, T> RecordPipeline<M, T>
+where
+    M: CompletionModel,
+    T: JsonSchema + DeserializeOwned + Serialize + Validate + Send + Sync,
+{
+    pub fn new(extractor: Extractor<M, T>) -> Self {
+        Self { extractor, repairs: 2, concurrency: 4 }
+    }
+
+    /// Extra attempts after the first, each told what was wrong
+    pub fn repairs(mut self, repairs: usize) -> Self {
+        self.repairs = repairs;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn extract(&self, doc: &RawDocument) -> Result<Record<T>, Reject> {
+        let mut request = doc.text.clone();
+        let mut problems = Vec::new();
+        let mut last = None;
+        for attempt in 1..=self.repairs + 1 {
+            match self.extractor.extract(request.as_str()).await {
+                Ok(value) => {
+                    problems = value.problems();
+                    if problems.is_empty() {
+                        return Ok(Record { id: doc.id.clone(), value, attempts: attempt });
+                    }
+                    last = serde_json::to_value(&value).ok();
+                }
+                Err(e) => problems = vec![format!("extraction failed: {e}")],
+            }
+            tracing::debug!(doc = %doc.id, attempt, ?problems, "extracted record rejected");
+            request = repair_request(&doc.text, last.as_ref(), &problems);
+        }
+        Err(Reject { id: doc.id.clone(), attempts: self.repairs + 1, problems, last_attempt: last })
+    }
+
+    /// Outcomes in completion order, `concurrency` documents at a time
+    pub fn run<'a>(&'a self, docs: impl IntoIterator<Item = RawDocument> + 'a) -> impl Stream<Item = Result<Record<T>, Reject>> + 'a {
+        stream::iter(docs)
+            .map(move |doc| async move { self.extract(&doc).await })
+            .buffer_unordered(self.concurrency)
+    }
+}
+
+/// One document per call, so it chains with other ops
+impl<M, T> Op for RecordPipeline<M, T>
+where
+    M: CompletionModel,
+    T: JsonSchema + DeserializeOwned + Serialize + Validate + Send + Sync,
+{
+    type Input = RawDocument;
+    type Output = Result<Record<T>, Reject>;
+
+    async fn call(&self, doc: RawDocument) -> Result<Record<T>, Reject> {
+        self.extract(&doc).await
+    }
+}
+
+// Example record: invoices
+
+const INVOICE_PREAMBLE: &str = "Extract the invoice. Dates are YYYY-MM-DD, currency is the \
+ISO 4217 code, and amounts are numbers without currency symbols. Include every line item.";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Invoice {
+    pub number: String,
+    pub vendor: String,
+    /// YYYY-MM-DD
+    pub issue_date: String,
+    /// ISO 4217, e.g. EUR
+    pub currency: String,
+    pub lines: Vec<LineItem>,
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LineItem {
+    pub description: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+}
+
+impl Validate for Invoice {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.number.trim().is_empty() {
+            problems.push("invoice number is empty".into());
+        }
+        let date: Vec<&str> = self.issue_date.split('-').collect();
+        let date_ok = date.len() == 3
+            && date.iter().zip([4, 2, 2]).all(|(part, len)| part.len() == len && part.chars().all(|c| c.is_ascii_digit()));
+        if !date_ok {
+            problems.push(format!("issue_date {:?} is not YYYY-MM-DD", self.issue_date));
+        }
+        if self.currency.len() != 3 || !self.currency.chars().all(|c| c.is_ascii_uppercase()) {
+            problems.push(format!("currency {:?} is not an ISO 4217 code", self.currency));
+        }
+        if self.lines.is_empty() {
+            problems.push("no line items".into());
+        }
+        let sum: f64 = self.lines.iter().map(|l| l.quantity * l.unit_price).sum();
+        // Tax and shipping lines belong in `lines`, so the sum should match to the cent
+        if (sum - self.total).abs() > 0.01 {
+            problems.push(format!("line items sum to {sum:.2} but total is {:.2}", self.total));
+        }
+        problems
+    }
+}
+
+/// Extract every invoice in `inbox/`; valid ones to invoices.jsonl, the rest to rejects.jsonl
+pub async fn extract_invoices() -> Result<()> {
+    let extractor = CLIENTS.openai().extractor::<Invoice>("gpt-4o").preamble(INVOICE_PREAMBLE).build();
+    let pipeline = RecordPipeline::new(extractor).repairs(2).concurrency(8);
+
+    let mut accepted = std::fs::File::create("invoices.jsonl")?;
+    let (mut ok, mut rejected) = (0, 0);
+    let mut outcomes = pipeline.run(RawDocument::read_dir("inbox")?);
+    while let Some(outcome) = outcomes.next().await {
+        match outcome {
+            Ok(record) => {
+                writeln!(accepted, "{}", serde_json::to_string(&record)?)?;
+                ok += 1;
+            }
+            Err(reject) => {
+                tracing::warn!(doc = %reject.id, problems = ?reject.problems, "invoice rejected");
+                reject.append_to("rejects.jsonl")?;
+                rejected += 1;
+            }
+        }
+    }
+    tracing::info!(ok, rejected, "invoice extraction finished");
+    Ok(())
+}
+
+// =============================================================================
+// MAP-REDUCE SUMMARIZATION
+// =============================================================================
+
+// Summarize more text than fits in one context window: chunk every document,
+// summarize the chunks concurrently (map), then merge the summaries `fan_in`
+// at a time, level by level (reduce), until one call can take the rest:
+//
+//     let summarize = MapReduce::new(Box::new(BoxedAgent(mini)))
+//         .reduce_agent(Box::new(BoxedAgent(large)))
+//         .fan_in(6)
+//         .max_depth(3);
+//     let summary = summarize.call(documents).await?;
+//
+// Prompts are templates where `{text}` marks the input; without it the text
+// is appended. `max_depth` caps the intermediate levels, and with it the
+// latency; whatever is left after the last level goes to one final call.
+
+const MAP_PROMPT: &str = "Summarize this excerpt in a short paragraph. Keep names, numbers, \
+decisions, and open questions.\n\n{text}";
+
+const REDUCE_PROMPT: &str = "These are summaries of consecutive parts of a larger text. Merge \
+them into one summary, removing repetition but keeping every distinct fact.\n\n{text}";
+
+const SUMMARY_SEPARATOR: &str = "\n\n---\n\n";
+
+fn fill(template: &str, text: &str) -> String {
+    if template.contains("{text}") {
+        template.replace("{text}", text)
+    } else {
+        format!("{template}\n\n{text}")
+    }
+}
+
+pub struct MapReduce {
+    map_agent: Arc<dyn DynAgent>,
+    reduce_agent: Arc<dyn DynAgent>,
+    chunker: Arc<dyn Chunker>,
+    map_prompt: String,
+    reduce_prompt: String,
+    fan_in: usize,
+    max_depth: usize,
+    concurrency: usize,
+}
+
+impl MapReduce {
+    /// Uses `agent` for both phases, with 2000-token chunks
+    pub fn new(agent: Box<dyn DynAgent>) -> Self {
+        let agent: Arc<dyn DynAgent> = agent.into();
+        let chunks = ChunkConfig { max_size: 2000, overlap: 100, sizer: Arc::new(TokenSizer) };
+        Self {
+            map_agent: agent.clone(),
+            reduce_agent: agent,
+            chunker: Arc::new(RecursiveChunker::new(chunks)),
+            map_prompt: MAP_PROMPT.to_string(),
+            reduce_prompt: REDUCE_PROMPT.to_string(),
+            fan_in: 8,
+            max_depth: 4,
+            concurrency: 8,
+        }
+    }
+
+    /// A stronger model for merging; the map phase makes most of the calls
+    pub fn reduce_agent(mut self, agent: Box<dyn DynAgent>) -> Self {
+        self.reduce_agent = agent.into();
+        self
+    }
+
+    pub fn chunker(mut self, chunker: impl Chunker + 'static) -> Self {
+        self.chunker = Arc::new(chunker);
+        self
+    }
+
+    pub fn map_prompt(mut self, template: impl Into<String>) -> Self {
+        self.map_prompt = template.into();
+        self
+    }
+
+    pub fn reduce_prompt(mut self, template: impl Into<String>) -> Self {
+        self.reduce_prompt = template.into();
+        self
+    }
+
+    /// Summaries merged per reduce call
+    pub fn fan_in(mut self, fan_in: usize) -> Self {
+        self.fan_in = fan_in.max(2);
+        self
+    }
+
+    /// Most intermediate reduce levels before the final call
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn summarize(&self, docs: &[String]) -> Result<String, StageError> {
+        let chunks: Vec<String> = docs.iter()
+            .flat_map(|doc| self.chunker.chunk(doc))
+            .map(|chunk| chunk.text)
+            .collect();
+        if chunks.is_empty() {
+            return Err(at("map")(anyhow::anyhow!("nothing to summarize")));
+        }
+        tracing::debug!(chunks = chunks.len(), "map-reduce: map");
+        let mut summaries = self.fan_out(&*self.map_agent, &self.map_prompt, chunks, "map").await?;
+
+        let mut depth = 0;
+        while summaries.len() > self.fan_in && depth < self.max_depth {
+            depth += 1;
+            tracing::debug!(depth, summaries = summaries.len(), "map-reduce: reduce level");
+            let groups = summaries.chunks(self.fan_in).map(|group| group.join(SUMMARY_SEPARATOR)).collect();
+            summaries = self.fan_out(&*self.reduce_agent, &self.reduce_prompt, groups, "reduce").await?;
+        }
+        if summaries.len() > self.fan_in {
+            tracing::warn!(summaries = summaries.len(), "map-reduce: depth limit reached; final reduce gets them all");
+        }
+        self.reduce_agent
+            .prompt(&fill(&self.reduce_prompt, &summaries.join(SUMMARY_SEPARATOR)))
+            .await
+            .map_err(at("reduce"))
+    }
+
+    async fn fan_out(
+        &self,
+        agent: &dyn DynAgent,
+        template: &str,
+        texts: Vec<String>,
+        stage: &'static str,
+    ) -> Result<Vec<String>, StageError> {
+        stream::iter(texts)
+            .map(|text| async move { agent.prompt(&fill(template, &text)).await.map_err(at(stage)) })
+            .buffered(self.concurrency)
+            .try_collect()
+            .await
+    }
+}
+
+/// Documents in, one summary out
+impl Op for MapReduce {
+    type Input = Vec<String>;
+    type Output = Result<String, StageError>;
+
+    async fn call(&self, docs: Vec<String>) -> Result<String, StageError> {
+        self.summarize(&docs).await
+    }
+}
+
+/// Summarize every Markdown file under `docs/` into one overview
+pub async fn summarize_docs() -> Result<()> {
+    let client = CLIENTS.openai();
+    let summarize = MapReduce::new(Box::new(BoxedAgent(client.agent("gpt-4o-mini").build())))
+        .reduce_agent(Box::new(BoxedAgent(client.agent("gpt-4o").build())))
+        .chunker(RecursiveChunker::new(ChunkConfig::tokens(3000, 150)?))
+        .reduce_prompt("Merge these summaries of a project's documentation into a one-page \
+            overview for a new contributor, with a section per major component.\n\n{text}")
+        .fan_in(6)
+        .max_depth(3);
+
+    let docs: Vec<String> = RawDocument::read_dir("docs")?.into_iter().map(|doc| doc.text).collect();
+    println!("{}", summarize.call(docs).await?);
+    Ok(())
+}
+
+// =============================================================================
+// OBSERVABILITY
+// =============================================================================
+
+// `observe` wraps a stage in a `pipeline_stage` tracing span and, when the
+// pipeline runs under `with_report`, tallies the stage's calls, failures,
+// latency, retries, and tokens into a `PipelineReport`:
+//
+//     let summarize = observe("summary", retry(summary_op, policy));
+//     let (summary, report) = with_report(&summarize, text, &PriceTable::default()).await;
+//     println!("{report}");
+//
+// Rig's `Prompt` returns only text, so `metered` estimates tokens with the
+// cl100k tokenizer (prompt and response; not the preamble or tool schemas).
+// Code holding exact counts, such as a raw completion response's usage, can
+// call `record_usage` from inside an observed stage instead. Each
+// `with_report` call gets its own report, so concurrent runs don't mix.
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StageStats {
+    pub stage: &'static str,
+    pub calls: u32,
+    pub failures: u32,
+    pub retries: u32,
+    /// Summed over calls; concurrent calls overlap
+    pub latency: Duration,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// `None` when a model it used has no price
+    pub cost_usd: Option<f64>,
+    #[serde(skip)]
+    models: HashMap<String, (u64, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineReport {
+    pub elapsed: Duration,
+    /// In the order stages first ran
+    pub stages: Vec<StageStats>,
+}
+
+impl PipelineReport {
+    pub fn stage(&self, name: &str) -> Option<&StageStats> {
+        self.stages.iter().find(|s| s.stage == name)
+    }
+
+    /// `None` if any stage's cost is unknown
+    pub fn cost_usd(&self) -> Option<f64> {
+        self.stages.iter().map(|s| s.cost_usd).sum()
+    }
+}
+
+impl fmt::Display for PipelineReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<20} {:>5} {:>5} {:>7} {:>10} {:>9} {:>9} {:>9}", "stage", "calls", "fail", "retries", "latency", "in tok", "out tok", "usd")?;
+        for s in &self.stages {
+            let cost = s.cost_usd.map_or("?".to_string(), |usd| format!("{usd:.4}"));
+            writeln!(
+                f,
+                "{:<20} {:>5} {:>5} {:>7} {:>10.2?} {:>9} {:>9} {:>9}",
+                s.stage, s.calls, s.failures, s.retries, s.latency, s.input_tokens, s.output_tokens, cost
+            )?;
+        }
+        let cost = self.cost_usd().map_or("unknown".to_string(), |usd| format!("${usd:.4}"));
+        write!(f, "total {:.2?}, {cost}", self.elapsed)
+    }
+}
+
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<StageStats>>>);
+
+impl Recorder {
+    fn update(&self, stage: &'static str, f: impl FnOnce(&mut StageStats)) {
+        let mut stages = self.0.lock().unwrap();
+        let index = match stages.iter().position(|s| s.stage == stage) {
+            Some(index) => index,
+            None => {
+                stages.push(StageStats { stage, ..Default::default() });
+                stages.len() - 1
+            }
+        };
+        f(&mut stages[index]);
+    }
+
+    fn finish(&self, elapsed: Duration, prices: &PriceTable) -> PipelineReport {
+        let mut stages = self.0.lock().unwrap().clone();
+        for stats in &mut stages {
+            stats.cost_usd = stats.models.iter()
+                .map(|(model, (input, output))| prices.cost(model, *input, *output))
+                .sum();
+        }
+        PipelineReport { elapsed, stages }
+    }
+}
+
+#[derive(Clone)]
+struct ReportScope {
+    recorder: Recorder,
+    /// The innermost observed stage running
+    stage: Option<&'static str>,
+}
+
+tokio::task_local! {
+    static REPORT: ReportScope;
+}
+
+/// Update `stage`'s stats, if a report is being collected
+fn record(stage: &'static str, f: impl FnOnce(&mut StageStats)) {
+    let _ = REPORT.try_with(|scope| scope.recorder.update(stage, f));
+}
+
+/// Update the innermost observed stage's stats, if any
+fn record_current(f: impl FnOnce(&mut StageStats)) {
+    let _ = REPORT.try_with(|scope| {
+        if let Some(stage) = scope.stage {
+            scope.recorder.update(stage, f);
+        }
+    });
+}
+
+/// Attribute token usage to the observed stage that is running
+pub fn record_usage(model: &str, input_tokens: u64, output_tokens: u64) {
+    record_current(|stats| {
+        stats.input_tokens += input_tokens;
+        stats.output_tokens += output_tokens;
+        let totals = stats.models.entry(model.to_string()).or_default();
+        totals.0 += input_tokens;
+        totals.1 += output_tokens;
+    });
+}
+
+/// Run `op` once and report what each observed stage inside it did
+pub async fn with_report<P: Op>(op: &P, input: P::Input, prices: &PriceTable) -> (P::Output, PipelineReport) {
+    let recorder = Recorder::default();
+    let started = Instant::now();
+    let output = REPORT.scope(ReportScope { recorder: recorder.clone(), stage: None }, op.call(input)).await;
+    (output, recorder.finish(started.elapsed(), prices))
+}
+
+pub struct Observed<P> {
+    stage: &'static str,
+    op: P,
+}
+
+pub fn observe<P>(stage: &'static str, op: P) -> Observed<P> {
+    Observed { stage, op }
+}
+
+impl<P, T, E> Op for Observed<P>
+where
+    P: Op<Output = Result<T, E>>,
+    T: Send + Sync,
+    E: Send + Sync,
+{
+    type Input = P::Input;
+    type Output = Result<T, E>;
+
+    async fn call(&self, input: P::Input) -> Result<T, E> {
+        let span = tracing::info_span!("pipeline_stage", stage = self.stage, latency_ms = tracing::field::Empty, outcome = tracing::field::Empty);
+        let started = Instant::now();
+        let result = match REPORT.try_with(Clone::clone) {
+            Ok(scope) => {
+                let scope = ReportScope { stage: Some(self.stage), ..scope };
+                REPORT.scope(scope, self.op.call(input)).instrument(span.clone()).await
+            }
+            Err(_) => self.op.call(input).instrument(span.clone()).await,
+        };
+        let elapsed = started.elapsed();
+        span.record("latency_ms", elapsed.as_millis() as u64);
+        span.record("outcome", if result.is_ok() { "ok" } else { "error" });
+        span.in_scope(|| tracing::debug!(?elapsed, ok = result.is_ok(), "pipeline stage finished"));
+        record(self.stage, |stats| {
+            stats.calls += 1;
+            stats.latency += elapsed;
+            if result.is_err() {
+                stats.failures += 1;
+            }
+        });
+        result
+    }
+}
+
+/// A prompt op that records estimated token usage for `model`
+pub struct Metered<A> {
+    model: String,
+    agent: A,
+}
+
+pub fn metered<A: Prompt>(model: impl Into<String>, agent: A) -> Metered<A> {
+    Metered { model: model.into(), agent }
+}
+
+impl<A: Prompt + Send + Sync> Op for Metered<A> {
+    type Input = String;
+    type Output = Result<String, PromptError>;
+
+    async fn call(&self, prompt: String) -> Result<String, PromptError> {
+        let response = self.agent.prompt(prompt.as_str()).await?;
+        record_usage(&self.model, TokenSizer.size(&prompt) as u64, TokenSizer.size(&response) as u64);
+        Ok(response)
+    }
+}
+
+/// Summarize then title a document, and print where the time and money went
+pub async fn observed_pipeline() -> Result<()> {
+    let client = CLIENTS.openai();
+    let summary = observe(
+        "summary",
+        retry(
+            pipeline::new()
+                .chain(metered("gpt-4o", client.agent("gpt-4o").preamble(SUMMARY_PREAMBLE).build()))
+                .map_err(at("summary")),
+            ProviderPolicy::default(),
+        ),
+    );
+    let title = Arc::new(observe(
+        "title",
+        pipeline::new()
+            .map(|summary: String| format!("Write a title for this summary. Reply with the title only.\n\n{summary}"))
+            .chain(metered("gpt-4o-mini", client.agent("gpt-4o-mini").build()))
+            .map_err(at("title")),
+    ));
+    let flow = pipeline::new().chain(summary).and_then(move |summary: String| {
+        let title = title.clone();
+        async move { Ok((title.call(summary.clone()).await?, summary)) }
+    });
+
+    let text = std::fs::read_to_string("README.md")?;
+    let (output, report) = with_report(&flow, text, &PriceTable::default()).await;
+    println!("{report}");
+    let (title, summary) = output?;
+    println!("{title}\n\n{summary}");
+    Ok(())
+}
+
+// =============================================================================
+// STREAMING FINAL STAGE
+// =============================================================================
+
+// In a multi-stage flow only the last stage produces text a person reads,
+// so only it needs to stream. `StreamThrough` runs the earlier stages
+// (retrieval, classification, prompt building) to completion, hands back
+// whatever they found, then streams the final agent's tokens:
+//
+//     let answer = StreamThrough::new(prepare, agent);
+//     let mut streaming = answer.stream(question).await?;
+//     show_sources(&streaming.context);           // before the first token
+//     while let Some(token) = streaming.tokens.next().await {
+//         print!("{}", token?);
+//     }
+//
+// The user waits for the eager stages plus the answer model's first token,
+// instead of for the whole answer.
+
+/// What the eager stages produce: data for the caller, and the final prompt
+pub struct Prepared<C> {
+    pub context: C,
+    pub prompt: String,
+}
+
+pub struct Streaming<C> {
+    pub context: C,
+    pub tokens: BoxStream<'static, Result<String, StageError>>,
+}
+
+pub struct StreamThrough<P, A> {
+    prepare: P,
+    agent: A,
+}
+
+impl<P, A, C> StreamThrough<P, A>
+where
+    P: Op<Output = Result<Prepared<C>, StageError>>,
+    A: StreamingPrompt + Send + Sync,
+    C: Send + Sync,
+{
+    pub fn new(prepare: P, agent: A) -> Self {
+        Self { prepare, agent }
+    }
+
+    pub async fn stream(&self, input: P::Input) -> Result<Streaming<C>, StageError> {
+        let started = Instant::now();
+        let prepared = self.prepare.call(input).await?;
+        tracing::debug!(elapsed = ?started.elapsed(), "eager stages done; streaming final stage");
+        let tokens = self.agent
+            .stream_prompt(prepared.prompt.as_str())
+            .await
+            .map_err(at("stream"))?
+            .map(|chunk| chunk.map(|text| text.to_string()).map_err(at("stream")))
+            .boxed();
+        Ok(Streaming { context: prepared.context, tokens })
+    }
+}
+
+/// Retrieve eagerly, show the sources, then stream the answer
+pub async fn streamed_answer(question: &str) -> Result<()> {
+    let client = CLIENTS.openai();
+    let store = Arc::new(InMemoryVectorStore::load("index.json")?);
+    let retriever: Arc<dyn Retriever> =
+        Arc::new(StoreIndex::new(store, client.embedding_model(rig::providers::openai::TEXT_EMBEDDING_3_SMALL)));
+
+    let prepare = pipeline::new().then(move |question: String| {
+        let retriever = retriever.clone();
+        async move {
+            let hits = retriever.retrieve(&question, 5).await.map_err(at("retrieve"))?;
+            let passages: String = hits.iter().enumerate()
+                .map(|(i, hit)| format!("[{}] {}\n\n", i + 1, hit.document.text))
+                .collect();
+            Ok(Prepared {
+                context: hits.iter().map(|hit| hit.document.id.clone()).collect::<Vec<_>>(),
+                prompt: format!("Passages:\n{passages}Question: {question}"),
+            })
+        }
+    });
+    let agent = client
+        .agent("gpt-4o")
+        .preamble("Answer from the passages only and cite them as [n]. Say so if they don't contain the answer.")
+        .build();
+    let answer = StreamThrough::new(prepare, agent);
+
+    let mut streaming = answer.stream(question.to_string()).await?;
+    println!("Sources: {}", streaming.context.join(", "));
+    while let Some(token) = streaming.tokens.next().await {
+        print!("{}", token?);
+    }
+    println!();
+    Ok(())
+}
+
+// =============================================================================
+// HUMAN REVIEW
+// =============================================================================
+
+// `ReviewGate` pauses the pipeline on an intermediate value (an outline, an
+// extracted record, a drafted email) until a person approves, edits, or
+// rejects it. Approval continues with the value, an edit continues with the
+// edited value, and a rejection fails the stage:
+//
+//     let gate = ReviewGate::new("outline", TerminalReviewer);
+//     let outline = gate.call(outline).await?;
+//
+// For a web or chat UI, `ChannelReviewer` sends each request with a reply
+// channel to whatever task serves the UI:
+//
+//     let (reviewer, mut requests) = ChannelReviewer::new(Duration::from_secs(3600));
+//     tokio::spawn(async move {
+//         while let Some(request) = requests.recv().await {
+//             let decision = ui.ask(request.stage, &request.value).await;
+//             let _ = request.respond.send(decision);
+//         }
+//     });
+
+pub enum Decision<T> {
+    Approve,
+    Edit(T),
+    Reject(String),
+}
+
+pub trait Reviewer<T>: Send + Sync {
+    fn review<'a>(&'a self, stage: &'static str, value: &'a T) -> BoxFuture<'a, Decision<T>>;
+}
+
+pub struct ReviewGate<T> {
+    stage: &'static str,
+    reviewer: Arc<dyn Reviewer<T>>,
+}
+
+impl<T> ReviewGate<T> {
+    pub fn new(stage: &'static str, reviewer: impl Reviewer<T> + 'static) -> Self {
+        Self { stage, reviewer: Arc::new(reviewer) }
+    }
+}
+
+impl<T: Send + Sync> Op for ReviewGate<T> {
+    type Input = T;
+    type Output = Result<T, StageError>;
+
+    async fn call(&self, value: T) -> Result<T, StageError> {
+        let decision = self.reviewer.review(self.stage, &value).await;
+        match decision {
+            Decision::Approve => Ok(value),
+            Decision::Edit(edited) => {
+                tracing::info!(stage = self.stage, "reviewer edited the value");
+                Ok(edited)
+            }
+            Decision::Reject(reason) => Err(at(self.stage)(anyhow::anyhow!("rejected in review: {reason}"))),
+        }
+    }
+}
+
+/// A synchronous callback, for rules or tests: `Callback(|stage, value| Decision::Approve)`
+pub struct Callback<F>(pub F);
+
+impl<T, F> Reviewer<T> for Callback<F>
+where
+    T: Send + Sync,
+    F: Fn(&'static str, &T) -> Decision<T> + Send + Sync,
+{
+    fn review<'a>(&'a self, stage: &'static str, value: &'a T) -> BoxFuture<'a, Decision<T>> {
+        let decision = (self.0)(stage, value);
+        Box::pin(async move { decision })
+    }
+}
+
+/// Shows the value as JSON on stdout; `e` opens it in `$EDITOR`
+pub struct TerminalReviewer;
+
+impl<T> Reviewer<T> for TerminalReviewer
+where
+    T: Serialize + DeserializeOwned + Send + Sync,
+{
+    fn review<'a>(&'a self, stage: &'static str, value: &'a T) -> BoxFuture<'a, Decision<T>> {
+        Box::pin(async move {
+            let json = match serde_json::to_string_pretty(value) {
+                Ok(json) => json,
+                Err(e) => return Decision::Reject(format!("cannot show value for review: {e}")),
+            };
+            let answer = tokio::task::spawn_blocking(move || ask_terminal(stage, json)).await;
+            match answer {
+                Ok(Ok(Decision::Edit(edited))) => match serde_json::from_str(&edited) {
+                    Ok(edited) => Decision::Edit(edited),
+                    Err(e) => Decision::Reject(format!("edited value is not valid: {e}")),
+                },
+                Ok(Ok(Decision::Approve)) => Decision::Approve,
+                Ok(Ok(Decision::Reject(reason))) => Decision::Reject(reason),
+                Ok(Err(e)) => Decision::Reject(format!("review failed: {e}")),
+                Err(e) => Decision::Reject(format!("review failed: {e}")),
+            }
+        })
+    }
+}
+
+/// Blocking prompt loop; edits come back as JSON text
+fn ask_terminal(stage: &str, mut json: String) -> Result<Decision<String>> {
+    let mut edited = false;
+    loop {
+        print!("\n--- review: {stage} ---\n{json}\n[a]pprove, [e]dit, [r]eject? ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "a" | "approve" if edited => return Ok(Decision::Edit(json)),
+            "a" | "approve" => return Ok(Decision::Approve),
+            "e" | "edit" => {
+                let path = std::env::temp_dir().join(format!("review-{stage}-{}.json", std::process::id()));
+                std::fs::write(&path, &json)?;
+                let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+                let status = std::process::Command::new(&editor).arg(&path).status()?;
+                anyhow::ensure!(status.success(), "{editor} exited with {status}");
+                json = std::fs::read_to_string(&path)?;
+                std::fs::remove_file(&path).ok();
+                // Show the edit and ask again, so it's approved as seen
+                edited = true;
+            }
+            "r" | "reject" => {
+                print!("Reason: ");
+                std::io::stdout().flush()?;
+                let mut reason = String::new();
+                std::io::stdin().read_line(&mut reason)?;
+                return Ok(Decision::Reject(reason.trim().to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+pub struct ReviewRequest<T> {
+    pub stage: &'static str,
+    pub value: T,
+    pub respond: oneshot::Sender<Decision<T>>,
+}
+
+/// Hands reviews to another task, such as a web handler or chat bot
+pub struct ChannelReviewer<T> {
+    requests: mpsc::Sender<ReviewRequest<T>>,
+    timeout: Duration,
+}
+
+impl<T> ChannelReviewer<T> {
+    /// The reviewer plus the receiving end for the UI; unanswered reviews
+    /// are rejected after `timeout`
+    pub fn new(timeout: Duration) -> (Self, mpsc::Receiver<ReviewRequest<T>>) {
+        let (requests, receiver) = mpsc::channel(16);
+        (Self { requests, timeout }, receiver)
+    }
+}
+
+impl<T> Reviewer<T> for ChannelReviewer<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn review<'a>(&'a self, stage: &'static str, value: &'a T) -> BoxFuture<'a, Decision<T>> {
+        Box::pin(async move {
+            let (respond, reply) = oneshot::channel();
+            let request = ReviewRequest { stage, value: value.clone(), respond };
+            if self.requests.send(request).await.is_err() {
+                return Decision::Reject("no reviewer is listening".to_string());
+            }
+            match tokio::time::timeout(self.timeout, reply).await {
+                Ok(Ok(decision)) => decision,
+                Ok(Err(_)) => Decision::Reject("reviewer dropped the request".to_string()),
+                Err(_) => Decision::Reject(format!("no review within {:?}", self.timeout)),
+            }
+        })
+    }
+}
+
+/// The article pipeline with a person approving or editing the outline
+pub async fn reviewed_article() -> Result<()> {
+    let client = CLIENTS.openai();
+    let outliner = client.extractor::<Outline>("gpt-4o-mini").preamble(OUTLINE_PREAMBLE).build();
+    let writer = Arc::new(client.agent("gpt-4o").preamble(WRITER_PREAMBLE).build());
+    let gate = Arc::new(ReviewGate::new("outline", TerminalReviewer));
+
+    let flow = pipeline::new()
+        .map(|brief: Brief| brief.outline_request())
+        .chain(agent_ops::extract(outliner))
+        .map_err(at("outline"))
+        .map_ok(Outline::normalize)
+        .and_then(move |outline: Outline| {
+            let (gate, writer) = (gate.clone(), writer.clone());
+            async move {
+                let outline = gate.call(outline).await?;
+                let body = writer.prompt(outline.draft_request()).await.map_err(at("draft"))?;
+                Ok(Article { outline, body })
+            }
+        });
+
+    let article = flow
+        .call(Brief { topic: "Zero-downtime Postgres migrations".into(), audience: "platform engineers".into() })
+        .await?;
+    println!("# {}\n\n{}", article.outline.title, article.body);
+    Ok(())
+}
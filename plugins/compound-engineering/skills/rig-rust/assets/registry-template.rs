@@ -0,0 +1,126 @@
+//! Tool Registry Templates for Rig
+//!
+//! Holds tools behind Rig's object-safe `ToolDyn` so they can be added and
+//! removed at runtime instead of being wired into each agent by hand.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use rig::completion::message::AssistantContent;
+use rig::completion::{CompletionModel, ToolDefinition};
+use rig::tool::{Tool, ToolDyn, ToolError};
+use rig::OneOrMany;
+
+use crate::config_template::CLIENTS;
+use crate::tool_template::{Calculator, HttpClient};
+
+// =============================================================================
+// REGISTRY
+// =============================================================================
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("Unknown tool: {0}")]
+    UnknownTool(String),
+
+    #[error("Tool {name} failed: {source}")]
+    Call { name: String, source: ToolError },
+}
+
+#[derive(Default, Clone)]
+pub struct ToolRegistry {
+    /// Ordered by name, so definitions go out in a stable order
+    tools: Arc<RwLock<BTreeMap<String, Arc<dyn ToolDyn>>>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a tool, returning the one it replaced (if any)
+    pub fn register<T: Tool + 'static>(&self, tool: T) -> Option<Arc<dyn ToolDyn>> {
+        self.register_dyn(Arc::new(tool))
+    }
+
+    pub fn register_dyn(&self, tool: Arc<dyn ToolDyn>) -> Option<Arc<dyn ToolDyn>> {
+        self.tools.write().unwrap().insert(tool.name(), tool)
+    }
+
+    pub fn unregister(&self, name: &str) -> Option<Arc<dyn ToolDyn>> {
+        self.tools.write().unwrap().remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ToolDyn>> {
+        self.tools.read().unwrap().get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.tools.read().unwrap().keys().cloned().collect()
+    }
+
+    /// All definitions for the current prompt, ready for a completion request
+    pub async fn definitions(&self, prompt: &str) -> Vec<ToolDefinition> {
+        // Snapshot so the lock isn't held across awaits
+        let tools: Vec<_> = self.tools.read().unwrap().values().cloned().collect();
+        futures::future::join_all(tools.iter().map(|t| t.definition(prompt.to_string()))).await
+    }
+
+    /// Route a tool call to the tool registered under `name`
+    pub async fn call(&self, name: &str, args: serde_json::Value) -> Result<String, RegistryError> {
+        let tool = self.get(name).ok_or_else(|| RegistryError::UnknownTool(name.to_string()))?;
+        tool.call(args.to_string()).await.map_err(|source| RegistryError::Call {
+            name: name.to_string(),
+            source,
+        })
+    }
+
+    /// Resolve a model's reply: text passes through, tool calls are
+    /// dispatched, and the outputs are joined one per line
+    pub async fn route(&self, choice: OneOrMany<AssistantContent>) -> Result<String, RegistryError> {
+        let mut outputs = Vec::new();
+        for content in choice {
+            match content {
+                AssistantContent::Text(text) => outputs.push(text.text),
+                AssistantContent::ToolCall(call) => {
+                    outputs.push(self.call(&call.function.name, call.function.arguments).await?);
+                }
+                // Reasoning, on rig versions that surface it
+                #[allow(unreachable_patterns)]
+                _ => {}
+            }
+        }
+        Ok(outputs.join("\n"))
+    }
+}
+
+// =============================================================================
+// USAGE
+// =============================================================================
+
+/// Completion with every registered tool attached
+pub async fn registry_agent() -> Result<()> {
+    let client = CLIENTS.openai();
+    let model = client.completion_model("gpt-4o");
+
+    let registry = ToolRegistry::new();
+    registry.register(Calculator::default());
+    registry.register(HttpClient::default());
+
+    // Tools can be dropped at runtime, e.g. when network access is disabled
+    registry.unregister("http_request");
+
+    let prompt = "What is 42 * 17?";
+    let response = model
+        .completion_request(prompt)
+        .preamble("You are a helpful assistant.".to_string())
+        .tools(registry.definitions(prompt).await)
+        .send()
+        .await?;
+
+    let output = registry.route(response.choice).await?;
+    println!("{}", output);
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+//! Retrieval Evaluation Templates for Rig
+//!
+//! Score any `Retriever` against a labeled set of (query, relevant documents)
+//! with recall@k, MRR, and nDCG@k, so chunking, embedding, and pipeline
+//! choices can be compared by number instead of by eyeballing answers.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::chunking_template::ChunkConfig;
+use crate::config_template::CLIENTS;
+use crate::loader_template::{Indexer, MarkdownLoader};
+use crate::rag_template::{Hit, InMemoryVectorStore, Metric, Retriever, StoreIndex};
+
+// =============================================================================
+// DATASET
+// =============================================================================
+
+// One JSON object per line:
+//     {"query": "How do I rotate API keys?", "relevant": ["docs/security.md"]}
+//     {"query": "What does E0502 mean?", "relevant": ["docs/errors.md#3", "docs/errors.md#4"]}
+//
+// Label by source file when comparing chunking settings (chunk ids change
+// with the chunk size); label by chunk id to grade one fixed index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub query: String,
+    pub relevant: Vec<String>,
+}
+
+pub fn load_cases(path: impl AsRef<Path>) -> Result<Vec<EvalCase>> {
+    let path = path.as_ref();
+    let raw = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+    raw.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| anyhow::anyhow!("{}:{}: {e}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// What the labels in `relevant` name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Granularity {
+    /// Source files (the `source` metadata the loaders set); several chunks
+    /// of one file count once, at the rank of the first
+    #[default]
+    Source,
+    /// Exact document (chunk) ids
+    Chunk,
+}
+
+impl Granularity {
+    /// Retrieved labels in rank order, without repeats
+    fn labels(self, hits: &[Hit]) -> Vec<String> {
+        let mut seen = HashSet::new();
+        hits.iter()
+            .map(|hit| match self {
+                Self::Chunk => hit.document.id.clone(),
+                Self::Source => hit.document.metadata.get("source").and_then(Value::as_str)
+                    .map(String::from)
+                    .unwrap_or_else(|| hit.document.id.clone()),
+            })
+            .filter(|label| seen.insert(label.clone()))
+            .collect()
+    }
+}
+
+// =============================================================================
+// METRICS
+// =============================================================================
+
+/// Share of the relevant labels found in the first `k` retrieved
+pub fn recall_at_k(retrieved: &[String], relevant: &HashSet<&str>, k: usize) -> f64 {
+    if relevant.is_empty() {
+        return 0.0;
+    }
+    let found = retrieved.iter().take(k).filter(|r| relevant.contains(r.as_str())).count();
+    found as f64 / relevant.len() as f64
+}
+
+/// 1 / rank of the first relevant label, 0 if none was retrieved
+pub fn reciprocal_rank(retrieved: &[String], relevant: &HashSet<&str>) -> f64 {
+    retrieved.iter()
+        .position(|r| relevant.contains(r.as_str()))
+        .map_or(0.0, |i| 1.0 / (i + 1) as f64)
+}
+
+/// Binary-relevance nDCG: 1 when every relevant label that fits in `k` is
+/// ranked ahead of everything else
+pub fn ndcg_at_k(retrieved: &[String], relevant: &HashSet<&str>, k: usize) -> f64 {
+    let gain = |rank: usize| 1.0 / (rank as f64 + 2.0).log2();
+    let dcg: f64 = retrieved.iter().take(k)
+        .enumerate()
+        .filter(|(_, r)| relevant.contains(r.as_str()))
+        .map(|(i, _)| gain(i))
+        .sum();
+    let ideal: f64 = (0..relevant.len().min(k)).map(gain).sum();
+    if ideal == 0.0 { 0.0 } else { dcg / ideal }
+}
+
+// =============================================================================
+// HARNESS
+// =============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub query: String,
+    pub retrieved: Vec<String>,
+    pub recall: f64,
+    pub reciprocal_rank: f64,
+    pub ndcg: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    pub k: usize,
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    pub ndcg_at_k: f64,
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    /// Queries where nothing relevant made the top k: the ones to look at first
+    pub fn misses(&self) -> impl Iterator<Item = &CaseResult> {
+        self.cases.iter().filter(|c| c.recall == 0.0)
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "recall@{k} {:.3}  MRR {:.3}  nDCG@{k} {:.3}  ({} queries, {} misses)",
+            self.recall_at_k,
+            self.mrr,
+            self.ndcg_at_k,
+            self.cases.len(),
+            self.misses().count(),
+            k = self.k,
+        )
+    }
+}
+
+pub struct Evaluator {
+    k: usize,
+    granularity: Granularity,
+    concurrency: usize,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self { k: 10, granularity: Granularity::default(), concurrency: 4 }
+    }
+}
+
+impl Evaluator {
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    pub fn granularity(mut self, granularity: Granularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    /// Queries in flight at once; keep it low when the retriever calls a model
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn evaluate(&self, retriever: &dyn Retriever, cases: &[EvalCase]) -> Result<EvalReport> {
+        anyhow::ensure!(!cases.is_empty(), "no evaluation cases");
+        // Under Source granularity several chunks can collapse into one
+        // label, so fetch extra to still fill k distinct labels
+        let fetch = match self.granularity {
+            Granularity::Chunk => self.k,
+            Granularity::Source => self.k * 3,
+        };
+        let results: Vec<CaseResult> = stream::iter(cases)
+            .map(|case| async move {
+                let hits = retriever.retrieve(&case.query, fetch).await?;
+                let mut retrieved = self.granularity.labels(&hits);
+                retrieved.truncate(self.k);
+                let relevant: HashSet<&str> = case.relevant.iter().map(String::as_str).collect();
+                anyhow::Ok(CaseResult {
+                    query: case.query.clone(),
+                    recall: recall_at_k(&retrieved, &relevant, self.k),
+                    reciprocal_rank: reciprocal_rank(&retrieved, &relevant),
+                    ndcg: ndcg_at_k(&retrieved, &relevant, self.k),
+                    retrieved,
+                })
+            })
+            .buffered(self.concurrency)
+            .try_collect()
+            .await?;
+
+        let mean = |f: fn(&CaseResult) -> f64| results.iter().map(f).sum::<f64>() / results.len() as f64;
+        Ok(EvalReport {
+            k: self.k,
+            recall_at_k: mean(|c| c.recall),
+            mrr: mean(|c| c.reciprocal_rank),
+            ndcg_at_k: mean(|c| c.ndcg),
+            cases: results,
+        })
+    }
+
+    /// Evaluate several configurations on the same cases, one line each
+    pub async fn compare(&self, candidates: &[(&str, &dyn Retriever)], cases: &[EvalCase]) -> Result<Vec<(String, EvalReport)>> {
+        let mut reports = Vec::with_capacity(candidates.len());
+        for (name, retriever) in candidates {
+            let report = self.evaluate(*retriever, cases).await?;
+            println!("{name:<24} {report}");
+            reports.push((name.to_string(), report));
+        }
+        Ok(reports)
+    }
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// Index `docs/` at two chunk sizes and compare them on `eval/retrieval.jsonl`
+pub async fn compare_chunk_sizes() -> Result<()> {
+    let cases = load_cases("eval/retrieval.jsonl")?;
+    let model = CLIENTS.openai().embedding_model(rig::providers::openai::TEXT_EMBEDDING_3_SMALL);
+
+    let mut indexes = Vec::new();
+    for (max, overlap) in [(256, 32), (1024, 128)] {
+        let store = Arc::new(InMemoryVectorStore::new(Metric::Cosine));
+        Indexer::new(store.clone(), model.clone())
+            .loader(MarkdownLoader::default().chunking(ChunkConfig::tokens(max, overlap)?))
+            .run(Path::new("docs"))
+            .await?;
+        indexes.push((format!("markdown {max}/{overlap}"), StoreIndex::new(store, model.clone())));
+    }
+
+    let candidates: Vec<(&str, &dyn Retriever)> = indexes.iter()
+        .map(|(name, index)| (name.as_str(), index as &dyn Retriever))
+        .collect();
+    let reports = Evaluator::default().k(5).compare(&candidates, &cases).await?;
+
+    for (name, report) in &reports {
+        for miss in report.misses() {
+            println!("{name}: missed {:?} (got {:?})", miss.query, miss.retrieved);
+        }
+    }
+    Ok(())
+}
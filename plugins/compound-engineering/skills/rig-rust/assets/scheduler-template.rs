@@ -0,0 +1,291 @@
+//! Scheduler Templates for Rig
+//!
+//! Agent jobs that run on a clock instead of on request: a digest of the
+//! day's notes every weekday morning, a re-index of the docs every night.
+//! Each job has a cron schedule and a timeout; a tick that arrives while the
+//! job's previous run is still going is skipped rather than stacked, so a
+//! slow night can't pile up overlapping re-indexes.
+//!
+//! ```toml
+//! [dependencies]
+//! tokio-cron-scheduler = "0.13"
+//! ```
+//!
+//! Schedules have six fields, seconds first, and are evaluated in UTC:
+//! ```text
+//! sec  min  hour  day-of-month  month  day-of-week
+//! 0    0    7     *             *      Mon-Fri       07:00 on weekdays
+//! 0    30   2     *             *      *             02:30 every day
+//! 0    */15 *     *             *      *             every 15 minutes
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::Instrument;
+
+use crate::config_template::{BoxedAgent, CLIENTS};
+use crate::loader_template::Indexer;
+use crate::pipeline_template::{MapReduce, RawDocument};
+use crate::rag_template::{InMemoryVectorStore, Metric};
+
+// =============================================================================
+// JOB STATUS
+// =============================================================================
+
+#[derive(Debug, Clone, Default)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    /// Runs that finished without error
+    pub runs: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    /// Ticks dropped because the previous run was still going
+    pub skipped: u64,
+    pub last_started: Option<SystemTime>,
+    pub last_duration: Option<Duration>,
+    /// Cleared by the next successful run
+    pub last_error: Option<String>,
+}
+
+/// Shared view of every job's counters, readable while the scheduler runs
+/// (for a health endpoint or a status command)
+#[derive(Clone, Default)]
+pub struct Statuses(Arc<Mutex<HashMap<String, JobStatus>>>);
+
+impl Statuses {
+    /// Sorted by job name
+    pub fn snapshot(&self) -> Vec<JobStatus> {
+        let mut statuses: Vec<JobStatus> = self.0.lock().unwrap().values().cloned().collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses
+    }
+
+    fn update(&self, job: &str, f: impl FnOnce(&mut JobStatus)) {
+        if let Some(status) = self.0.lock().unwrap().get_mut(job) {
+            f(status);
+        }
+    }
+}
+
+// =============================================================================
+// SCHEDULER
+// =============================================================================
+
+// Usage:
+//     let scheduler = Scheduler::new()
+//         .job("daily-digest", "0 0 7 * * Mon-Fri", Duration::from_secs(900), || daily_digest())
+//         .job("nightly-reindex", "0 30 2 * * *", Duration::from_secs(7200), || reindex());
+//     let statuses = scheduler.statuses();
+//     scheduler.run(async { tokio::signal::ctrl_c().await.ok(); }).await?;
+//
+// A run that hits its timeout is dropped at its next `.await`, so jobs should
+// leave things consistent at await points (write to a temp file, then rename).
+
+type JobFn = Box<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+struct ScheduledJob {
+    name: String,
+    schedule: String,
+    timeout: Duration,
+    run: JobFn,
+    /// Held for the length of a run; a tick that can't take it is skipped
+    running: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<Arc<ScheduledJob>>,
+    statuses: Statuses,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `run` is called on every tick of `schedule`; names must be unique
+    pub fn job<F, Fut>(mut self, name: impl Into<String>, schedule: impl Into<String>, timeout: Duration, run: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let (name, schedule) = (name.into(), schedule.into());
+        self.statuses.0.lock().unwrap().insert(
+            name.clone(),
+            JobStatus { name: name.clone(), schedule: schedule.clone(), ..Default::default() },
+        );
+        self.jobs.push(Arc::new(ScheduledJob {
+            name,
+            schedule,
+            timeout,
+            run: Box::new(move || Box::pin(run())),
+            running: Arc::new(tokio::sync::Mutex::new(())),
+        }));
+        self
+    }
+
+    pub fn statuses(&self) -> Statuses {
+        self.statuses.clone()
+    }
+
+    /// Run one job now, outside its schedule, with the same overlap guard and
+    /// timeout; for a "run now" command or trying a job out
+    pub async fn run_now(&self, name: &str) -> Result<()> {
+        let job = self.jobs.iter()
+            .find(|job| job.name == name)
+            .ok_or_else(|| anyhow::anyhow!("no job named `{name}`"))?;
+        tick(job, &self.statuses).await.map_err(|e| anyhow::anyhow!("job `{name}` {e:#}"))
+    }
+
+    /// Tick jobs until `shutdown` resolves, then wait for runs in progress
+    pub async fn run(&self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        anyhow::ensure!(!self.jobs.is_empty(), "scheduler has no jobs");
+        let mut names = HashSet::new();
+        for job in &self.jobs {
+            anyhow::ensure!(names.insert(job.name.as_str()), "job `{}` is defined twice", job.name);
+        }
+
+        let mut scheduler = JobScheduler::new().await.map_err(|e| anyhow::anyhow!("cannot create scheduler: {e}"))?;
+        for job in &self.jobs {
+            let (scheduled, statuses) = (job.clone(), self.statuses.clone());
+            let cron = Job::new_async(job.schedule.as_str(), move |_, _| {
+                let (job, statuses) = (scheduled.clone(), statuses.clone());
+                // Outcomes are recorded in `statuses` and logged by `tick`
+                Box::pin(async move {
+                    let _ = tick(&job, &statuses).await;
+                })
+            })
+            .map_err(|e| anyhow::anyhow!("job `{}` has an invalid schedule `{}`: {e}", job.name, job.schedule))?;
+            scheduler.add(cron).await.map_err(|e| anyhow::anyhow!("cannot add job `{}`: {e}", job.name))?;
+        }
+        scheduler.start().await.map_err(|e| anyhow::anyhow!("cannot start scheduler: {e}"))?;
+        tracing::info!(jobs = self.jobs.len(), "scheduler started");
+
+        shutdown.await;
+        scheduler.shutdown().await.map_err(|e| anyhow::anyhow!("cannot stop scheduler: {e}"))?;
+        // Each run in progress is bounded by its job's timeout
+        for job in &self.jobs {
+            let _idle = job.running.lock().await;
+        }
+        tracing::info!("scheduler stopped");
+        Ok(())
+    }
+}
+
+/// Run `job` once, unless its previous run is still going; the error says
+/// whether it was skipped, failed, or timed out
+async fn tick(job: &ScheduledJob, statuses: &Statuses) -> Result<()> {
+    let Ok(_running) = job.running.try_lock() else {
+        tracing::warn!(job = %job.name, "previous run still going; skipping this tick");
+        statuses.update(&job.name, |s| s.skipped += 1);
+        anyhow::bail!("skipped: the previous run is still going");
+    };
+
+    statuses.update(&job.name, |s| s.last_started = Some(SystemTime::now()));
+    let started = Instant::now();
+    let span = tracing::info_span!("scheduled_job", job = %job.name);
+    let outcome = tokio::time::timeout(job.timeout, (job.run)()).instrument(span).await;
+    let elapsed = started.elapsed();
+
+    statuses.update(&job.name, |s| {
+        s.last_duration = Some(elapsed);
+        match &outcome {
+            Ok(Ok(())) => {
+                s.runs += 1;
+                s.last_error = None;
+            }
+            Ok(Err(e)) => {
+                s.failures += 1;
+                s.last_error = Some(format!("{e:#}"));
+            }
+            Err(_) => {
+                s.timeouts += 1;
+                s.last_error = Some(format!("timed out after {:?}", job.timeout));
+            }
+        }
+    });
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!(job = %job.name, ?elapsed, "job finished");
+            Ok(())
+        }
+        Ok(Err(e)) => {
+            tracing::error!(job = %job.name, ?elapsed, error = %e, "job failed");
+            Err(anyhow::anyhow!("failed: {e:#}"))
+        }
+        Err(_) => {
+            tracing::error!(job = %job.name, timeout = ?job.timeout, "job timed out");
+            Err(anyhow::anyhow!("timed out after {:?}", job.timeout))
+        }
+    }
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// Summarize everything in `notes/` into `digests/latest.md`
+async fn daily_digest(summarize: &MapReduce) -> Result<()> {
+    let notes: Vec<String> = RawDocument::read_dir("notes")?.into_iter().map(|doc| doc.text).collect();
+    anyhow::ensure!(!notes.is_empty(), "no notes to digest");
+    let digest = summarize.summarize(&notes).await?;
+
+    std::fs::create_dir_all("digests")?;
+    // Written whole, then renamed, so a timed-out run never leaves half a digest
+    std::fs::write("digests/latest.md.tmp", digest)?;
+    std::fs::rename("digests/latest.md.tmp", "digests/latest.md")?;
+    Ok(())
+}
+
+/// Incremental re-index of `docs/`: only changed files are embedded
+async fn nightly_reindex() -> Result<()> {
+    let model = CLIENTS.openai().embedding_model(rig::providers::openai::TEXT_EMBEDDING_3_SMALL);
+    let store = Arc::new(InMemoryVectorStore::load_or_new("index.json", Metric::Cosine)?);
+    let stats = Indexer::new(store.clone(), model)
+        .manifest("index.manifest.json")
+        .run(Path::new("docs"))
+        .await?;
+    store.save("index.json")?;
+    tracing::info!(files = stats.files, unchanged = stats.unchanged, removed = stats.removed, "re-indexed docs");
+    Ok(())
+}
+
+pub async fn run_scheduled_jobs() -> Result<()> {
+    let summarize = Arc::new(
+        MapReduce::new(Box::new(BoxedAgent(CLIENTS.openai().agent("gpt-4o-mini").build())))
+            .reduce_prompt("Write a morning digest of these notes: decisions made, open questions, and \
+                what changed, each as a short bulleted section.\n\n{text}"),
+    );
+
+    let scheduler = Scheduler::new()
+        .job("daily-digest", "0 0 7 * * Mon-Fri", Duration::from_secs(15 * 60), move || {
+            let summarize = summarize.clone();
+            async move { daily_digest(&summarize).await }
+        })
+        .job("nightly-reindex", "0 30 2 * * *", Duration::from_secs(2 * 3600), nightly_reindex);
+
+    // Catch a broken job at deploy time rather than at 02:30
+    scheduler.run_now("nightly-reindex").await?;
+
+    let statuses = scheduler.statuses();
+    scheduler.run(async { tokio::signal::ctrl_c().await.ok(); }).await?;
+    for status in statuses.snapshot() {
+        tracing::info!(
+            job = %status.name,
+            runs = status.runs,
+            failures = status.failures,
+            timeouts = status.timeouts,
+            skipped = status.skipped,
+            "job totals"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+//! State Machine Templates for Rig
+//!
+//! An assistant that works in phases (ask questions, then draft, then take
+//! review feedback) as a typestate machine. Each phase is its own type, and
+//! a transition consumes the session and returns it in the next phase, so
+//! calling a method from the wrong phase is a compile error rather than a
+//! runtime check scattered through the prompt logic:
+//!
+//! ```text
+//! Gathering --start_drafting--> Drafting --write--> Reviewing --approve--> Done
+//!                                   ^                   |
+//!                                   +------revise-------+
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::config_template::{BoxedAgent, DynAgent, CLIENTS};
+
+// =============================================================================
+// PHASES
+// =============================================================================
+
+/// A phase of a `Session`; the name shows up in errors and logs
+pub trait Phase {
+    const NAME: &'static str;
+}
+
+pub struct Gathering {
+    answers: Vec<(String, String)>,
+    pending: Option<String>,
+    max_questions: usize,
+}
+
+pub struct Drafting {
+    requirements: String,
+    /// The rejected draft and the feedback on it, when revising
+    previous: Option<(String, String)>,
+    revisions: u32,
+}
+
+pub struct Reviewing {
+    requirements: String,
+    draft: String,
+    revisions: u32,
+}
+
+pub struct Done {
+    document: String,
+    revisions: u32,
+}
+
+impl Phase for Gathering {
+    const NAME: &'static str = "gathering";
+}
+
+impl Phase for Drafting {
+    const NAME: &'static str = "drafting";
+}
+
+impl Phase for Reviewing {
+    const NAME: &'static str = "reviewing";
+}
+
+impl Phase for Done {
+    const NAME: &'static str = "done";
+}
+
+// =============================================================================
+// SESSION
+// =============================================================================
+
+// Usage:
+//     let mut session = Session::start(agent, "a design doc for API rate limiting");
+//     while let Some(question) = session.next_question().await? {
+//         session.answer(ask_user(&question).await?)?;
+//     }
+//     let drafting = session.start_drafting().await?;
+//     let done = drafting.write().await?.approve();
+//
+//     drafting.approve()     // error[E0599]: no method named `approve` found for struct `Session<Drafting>`
+//
+// Transitions that call the model return `Stuck` on failure, which hands the
+// session back in the phase it was in, so the caller can retry:
+//     let drafting = match session.start_drafting().await {
+//         Ok(drafting) => drafting,
+//         Err(stuck) => { warn!("{stuck}"); session = stuck.session; continue; }
+//     };
+
+const GATHER_INSTRUCTIONS: &str = "You are collecting what you need to write the document \
+described by the goal. Ask the single most useful question you still need answered, in one \
+sentence. If the answers so far are enough to write a good first draft, reply with exactly READY.";
+
+const REQUIREMENTS_INSTRUCTIONS: &str = "Turn the goal and the answers below into a numbered \
+list of requirements for the document. Include every constraint the user stated; invent nothing.";
+
+const DRAFT_INSTRUCTIONS: &str = "Write the document in Markdown. Meet every requirement; where \
+one can't be met, say so in the document rather than leaving it out.";
+
+pub struct Session<S> {
+    agent: Arc<dyn DynAgent>,
+    goal: String,
+    state: S,
+}
+
+impl<S: Phase> Session<S> {
+    pub fn goal(&self) -> &str {
+        &self.goal
+    }
+
+    pub fn phase(&self) -> &'static str {
+        S::NAME
+    }
+
+    /// The only way to change phase: consume this session, build the next state from this one
+    fn transition<T: Phase>(self, next: impl FnOnce(S) -> T) -> Session<T> {
+        tracing::debug!(from = S::NAME, to = T::NAME, "session transition");
+        Session { agent: self.agent, goal: self.goal, state: next(self.state) }
+    }
+
+    async fn ask(&self, prompt: &str) -> Result<String> {
+        Ok(self.agent.prompt(prompt).await?.trim().to_string())
+    }
+}
+
+/// A model call failed during a transition; `session` is unchanged
+pub struct Stuck<S> {
+    pub session: Session<S>,
+    pub error: anyhow::Error,
+}
+
+impl<S: Phase> fmt::Display for Stuck<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "session stuck in {}: {:#}", S::NAME, self.error)
+    }
+}
+
+impl<S: Phase> fmt::Debug for Stuck<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stuck").field("phase", &S::NAME).field("error", &self.error).finish()
+    }
+}
+
+impl<S: Phase> std::error::Error for Stuck<S> {}
+
+impl Session<Gathering> {
+    pub fn start(agent: Box<dyn DynAgent>, goal: impl Into<String>) -> Self {
+        Self {
+            agent: agent.into(),
+            goal: goal.into(),
+            state: Gathering { answers: Vec::new(), pending: None, max_questions: 8 },
+        }
+    }
+
+    /// Questions to ask at most before drafting anyway
+    pub fn max_questions(mut self, max: usize) -> Self {
+        self.state.max_questions = max;
+        self
+    }
+
+    /// The next question for the user, or `None` once the model has enough
+    /// to draft (or the question budget is spent)
+    pub async fn next_question(&mut self) -> Result<Option<String>> {
+        anyhow::ensure!(self.state.pending.is_none(), "answer the pending question first");
+        if self.state.answers.len() >= self.state.max_questions {
+            return Ok(None);
+        }
+        let reply = self.ask(&format!("{GATHER_INSTRUCTIONS}\n\nGoal: {}\n\n{}", self.goal, self.transcript())).await?;
+        if reply.trim_end_matches('.').eq_ignore_ascii_case("ready") {
+            return Ok(None);
+        }
+        self.state.pending = Some(reply.clone());
+        Ok(Some(reply))
+    }
+
+    pub fn answer(&mut self, answer: impl Into<String>) -> Result<()> {
+        let question = self.state.pending.take().ok_or_else(|| anyhow::anyhow!("no question is waiting for an answer"))?;
+        self.state.answers.push((question, answer.into()));
+        Ok(())
+    }
+
+    /// Condense the answers into requirements. An unanswered question is dropped
+    pub async fn start_drafting(mut self) -> Result<Session<Drafting>, Stuck<Gathering>> {
+        self.state.pending = None;
+        let prompt = format!("{REQUIREMENTS_INSTRUCTIONS}\n\nGoal: {}\n\n{}", self.goal, self.transcript());
+        match self.ask(&prompt).await {
+            Ok(requirements) => Ok(self.transition(|_| Drafting { requirements, previous: None, revisions: 0 })),
+            Err(error) => Err(Stuck { session: self, error }),
+        }
+    }
+
+    fn transcript(&self) -> String {
+        if self.state.answers.is_empty() {
+            return "No questions asked yet.".to_string();
+        }
+        self.state.answers.iter()
+            .map(|(question, answer)| format!("Q: {question}\nA: {answer}"))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl Session<Drafting> {
+    pub fn requirements(&self) -> &str {
+        &self.state.requirements
+    }
+
+    pub async fn write(self) -> Result<Session<Reviewing>, Stuck<Drafting>> {
+        let mut prompt = format!("{DRAFT_INSTRUCTIONS}\n\nGoal: {}\n\nRequirements:\n{}", self.goal, self.state.requirements);
+        if let Some((draft, feedback)) = &self.state.previous {
+            prompt.push_str(&format!("\n\nPrevious draft:\n{draft}\n\nReviewer feedback to address:\n{feedback}"));
+        }
+        match self.ask(&prompt).await {
+            Ok(draft) => Ok(self.transition(|Drafting { requirements, revisions, .. }| Reviewing { requirements, draft, revisions })),
+            Err(error) => Err(Stuck { session: self, error }),
+        }
+    }
+}
+
+impl Session<Reviewing> {
+    pub fn draft(&self) -> &str {
+        &self.state.draft
+    }
+
+    pub fn approve(self) -> Session<Done> {
+        self.transition(|Reviewing { draft, revisions, .. }| Done { document: draft, revisions })
+    }
+
+    /// Back to drafting, with the feedback for the next `write`
+    pub fn revise(self, feedback: impl Into<String>) -> Session<Drafting> {
+        let feedback = feedback.into();
+        self.transition(|Reviewing { requirements, draft, revisions }| Drafting {
+            requirements,
+            previous: Some((draft, feedback)),
+            revisions: revisions + 1,
+        })
+    }
+}
+
+impl Session<Done> {
+    pub fn document(&self) -> &str {
+        &self.state.document
+    }
+
+    /// Rounds of feedback before approval
+    pub fn revisions(&self) -> u32 {
+        self.state.revisions
+    }
+
+    pub fn into_document(self) -> String {
+        self.state.document
+    }
+}
+
+/// For sessions whose phase is only known at runtime: one per user in a
+/// server's session map, advanced by whichever request comes in next
+pub enum AnySession {
+    Gathering(Session<Gathering>),
+    Drafting(Session<Drafting>),
+    Reviewing(Session<Reviewing>),
+    Done(Session<Done>),
+}
+
+impl AnySession {
+    pub fn phase(&self) -> &'static str {
+        match self {
+            Self::Gathering(s) => s.phase(),
+            Self::Drafting(s) => s.phase(),
+            Self::Reviewing(s) => s.phase(),
+            Self::Done(s) => s.phase(),
+        }
+    }
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// Interview the user in the terminal, draft a design doc, and revise it
+/// until they approve
+pub async fn design_doc_assistant() -> Result<()> {
+    let agent = CLIENTS.openai().agent("gpt-4o").preamble("You help engineers write design documents.").build();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    let mut session = Session::start(Box::new(BoxedAgent(agent)), "A design doc for rate limiting our public API")
+        .max_questions(5);
+    while let Some(question) = session.next_question().await? {
+        println!("\n{question}");
+        session.answer(lines.next_line().await?.unwrap_or_default())?;
+    }
+
+    let mut drafting = session.start_drafting().await?;
+    println!("\nRequirements:\n{}", drafting.requirements());
+    let done = loop {
+        let reviewing = drafting.write().await?;
+        println!("\n{}\n\nPress enter to approve, or describe what to change:", reviewing.draft());
+        let feedback = lines.next_line().await?.unwrap_or_default();
+        if feedback.trim().is_empty() {
+            break reviewing.approve();
+        }
+        drafting = reviewing.revise(feedback);
+    };
+
+    println!("approved after {} revisions", done.revisions());
+    std::fs::write("design.md", done.into_document())?;
+    Ok(())
+}
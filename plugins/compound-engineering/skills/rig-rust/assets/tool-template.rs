@@ -2,52 +2,465 @@
 
 use rig::{completion::ToolDefinition, tool::Tool};
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::middleware_template::{Cacheable, Confirmation, Idempotency};
 
 // Calculator Tool
+//
+// Exact decimal arithmetic (no f64 rounding surprises on money), variables that
+// persist across calls, and unit-aware quantities:
+//   price = 19.99
+//   price * 3 * 1.0825
+//   5 km + 300 m to mi
 #[derive(Deserialize, JsonSchema)]
 pub struct CalculatorInput {
     expression: String,
+    precision: Option<i64>,
 }
 
 #[derive(Debug, thiserror::Error)]
 #[error("Calculation error: {0}")]
 pub struct CalculatorError(String);
 
-pub struct Calculator;
+pub struct Calculator {
+    variables: std::sync::Mutex<HashMap<String, Quantity>>,
+    precision: i64,
+    rounding: bigdecimal::RoundingMode,
+}
+
+impl Default for Calculator {
+    fn default() -> Self {
+        Self {
+            variables: Default::default(),
+            precision: 10,
+            rounding: bigdecimal::RoundingMode::HalfEven,
+        }
+    }
+}
+
+impl Calculator {
+    /// Decimal places in results (can be overridden per call)
+    pub fn precision(mut self, places: i64) -> Self {
+        self.precision = places;
+        self
+    }
+
+    pub fn rounding(mut self, mode: bigdecimal::RoundingMode) -> Self {
+        self.rounding = mode;
+        self
+    }
+}
+
+struct Unit {
+    name: &'static str,
+    dimension: &'static str,
+    /// Size in the dimension's base unit
+    factor: &'static str,
+}
+
+const UNITS: &[Unit] = &[
+    Unit { name: "mm", dimension: "length", factor: "0.001" },
+    Unit { name: "cm", dimension: "length", factor: "0.01" },
+    Unit { name: "m", dimension: "length", factor: "1" },
+    Unit { name: "km", dimension: "length", factor: "1000" },
+    Unit { name: "in", dimension: "length", factor: "0.0254" },
+    Unit { name: "ft", dimension: "length", factor: "0.3048" },
+    Unit { name: "mi", dimension: "length", factor: "1609.344" },
+    Unit { name: "mg", dimension: "mass", factor: "0.001" },
+    Unit { name: "g", dimension: "mass", factor: "1" },
+    Unit { name: "kg", dimension: "mass", factor: "1000" },
+    Unit { name: "oz", dimension: "mass", factor: "28.349523125" },
+    Unit { name: "lb", dimension: "mass", factor: "453.59237" },
+    Unit { name: "ms", dimension: "time", factor: "0.001" },
+    Unit { name: "s", dimension: "time", factor: "1" },
+    Unit { name: "min", dimension: "time", factor: "60" },
+    Unit { name: "h", dimension: "time", factor: "3600" },
+    Unit { name: "day", dimension: "time", factor: "86400" },
+];
+
+fn unit(name: &str) -> Option<&'static Unit> {
+    UNITS.iter().find(|u| u.name == name)
+}
+
+#[derive(Clone)]
+struct Quantity {
+    value: bigdecimal::BigDecimal,
+    unit: Option<&'static Unit>,
+}
+
+impl Quantity {
+    fn convert(&self, to: &'static Unit) -> Result<Quantity, CalculatorError> {
+        use std::str::FromStr;
+
+        let from = self.unit.ok_or_else(|| CalculatorError(format!("Cannot convert a plain number to {}", to.name)))?;
+        if from.dimension != to.dimension {
+            return Err(CalculatorError(format!("Cannot convert {} to {}", from.name, to.name)));
+        }
+        let factor = |u: &Unit| bigdecimal::BigDecimal::from_str(u.factor).unwrap();
+        Ok(Quantity { value: &self.value * factor(from) / factor(to), unit: Some(to) })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Ident(String),
+    Op(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, CalculatorError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit() || **d == '.' || **d == '_') {
+                if d != '_' {
+                    number.push(d);
+                }
+                chars.next();
+            }
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_alphanumeric() || **d == '_') {
+                ident.push(d);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if "+-*/%^(),=".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else {
+            return Err(CalculatorError(format!("Unexpected character '{c}'")));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Whether evaluating `tokens` assigns or reads a variable. An identifier is
+/// a function name before `(`, `to`, or a unit right after a value; any other
+/// identifier is a variable
+fn reads_variables(tokens: &[Token]) -> bool {
+    tokens.iter().enumerate().any(|(i, token)| match token {
+        Token::Op('=') => true,
+        Token::Ident(name) => {
+            let call = tokens.get(i + 1) == Some(&Token::Op('('));
+            let unit = i > 0 && matches!(&tokens[i - 1], Token::Number(_) | Token::Op(')') | Token::Ident(_));
+            name != "to" && !call && !unit
+        }
+        _ => false,
+    })
+}
+
+/// Recursive-descent evaluator over `BigDecimal`
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    variables: &'a HashMap<String, Quantity>,
+    rounding: bigdecimal::RoundingMode,
+}
+
+/// Bounds on `^` so a huge exponent can't stall the evaluator (it runs under the variables lock)
+const MAX_EXPONENT: u32 = 1024;
+const MAX_DIGITS: u64 = 10_000;
+
+/// Decimal places accepted for `precision` and `round()`; a huge scale would
+/// allocate the digits while holding the variables lock
+const MAX_PLACES: i64 = 100;
+
+fn check_places(places: i64) -> Result<i64, CalculatorError> {
+    if (0..=MAX_PLACES).contains(&places) {
+        Ok(places)
+    } else {
+        Err(CalculatorError(format!("Decimal places must be between 0 and {MAX_PLACES}, got {places}")))
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: char) -> Result<(), CalculatorError> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(CalculatorError(format!("Expected '{op}'")))
+        }
+    }
+
+    /// expr ('to' unit)?
+    fn statement(&mut self) -> Result<Quantity, CalculatorError> {
+        let value = self.expr()?;
+        if self.peek() == Some(&Token::Ident("to".into())) {
+            self.pos += 1;
+            return match self.next() {
+                Some(Token::Ident(name)) => {
+                    let target = unit(&name).ok_or_else(|| CalculatorError(format!("Unknown unit {name}")))?;
+                    value.convert(target)
+                }
+                _ => Err(CalculatorError("Expected a unit after 'to'".into())),
+            };
+        }
+        Ok(value)
+    }
+
+    fn expr(&mut self) -> Result<Quantity, CalculatorError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') { '+' } else if self.eat('-') { '-' } else { return Ok(lhs) };
+            let rhs = self.term()?;
+            // Mixed units of one dimension are converted to the left operand's unit
+            let rhs = match (lhs.unit, rhs.unit) {
+                (None, None) => rhs,
+                (Some(u), Some(_)) => rhs.convert(u)?,
+                _ => return Err(CalculatorError("Cannot add a plain number to a quantity with units".into())),
+            };
+            lhs.value = if op == '+' { lhs.value + rhs.value } else { lhs.value - rhs.value };
+        }
+    }
+
+    fn term(&mut self) -> Result<Quantity, CalculatorError> {
+        let mut lhs = self.power()?;
+        loop {
+            let op = if self.eat('*') { '*' } else if self.eat('/') { '/' } else if self.eat('%') { '%' } else { return Ok(lhs) };
+            let rhs = self.power()?;
+            if op != '*' && rhs.value == bigdecimal::BigDecimal::from(0) {
+                return Err(CalculatorError("Division by zero".into()));
+            }
+            lhs = match (op, lhs.unit, rhs.unit) {
+                ('*', Some(_), Some(_)) => return Err(CalculatorError("Products of units are not supported".into())),
+                ('*', unit, None) | ('*', None, unit) => Quantity { value: lhs.value * rhs.value, unit },
+                // Dividing like quantities gives a plain ratio: (3 km) / (500 m) = 6
+                ('/', Some(u), Some(_)) => Quantity { value: lhs.value / rhs.convert(u)?.value, unit: None },
+                ('/', unit, None) => Quantity { value: lhs.value / rhs.value, unit },
+                ('%', unit, None) => Quantity { value: lhs.value % rhs.value, unit },
+                _ => return Err(CalculatorError("Cannot divide a plain number by a quantity with units".into())),
+            };
+        }
+    }
+
+    /// Right-associative integer powers: 2 ^ 3 ^ 2 = 2 ^ 9
+    fn power(&mut self) -> Result<Quantity, CalculatorError> {
+        use bigdecimal::ToPrimitive;
+
+        let base = self.unary()?;
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        let exponent = self.power()?;
+        if base.unit.is_some() || exponent.unit.is_some() {
+            return Err(CalculatorError("Powers of quantities with units are not supported".into()));
+        }
+        let n = exponent.value.to_i32()
+            .filter(|n| exponent.value == bigdecimal::BigDecimal::from(*n))
+            .ok_or_else(|| CalculatorError("Exponents must be whole numbers".into()))?;
+        if n.unsigned_abs() > MAX_EXPONENT {
+            return Err(CalculatorError(format!("Exponents are limited to ±{MAX_EXPONENT}")));
+        }
+        let mut value = bigdecimal::BigDecimal::from(1);
+        for _ in 0..n.unsigned_abs() {
+            value *= &base.value;
+            if value.digits() > MAX_DIGITS {
+                return Err(CalculatorError(format!("Result exceeds {MAX_DIGITS} digits")));
+            }
+        }
+        if n < 0 {
+            if value == bigdecimal::BigDecimal::from(0) {
+                return Err(CalculatorError("Division by zero".into()));
+            }
+            value = bigdecimal::BigDecimal::from(1) / value;
+        }
+        Ok(Quantity { value, unit: None })
+    }
+
+    fn unary(&mut self) -> Result<Quantity, CalculatorError> {
+        if self.eat('-') {
+            let mut q = self.unary()?;
+            q.value = -q.value;
+            return Ok(q);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Quantity, CalculatorError> {
+        use std::str::FromStr;
+
+        let mut q = match self.next() {
+            Some(Token::Number(n)) => Quantity {
+                value: bigdecimal::BigDecimal::from_str(&n).map_err(|_| CalculatorError(format!("Invalid number {n}")))?,
+                unit: None,
+            },
+            Some(Token::Op('(')) => {
+                let q = self.expr()?;
+                self.expect(')')?;
+                q
+            }
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::Op('(')) => {
+                self.pos += 1;
+                return self.function(&name);
+            }
+            Some(Token::Ident(name)) => self.variables.get(&name).cloned()
+                .ok_or_else(|| CalculatorError(format!("Unknown variable {name}")))?,
+            _ => return Err(CalculatorError("Unexpected end of expression".into())),
+        };
+        // A unit directly after a value attaches to it: `5 km`, `(2 + 3) kg`
+        if q.unit.is_none() {
+            if let Some(Token::Ident(name)) = self.peek() {
+                if let Some(u) = unit(name) {
+                    q.unit = Some(u);
+                    self.pos += 1;
+                }
+            }
+        }
+        Ok(q)
+    }
+
+    fn function(&mut self, name: &str) -> Result<Quantity, CalculatorError> {
+        use bigdecimal::ToPrimitive;
+
+        let arg = self.expr()?;
+        let result = match name {
+            "abs" => Quantity { value: arg.value.abs(), unit: arg.unit },
+            "sqrt" if arg.unit.is_some() => {
+                return Err(CalculatorError("sqrt of a quantity with units is not supported".into()));
+            }
+            "sqrt" => Quantity {
+                value: arg.value.sqrt().ok_or_else(|| CalculatorError("sqrt of a negative number".into()))?,
+                unit: None,
+            },
+            "round" => {
+                self.expect(',')?;
+                let places = self.expr()?.value.to_i64()
+                    .ok_or_else(|| CalculatorError("round() places must be a whole number".into()))?;
+                let places = check_places(places)?;
+                Quantity {
+                    value: arg.value.with_scale_round(places, self.rounding),
+                    unit: arg.unit,
+                }
+            }
+            _ => return Err(CalculatorError(format!("Unknown function {name}"))),
+        };
+        self.expect(')')?;
+        Ok(result)
+    }
+}
 
 impl Tool for Calculator {
     const NAME: &'static str = "calculator";
     type Args = CalculatorInput;
-    type Output = f64;
+    type Output = String;
     type Error = CalculatorError;
     
     async fn definition(&self, _: String) -> ToolDefinition {
+        let units: Vec<_> = UNITS.iter().map(|u| u.name).collect();
         ToolDefinition {
             name: Self::NAME.to_string(),
-            description: "Evaluate math expressions".to_string(),
+            description: format!(
+                "Evaluate math expressions exactly with + - * / % ^, abs(), sqrt(), round(x, places). \
+                 Assign with `name = expr`; variables persist between calls. \
+                 Units ({}) attach to numbers and convert with `to`, e.g. `5 km + 300 m to mi`.",
+                units.join(", ")
+            ),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
-                    "expression": { "type": "string" }
+                    "expression": { "type": "string" },
+                    "precision": {
+                        "type": "integer",
+                        "description": "Decimal places in the result",
+                        "default": self.precision
+                    }
                 },
                 "required": ["expression"]
             }),
         }
     }
     
-    async fn call(&self, args: Self::Args) -> Result<f64, CalculatorError> {
-        meval::eval_str(&args.expression)
-            .map_err(|e| CalculatorError(e.to_string()))
+    async fn call(&self, args: Self::Args) -> Result<String, CalculatorError> {
+        let mut tokens = tokenize(&args.expression)?;
+
+        // `name = expr` binds the result for later calls
+        let target = match tokens.as_slice() {
+            [Token::Ident(name), Token::Op('='), ..] if name != "to" => Some(name.clone()),
+            _ => None,
+        };
+        if target.is_some() {
+            tokens.drain(..2);
+        }
+
+        let mut variables = self.variables.lock().unwrap();
+        let mut parser = Parser { tokens, pos: 0, variables: &variables, rounding: self.rounding };
+        let result = parser.statement()?;
+        if let Some(token) = parser.peek() {
+            return Err(CalculatorError(format!("Unexpected {token:?}")));
+        }
+
+        let precision = check_places(args.precision.unwrap_or(self.precision))?;
+        let rounded = result.value.with_scale_round(precision, self.rounding);
+        let text = match result.unit {
+            Some(u) => format!("{} {}", rounded, u.name),
+            None => rounded.to_string(),
+        };
+
+        if let Some(name) = target {
+            variables.insert(name.clone(), result);
+            return Ok(format!("{name} = {text}"));
+        }
+        Ok(text)
     }
 }
 
 // HTTP Client Tool
+#[derive(Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HttpAuth {
+    Basic { username: String, password: Option<String> },
+    Bearer { token: String },
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct HttpInput {
     url: String,
     #[serde(default)]
     method: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    json: Option<serde_json::Value>,
+    auth: Option<HttpAuth>,
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    retries: u32,
+    max_response_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HttpOutput {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    pub truncated: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -56,44 +469,1180 @@ pub struct HttpError(String);
 
 pub struct HttpClient {
     client: reqwest::Client,
+    default_timeout: Duration,
+    max_retries: u32,
+    max_response_bytes: usize,
 }
 
 impl Default for HttpClient {
     fn default() -> Self {
-        Self { client: reqwest::Client::new() }
+        Self {
+            client: reqwest::Client::new(),
+            default_timeout: Duration::from_secs(30),
+            max_retries: 3,
+            max_response_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl HttpClient {
+    /// Use a preconfigured client, e.g. one routed through a corporate proxy:
+    /// `HttpClient::default().client(ProxyConfig::from_env().http_client()?)`
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    fn is_retryable(status: reqwest::StatusCode) -> bool {
+        status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
     }
 }
 
 impl Tool for HttpClient {
     const NAME: &'static str = "http_request";
     type Args = HttpInput;
-    type Output = String;
+    type Output = HttpOutput;
     type Error = HttpError;
     
     async fn definition(&self, _: String) -> ToolDefinition {
         ToolDefinition {
             name: Self::NAME.to_string(),
-            description: "Make HTTP requests".to_string(),
+            description: "Make HTTP requests and return status, headers, and body".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
                     "url": { "type": "string" },
-                    "method": { "type": "string", "default": "GET" }
+                    "method": { "type": "string", "default": "GET" },
+                    "headers": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" }
+                    },
+                    "json": { "description": "JSON request body" },
+                    "auth": {
+                        "type": "object",
+                        "properties": {
+                            "type": { "type": "string", "enum": ["basic", "bearer"] },
+                            "username": { "type": "string" },
+                            "password": { "type": "string", "writeOnly": true },
+                            "token": { "type": "string", "writeOnly": true }
+                        },
+                        "required": ["type"]
+                    },
+                    "timeout_secs": { "type": "integer" },
+                    "retries": {
+                        "type": "integer",
+                        "default": 0,
+                        "maximum": self.max_retries
+                    },
+                    "max_response_bytes": {
+                        "type": "integer",
+                        "maximum": self.max_response_bytes
+                    }
                 },
                 "required": ["url"]
             }),
         }
     }
     
-    async fn call(&self, args: Self::Args) -> Result<String, HttpError> {
+    async fn call(&self, args: Self::Args) -> Result<HttpOutput, HttpError> {
         let method = if args.method.is_empty() { "GET" } else { &args.method };
         let method: reqwest::Method = method.parse()
             .map_err(|_| HttpError("Invalid method".into()))?;
         
-        self.client.request(method, &args.url)
+        let mut req = self.client.request(method, &args.url)
+            .timeout(args.timeout_secs.map_or(self.default_timeout, Duration::from_secs));
+        for (name, value) in &args.headers {
+            req = req.header(name, value);
+        }
+        if let Some(json) = &args.json {
+            req = req.json(json);
+        }
+        req = match args.auth {
+            Some(HttpAuth::Basic { username, password }) => req.basic_auth(username, password),
+            Some(HttpAuth::Bearer { token }) => req.bearer_auth(token),
+            None => req,
+        };
+        
+        // JSON bodies are buffered, so the request can be cloned per attempt
+        let retries = args.retries.min(self.max_retries);
+        let mut attempt = 0;
+        let mut response = loop {
+            let attempt_req = req.try_clone()
+                .ok_or_else(|| HttpError("Request body cannot be retried".into()))?;
+            match attempt_req.send().await {
+                Ok(resp) if attempt < retries && Self::is_retryable(resp.status()) => {}
+                Ok(resp) => break resp,
+                Err(e) if attempt < retries && (e.is_timeout() || e.is_connect()) => {}
+                Err(e) => return Err(HttpError(e.to_string())),
+            }
+            attempt += 1;
+            tokio::time::sleep(Duration::from_millis(200 * 2u64.pow(attempt))).await;
+        };
+        
+        let status = response.status().as_u16();
+        let headers = response.headers().iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or_default().to_string()))
+            .collect();
+        
+        let limit = args.max_response_bytes
+            .map_or(self.max_response_bytes, |n| n.min(self.max_response_bytes));
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await.map_err(|e| HttpError(e.to_string()))? {
+            if body.len() + chunk.len() > limit {
+                body.extend_from_slice(&chunk[..limit - body.len()]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        
+        Ok(HttpOutput {
+            status,
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+            truncated,
+        })
+    }
+}
+
+// Redis Key-Value Tool
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisAction {
+    Get,
+    Set,
+    Del,
+    Scan,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RedisInput {
+    action: RedisAction,
+    #[serde(default)]
+    key: String,
+    value: Option<String>,
+    ttl_secs: Option<u64>,
+    /// Key prefix for scan, matched literally
+    #[serde(default)]
+    prefix: String,
+}
+
+/// Backslash-escape Redis glob metacharacters so `text` matches only itself
+fn escape_glob(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Redis error: {0}")]
+pub struct RedisError(String);
+
+pub struct RedisTool {
+    conn: redis::aio::ConnectionManager,
+    prefix: String,
+    max_value_bytes: usize,
+    max_scan_keys: usize,
+}
+
+impl RedisTool {
+    /// Every key the model touches is scoped under `prefix:`
+    pub async fn new(url: &str, prefix: impl Into<String>) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url).map_err(|e| RedisError(e.to_string()))?;
+        let conn = redis::aio::ConnectionManager::new(client).await
+            .map_err(|e| RedisError(e.to_string()))?;
+        Ok(Self {
+            conn,
+            prefix: prefix.into(),
+            max_value_bytes: 64 * 1024,
+            max_scan_keys: 100,
+        })
+    }
+
+    pub fn max_value_bytes(mut self, bytes: usize) -> Self {
+        self.max_value_bytes = bytes;
+        self
+    }
+
+    pub fn max_scan_keys(mut self, keys: usize) -> Self {
+        self.max_scan_keys = keys;
+        self
+    }
+
+    fn scoped(&self, key: &str) -> Result<String, RedisError> {
+        if key.is_empty() {
+            return Err(RedisError("Key is required".into()));
+        }
+        Ok(format!("{}:{}", self.prefix, key))
+    }
+}
+
+impl Tool for RedisTool {
+    const NAME: &'static str = "redis";
+    type Args = RedisInput;
+    type Output = serde_json::Value;
+    type Error = RedisError;
+    
+    async fn definition(&self, _: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Read and write scratch key-value storage. Values are limited to {} bytes.",
+                self.max_value_bytes
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": { "type": "string", "enum": ["get", "set", "del", "scan"] },
+                    "key": { "type": "string" },
+                    "value": { "type": "string", "description": "Value for set" },
+                    "ttl_secs": { "type": "integer", "description": "Optional expiry for set" },
+                    "prefix": { "type": "string", "description": "Key prefix for scan; empty lists every key" }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+    
+    async fn call(&self, args: Self::Args) -> Result<serde_json::Value, RedisError> {
+        use redis::AsyncCommands;
+
+        let mut conn = self.conn.clone();
+        match args.action {
+            RedisAction::Get => {
+                let value: Option<String> = conn.get(self.scoped(&args.key)?).await
+                    .map_err(|e| RedisError(e.to_string()))?;
+                match value {
+                    Some(v) if v.len() > self.max_value_bytes => Err(RedisError(format!(
+                        "Value is {} bytes, over the {} byte limit", v.len(), self.max_value_bytes
+                    ))),
+                    v => Ok(serde_json::json!({ "key": args.key, "value": v })),
+                }
+            }
+            RedisAction::Set => {
+                let value = args.value.ok_or_else(|| RedisError("Value is required for set".into()))?;
+                if value.len() > self.max_value_bytes {
+                    return Err(RedisError(format!(
+                        "Value is {} bytes, over the {} byte limit", value.len(), self.max_value_bytes
+                    )));
+                }
+                let key = self.scoped(&args.key)?;
+                match args.ttl_secs {
+                    Some(ttl) => conn.set_ex::<_, _, ()>(key, value, ttl).await,
+                    None => conn.set::<_, _, ()>(key, value).await,
+                }
+                .map_err(|e| RedisError(e.to_string()))?;
+                Ok(serde_json::json!({ "key": args.key, "ok": true }))
+            }
+            RedisAction::Del => {
+                let removed: i64 = conn.del(self.scoped(&args.key)?).await
+                    .map_err(|e| RedisError(e.to_string()))?;
+                Ok(serde_json::json!({ "key": args.key, "deleted": removed > 0 }))
+            }
+            RedisAction::Scan => {
+                let scope = format!("{}:", self.prefix);
+                let pattern = format!("{}{}*", escape_glob(&scope), escape_glob(&args.prefix));
+                let mut keys = Vec::new();
+                let mut cursor = 0u64;
+                loop {
+                    let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH").arg(&pattern)
+                        .arg("COUNT").arg(100)
+                        .query_async(&mut conn).await
+                        .map_err(|e| RedisError(e.to_string()))?;
+                    keys.extend(batch.iter().filter_map(|k| k.strip_prefix(&scope)).map(String::from));
+                    cursor = next;
+                    if cursor == 0 || keys.len() >= self.max_scan_keys {
+                        break;
+                    }
+                }
+                let truncated = keys.len() > self.max_scan_keys;
+                keys.truncate(self.max_scan_keys);
+                Ok(serde_json::json!({ "keys": keys, "truncated": truncated || cursor != 0 }))
+            }
+        }
+    }
+}
+
+// Headless Browser Tool
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BrowserAction {
+    Navigate,
+    ExtractText,
+    Click,
+    Screenshot,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct BrowserInput {
+    action: BrowserAction,
+    url: Option<String>,
+    selector: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Browser error: {0}")]
+pub struct BrowserError(String);
+
+pub struct BrowserTool {
+    _browser: chromiumoxide::Browser,
+    page: tokio::sync::Mutex<chromiumoxide::Page>,
+    allowed_hosts: Vec<String>,
+    screenshot_dir: std::path::PathBuf,
+    max_text_chars: usize,
+    _handler: tokio::task::JoinHandle<()>,
+}
+
+impl BrowserTool {
+    /// Only pages on `allowed_hosts` (or their subdomains) can be visited
+    pub async fn launch(allowed_hosts: Vec<String>) -> Result<Self, BrowserError> {
+        use futures::StreamExt;
+
+        let config = chromiumoxide::BrowserConfig::builder().build()
+            .map_err(BrowserError)?;
+        let (browser, mut handler) = chromiumoxide::Browser::launch(config).await
+            .map_err(|e| BrowserError(e.to_string()))?;
+        // The CDP event loop must be polled for the browser to make progress
+        let handler = tokio::spawn(async move {
+            while let Some(event) = handler.next().await {
+                if event.is_err() {
+                    break;
+                }
+            }
+        });
+        let page = browser.new_page("about:blank").await
+            .map_err(|e| BrowserError(e.to_string()))?;
+
+        Ok(Self {
+            _browser: browser,
+            page: tokio::sync::Mutex::new(page),
+            allowed_hosts,
+            screenshot_dir: std::env::temp_dir(),
+            max_text_chars: 20_000,
+            _handler: handler,
+        })
+    }
+
+    pub fn screenshot_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.screenshot_dir = dir.into();
+        self
+    }
+
+    fn check_allowed(&self, url: &str) -> Result<(), BrowserError> {
+        let host = url::Url::parse(url).ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .ok_or_else(|| BrowserError(format!("Invalid URL: {url}")))?;
+        let allowed = self.allowed_hosts.iter()
+            .any(|a| host == *a || host.ends_with(&format!(".{a}")));
+        if allowed {
+            Ok(())
+        } else {
+            Err(BrowserError(format!("{host} is not on the navigation allowlist")))
+        }
+    }
+}
+
+impl Tool for BrowserTool {
+    const NAME: &'static str = "browser";
+    type Args = BrowserInput;
+    type Output = serde_json::Value;
+    type Error = BrowserError;
+    
+    async fn definition(&self, _: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Drive a headless browser for JavaScript-rendered pages. Allowed hosts: {}",
+                self.allowed_hosts.join(", ")
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["navigate", "extract_text", "click", "screenshot"]
+                    },
+                    "url": { "type": "string", "description": "Page to open for navigate" },
+                    "selector": { "type": "string", "description": "CSS selector for click or extract_text" }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+    
+    async fn call(&self, args: Self::Args) -> Result<serde_json::Value, BrowserError> {
+        let page = self.page.lock().await;
+        let err = |e: chromiumoxide::error::CdpError| BrowserError(e.to_string());
+
+        match args.action {
+            BrowserAction::Navigate => {
+                let url = args.url.ok_or_else(|| BrowserError("url is required for navigate".into()))?;
+                self.check_allowed(&url)?;
+                page.goto(url.as_str()).await.map_err(err)?;
+            }
+            BrowserAction::ExtractText => {
+                let text: String = match &args.selector {
+                    Some(selector) => page.find_element(selector.as_str()).await.map_err(err)?
+                        .inner_text().await.map_err(err)?
+                        .unwrap_or_default(),
+                    None => page.evaluate("document.body.innerText").await.map_err(err)?
+                        .into_value()
+                        .map_err(|e| BrowserError(e.to_string()))?,
+                };
+                let truncated = text.chars().count() > self.max_text_chars;
+                let text: String = text.chars().take(self.max_text_chars).collect();
+                return Ok(serde_json::json!({ "text": text, "truncated": truncated }));
+            }
+            BrowserAction::Click => {
+                let selector = args.selector.ok_or_else(|| BrowserError("selector is required for click".into()))?;
+                page.find_element(selector.as_str()).await.map_err(err)?
+                    .click().await.map_err(err)?;
+            }
+            BrowserAction::Screenshot => {
+                let png = page
+                    .screenshot(chromiumoxide::page::ScreenshotParams::builder().full_page(true).build())
+                    .await
+                    .map_err(err)?;
+                let path = self.screenshot_dir.join(format!("screenshot-{}.png", uuid::Uuid::new_v4()));
+                tokio::fs::write(&path, png).await.map_err(|e| BrowserError(e.to_string()))?;
+                return Ok(serde_json::json!({ "path": path }));
+            }
+        }
+
+        // Clicks and redirects can leave the allowlist; bail out to a blank page if so
+        let current = page.url().await.map_err(err)?.unwrap_or_default();
+        if let Err(e) = self.check_allowed(&current) {
+            page.goto("about:blank").await.map_err(err)?;
+            return Err(e);
+        }
+        let title = page.get_title().await.map_err(err)?;
+        Ok(serde_json::json!({ "url": current, "title": title }))
+    }
+}
+
+// Kubernetes Inspection Tool (read-only)
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KubeAction {
+    ListPods,
+    ListDeployments,
+    Logs,
+    Describe,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KubeKind {
+    Pod,
+    Deployment,
+    Service,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct KubeInput {
+    action: KubeAction,
+    namespace: Option<String>,
+    name: Option<String>,
+    kind: Option<KubeKind>,
+    container: Option<String>,
+    #[serde(default = "default_tail_lines")]
+    tail_lines: i64,
+}
+
+fn default_tail_lines() -> i64 { 200 }
+
+/// Upper bound on `tail_lines`, whatever the model asks for
+const MAX_TAIL_LINES: i64 = 2000;
+
+/// Cut `text` to at most `max_bytes` on a char boundary; `true` if anything was dropped
+fn truncate_bytes(text: &mut String, max_bytes: usize) -> bool {
+    if text.len() <= max_bytes {
+        return false;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    true
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Kubernetes error: {0}")]
+pub struct KubeError(String);
+
+impl From<kube::Error> for KubeError {
+    fn from(e: kube::Error) -> Self {
+        KubeError(e.to_string())
+    }
+}
+
+pub struct KubeTool {
+    client: kube::Client,
+    namespaces: Vec<String>,
+    max_log_bytes: usize,
+}
+
+impl KubeTool {
+    /// Uses the local kubeconfig or in-cluster service account. The first
+    /// namespace is the default; any other namespace is refused.
+    pub async fn new(namespaces: Vec<String>) -> Result<Self, KubeError> {
+        if namespaces.is_empty() {
+            return Err(KubeError("At least one namespace is required".into()));
+        }
+        Ok(Self { client: kube::Client::try_default().await?, namespaces, max_log_bytes: 64 * 1024 })
+    }
+
+    /// Cap on log output per call (default 64 KiB)
+    pub fn max_log_bytes(mut self, bytes: usize) -> Self {
+        self.max_log_bytes = bytes;
+        self
+    }
+
+    fn namespace(&self, requested: Option<String>) -> Result<String, KubeError> {
+        match requested {
+            None => Ok(self.namespaces[0].clone()),
+            Some(ns) if self.namespaces.contains(&ns) => Ok(ns),
+            Some(ns) => Err(KubeError(format!("Namespace {ns} is not allowed"))),
+        }
+    }
+}
+
+/// Drop noisy bookkeeping so descriptions fit in context
+fn without_managed_fields<K: serde::Serialize>(resource: &K) -> Result<serde_json::Value, KubeError> {
+    let mut value = serde_json::to_value(resource).map_err(|e| KubeError(e.to_string()))?;
+    if let Some(metadata) = value.get_mut("metadata").and_then(|m| m.as_object_mut()) {
+        metadata.remove("managedFields");
+    }
+    Ok(value)
+}
+
+impl Tool for KubeTool {
+    const NAME: &'static str = "kubernetes";
+    type Args = KubeInput;
+    type Output = serde_json::Value;
+    type Error = KubeError;
+    
+    async fn definition(&self, _: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Inspect a Kubernetes cluster (read-only). Namespaces: {}",
+                self.namespaces.join(", ")
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["list_pods", "list_deployments", "logs", "describe"]
+                    },
+                    "namespace": { "type": "string", "default": self.namespaces[0] },
+                    "name": { "type": "string", "description": "Resource name for logs or describe" },
+                    "kind": { "type": "string", "enum": ["pod", "deployment", "service"] },
+                    "container": { "type": "string", "description": "Container for logs in multi-container pods" },
+                    "tail_lines": { "type": "integer", "default": 200, "maximum": MAX_TAIL_LINES }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+    
+    async fn call(&self, args: Self::Args) -> Result<serde_json::Value, KubeError> {
+        use k8s_openapi::api::apps::v1::Deployment;
+        use k8s_openapi::api::core::v1::{Pod, Service};
+        use kube::api::{Api, ListParams, LogParams};
+
+        let ns = self.namespace(args.namespace)?;
+        let name = || args.name.clone().ok_or_else(|| KubeError("name is required".into()));
+
+        match args.action {
+            KubeAction::ListPods => {
+                let pods: Api<Pod> = Api::namespaced(self.client.clone(), &ns);
+                let pods = pods.list(&ListParams::default()).await?;
+                Ok(serde_json::Value::Array(pods.items.iter().map(|pod| {
+                    let status = pod.status.as_ref();
+                    let restarts: i32 = status
+                        .and_then(|s| s.container_statuses.as_ref())
+                        .map(|cs| cs.iter().map(|c| c.restart_count).sum())
+                        .unwrap_or(0);
+                    serde_json::json!({
+                        "name": pod.metadata.name,
+                        "phase": status.and_then(|s| s.phase.clone()),
+                        "restarts": restarts,
+                        "node": pod.spec.as_ref().and_then(|s| s.node_name.clone()),
+                    })
+                }).collect()))
+            }
+            KubeAction::ListDeployments => {
+                let deployments: Api<Deployment> = Api::namespaced(self.client.clone(), &ns);
+                let deployments = deployments.list(&ListParams::default()).await?;
+                Ok(serde_json::Value::Array(deployments.items.iter().map(|d| {
+                    serde_json::json!({
+                        "name": d.metadata.name,
+                        "desired": d.spec.as_ref().and_then(|s| s.replicas),
+                        "ready": d.status.as_ref().and_then(|s| s.ready_replicas).unwrap_or(0),
+                    })
+                }).collect()))
+            }
+            KubeAction::Logs => {
+                let pods: Api<Pod> = Api::namespaced(self.client.clone(), &ns);
+                let params = LogParams {
+                    container: args.container.clone(),
+                    tail_lines: Some(args.tail_lines.clamp(1, MAX_TAIL_LINES)),
+                    // One byte over, so a log that fills the cap exactly isn't reported as cut
+                    limit_bytes: Some(self.max_log_bytes as i64 + 1),
+                    ..Default::default()
+                };
+                let mut logs = pods.logs(&name()?, &params).await?;
+                let truncated = truncate_bytes(&mut logs, self.max_log_bytes);
+                Ok(serde_json::json!({ "logs": logs, "truncated": truncated }))
+            }
+            KubeAction::Describe => {
+                let name = name()?;
+                match args.kind.unwrap_or(KubeKind::Pod) {
+                    KubeKind::Pod => without_managed_fields(
+                        &Api::<Pod>::namespaced(self.client.clone(), &ns).get(&name).await?,
+                    ),
+                    KubeKind::Deployment => without_managed_fields(
+                        &Api::<Deployment>::namespaced(self.client.clone(), &ns).get(&name).await?,
+                    ),
+                    KubeKind::Service => without_managed_fields(
+                        &Api::<Service>::namespaced(self.client.clone(), &ns).get(&name).await?,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+// Docker Management Tool
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DockerAction {
+    List,
+    Inspect,
+    Logs,
+    Restart,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DockerInput {
+    action: DockerAction,
+    container: Option<String>,
+    #[serde(default)]
+    all: bool,
+    #[serde(default = "default_tail_lines")]
+    tail_lines: i64,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Docker error: {0}")]
+pub struct DockerError(String);
+
+impl From<bollard::errors::Error> for DockerError {
+    fn from(e: bollard::errors::Error) -> Self {
+        DockerError(e.to_string())
+    }
+}
+
+/// Restart is destructive; wrap in `ConfirmTool` so it waits for approval
+pub struct DockerTool {
+    docker: bollard::Docker,
+    max_log_bytes: usize,
+}
+
+impl DockerTool {
+    pub fn connect() -> Result<Self, DockerError> {
+        Ok(Self { docker: bollard::Docker::connect_with_local_defaults()?, max_log_bytes: 64 * 1024 })
+    }
+
+    /// Cap on log output per call (default 64 KiB)
+    pub fn max_log_bytes(mut self, bytes: usize) -> Self {
+        self.max_log_bytes = bytes;
+        self
+    }
+}
+
+impl Tool for DockerTool {
+    const NAME: &'static str = "docker";
+    type Args = DockerInput;
+    type Output = serde_json::Value;
+    type Error = DockerError;
+    
+    async fn definition(&self, _: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "List, inspect, read logs from, and restart local Docker containers".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": { "type": "string", "enum": ["list", "inspect", "logs", "restart"] },
+                    "container": { "type": "string", "description": "Container name or id" },
+                    "all": { "type": "boolean", "default": false, "description": "Include stopped containers in list" },
+                    "tail_lines": { "type": "integer", "default": 200, "maximum": MAX_TAIL_LINES }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+    
+    async fn call(&self, args: Self::Args) -> Result<serde_json::Value, DockerError> {
+        use bollard::container::{ListContainersOptions, LogsOptions};
+        use futures::StreamExt;
+
+        let container = || args.container.clone()
+            .ok_or_else(|| DockerError("container is required".into()));
+
+        match args.action {
+            DockerAction::List => {
+                let options = ListContainersOptions::<String> { all: args.all, ..Default::default() };
+                let containers = self.docker.list_containers(Some(options)).await?;
+                Ok(serde_json::Value::Array(containers.iter().map(|c| serde_json::json!({
+                    "id": c.id.as_deref().map(|id| &id[..id.len().min(12)]),
+                    "names": c.names,
+                    "image": c.image,
+                    "state": c.state,
+                    "status": c.status,
+                })).collect()))
+            }
+            DockerAction::Inspect => {
+                let details = self.docker.inspect_container(&container()?, None).await?;
+                serde_json::to_value(details).map_err(|e| DockerError(e.to_string()))
+            }
+            DockerAction::Logs => {
+                let options = LogsOptions::<String> {
+                    stdout: true,
+                    stderr: true,
+                    tail: args.tail_lines.clamp(1, MAX_TAIL_LINES).to_string(),
+                    ..Default::default()
+                };
+                let mut stream = self.docker.logs(&container()?, Some(options));
+                let mut logs = String::new();
+                let mut truncated = false;
+                while let Some(line) = stream.next().await {
+                    logs.push_str(&line?.to_string());
+                    // Dropping the stream stops the rest of the output
+                    if truncate_bytes(&mut logs, self.max_log_bytes) {
+                        truncated = true;
+                        break;
+                    }
+                }
+                Ok(serde_json::json!({ "logs": logs, "truncated": truncated }))
+            }
+            DockerAction::Restart => {
+                let container = container()?;
+                self.docker.restart_container(&container, None).await?;
+                Ok(serde_json::json!({ "container": container, "restarted": true }))
+            }
+        }
+    }
+}
+
+// Wikipedia Lookup Tool
+#[derive(Deserialize, JsonSchema)]
+pub struct WikiInput {
+    topic: String,
+    #[serde(default = "default_wiki_language")]
+    language: String,
+}
+
+fn default_wiki_language() -> String { "en".to_string() }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WikiSummary {
+    pub title: String,
+    pub extract: String,
+    pub url: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Wikipedia error: {0}")]
+pub struct WikiError(String);
+
+pub struct WikiLookup {
+    client: reqwest::Client,
+}
+
+impl Default for WikiLookup {
+    fn default() -> Self {
+        // Wikimedia asks API clients to identify themselves
+        let client = reqwest::Client::builder()
+            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("static client config");
+        Self { client }
+    }
+}
+
+impl WikiLookup {
+    async fn summary(&self, language: &str, title: &str) -> Result<Option<WikiSummary>, WikiError> {
+        #[derive(Deserialize)]
+        struct Page { title: String, extract: String, content_urls: Urls }
+        #[derive(Deserialize)]
+        struct Urls { desktop: Desktop }
+        #[derive(Deserialize)]
+        struct Desktop { page: String }
+
+        let url = format!(
+            "https://{language}.wikipedia.org/api/rest_v1/page/summary/{}",
+            urlencoding::encode(&title.replace(' ', "_"))
+        );
+        let response = self.client.get(url).send().await
+            .map_err(|e| WikiError(e.to_string()))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let page: Page = response.error_for_status()
+            .map_err(|e| WikiError(e.to_string()))?
+            .json().await
+            .map_err(|e| WikiError(e.to_string()))?;
+        Ok(Some(WikiSummary { title: page.title, extract: page.extract, url: page.content_urls.desktop.page }))
+    }
+
+    /// Best title match for loosely phrased topics ("rust language" -> "Rust (programming language)")
+    async fn search(&self, language: &str, query: &str) -> Result<Option<String>, WikiError> {
+        #[derive(Deserialize)]
+        struct Results { pages: Vec<Hit> }
+        #[derive(Deserialize)]
+        struct Hit { key: String }
+
+        let results: Results = self.client
+            .get(format!("https://{language}.wikipedia.org/w/rest.php/v1/search/title"))
+            .query(&[("q", query), ("limit", "1")])
             .send().await
-            .map_err(|e| HttpError(e.to_string()))?
-            .text().await
-            .map_err(|e| HttpError(e.to_string()))
+            .map_err(|e| WikiError(e.to_string()))?
+            .json().await
+            .map_err(|e| WikiError(e.to_string()))?;
+        Ok(results.pages.into_iter().next().map(|hit| hit.key))
+    }
+}
+
+impl Tool for WikiLookup {
+    const NAME: &'static str = "wikipedia";
+    type Args = WikiInput;
+    type Output = WikiSummary;
+    type Error = WikiError;
+    
+    async fn definition(&self, _: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Look up a topic on Wikipedia and return its summary and URL".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "topic": { "type": "string" },
+                    "language": {
+                        "type": "string",
+                        "default": "en",
+                        "description": "Wikipedia language code, e.g. en, de, ja"
+                    }
+                },
+                "required": ["topic"]
+            }),
+        }
+    }
+    
+    async fn call(&self, args: Self::Args) -> Result<WikiSummary, WikiError> {
+        // The language becomes part of the hostname, so keep it to a plain code
+        let valid = !args.language.is_empty()
+            && args.language.len() <= 12
+            && args.language.chars().all(|c| c.is_ascii_lowercase() || c == '-');
+        if !valid {
+            return Err(WikiError(format!("Invalid language code: {}", args.language)));
+        }
+
+        if let Some(summary) = self.summary(&args.language, &args.topic).await? {
+            return Ok(summary);
+        }
+        match self.search(&args.language, &args.topic).await? {
+            Some(title) => self.summary(&args.language, &title).await?
+                .ok_or_else(|| WikiError(format!("No article found for {}", args.topic))),
+            None => Err(WikiError(format!("No article found for {}", args.topic))),
+        }
+    }
+}
+
+// RSS/Atom Feed Reader Tool
+#[derive(Deserialize, JsonSchema)]
+pub struct FeedInput {
+    url: String,
+    #[serde(default = "default_feed_limit")]
+    limit: usize,
+}
+
+fn default_feed_limit() -> usize { 10 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedEntry {
+    pub title: String,
+    pub published: Option<String>,
+    pub link: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Feed error: {0}")]
+pub struct FeedError(String);
+
+struct CachedFeed {
+    fetched: std::time::Instant,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    entries: Vec<FeedEntry>,
+}
+
+pub struct FeedReader {
+    client: reqwest::Client,
+    ttl: Duration,
+    cache: std::sync::Mutex<HashMap<String, CachedFeed>>,
+}
+
+impl Default for FeedReader {
+    fn default() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            ttl: Duration::from_secs(15 * 60),
+            cache: Default::default(),
+        }
+    }
+}
+
+impl FeedReader {
+    /// How long a feed is served from cache before it is revalidated
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<FeedEntry>, FeedError> {
+        // The lock is never held across a request, so one slow feed doesn't block the others
+        let validators = {
+            let cache = self.cache.lock().unwrap();
+            match cache.get(url) {
+                Some(cached) if cached.fetched.elapsed() < self.ttl => return Ok(cached.entries.clone()),
+                Some(cached) => Some((cached.etag.clone(), cached.last_modified.clone())),
+                None => None,
+            }
+        };
+
+        // Past the TTL, revalidate with a conditional GET so unchanged feeds cost a 304
+        let mut req = self.client.get(url);
+        if let Some((etag, last_modified)) = validators {
+            if let Some(etag) = etag {
+                req = req.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(modified) = last_modified {
+                req = req.header(reqwest::header::IF_MODIFIED_SINCE, modified);
+            }
+        }
+        let mut response = req.send().await.map_err(|e| FeedError(e.to_string()))?;
+
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            if let Some(cached) = self.cache.lock().unwrap().get_mut(url) {
+                cached.fetched = std::time::Instant::now();
+                return Ok(cached.entries.clone());
+            }
+            // Nothing cached to serve and a 304 has no body, so fetch unconditionally
+            response = self.client.get(url).send().await.map_err(|e| FeedError(e.to_string()))?;
+        }
+
+        let response = response.error_for_status().map_err(|e| FeedError(e.to_string()))?;
+        let header = |name: reqwest::header::HeaderName| response.headers().get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+        let bytes = response.bytes().await.map_err(|e| FeedError(e.to_string()))?;
+
+        let feed = feed_rs::parser::parse(&bytes[..]).map_err(|e| FeedError(e.to_string()))?;
+        let mut entries: Vec<FeedEntry> = feed.entries.into_iter()
+            .map(|entry| FeedEntry {
+                title: entry.title.map(|t| t.content).unwrap_or_default(),
+                published: entry.published.or(entry.updated).map(|d| d.to_rfc3339()),
+                link: entry.links.into_iter().next().map(|l| l.href),
+            })
+            .collect();
+        // RFC 3339 strings sort chronologically; undated entries go last
+        entries.sort_by(|a, b| b.published.cmp(&a.published));
+
+        self.cache.lock().unwrap().insert(url.to_string(), CachedFeed {
+            fetched: std::time::Instant::now(),
+            etag,
+            last_modified,
+            entries: entries.clone(),
+        });
+        Ok(entries)
+    }
+}
+
+impl Tool for FeedReader {
+    const NAME: &'static str = "read_feed";
+    type Args = FeedInput;
+    type Output = Vec<FeedEntry>;
+    type Error = FeedError;
+    
+    async fn definition(&self, _: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Fetch an RSS or Atom feed and return its most recent entries".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "url": { "type": "string" },
+                    "limit": { "type": "integer", "default": 10 }
+                },
+                "required": ["url"]
+            }),
+        }
+    }
+    
+    async fn call(&self, args: Self::Args) -> Result<Vec<FeedEntry>, FeedError> {
+        let mut entries = self.fetch(&args.url).await?;
+        entries.truncate(args.limit);
+        Ok(entries)
+    }
+}
+
+// Idempotency Markers
+impl Idempotency for Calculator {
+    fn is_idempotent(&self, _: &CalculatorInput) -> bool {
+        // Re-running an assignment rebinds the same value
+        true
+    }
+}
+
+impl Idempotency for HttpClient {
+    fn is_idempotent(&self, args: &HttpInput) -> bool {
+        let method = if args.method.is_empty() { "GET" } else { &args.method };
+        matches!(
+            method.to_ascii_uppercase().as_str(),
+            "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE"
+        )
+    }
+}
+
+impl Idempotency for RedisTool {
+    fn is_idempotent(&self, args: &RedisInput) -> bool {
+        // A retried SET can overwrite what another client wrote after the
+        // first attempt landed; reads and DEL end in the same state
+        !matches!(args.action, RedisAction::Set)
+    }
+}
+
+impl Idempotency for BrowserTool {
+    fn is_idempotent(&self, args: &BrowserInput) -> bool {
+        !matches!(args.action, BrowserAction::Click)
+    }
+}
+
+impl Idempotency for KubeTool {
+    fn is_idempotent(&self, _: &KubeInput) -> bool {
+        true
+    }
+}
+
+impl Idempotency for DockerTool {
+    fn is_idempotent(&self, args: &DockerInput) -> bool {
+        !matches!(args.action, DockerAction::Restart)
+    }
+}
+
+impl Idempotency for WikiLookup {
+    fn is_idempotent(&self, _: &WikiInput) -> bool {
+        true
+    }
+}
+
+impl Idempotency for FeedReader {
+    fn is_idempotent(&self, _: &FeedInput) -> bool {
+        true
+    }
+}
+
+// Cacheability Markers
+impl Cacheable for Calculator {
+    /// Plain arithmetic depends only on its text; assignments and variable
+    /// reads depend on what earlier calls bound
+    fn is_cacheable(&self, args: &CalculatorInput) -> bool {
+        tokenize(&args.expression).is_ok_and(|tokens| !reads_variables(&tokens))
+    }
+}
+
+impl Cacheable for HttpClient {
+    fn is_cacheable(&self, args: &HttpInput) -> bool {
+        let method = if args.method.is_empty() { "GET" } else { &args.method };
+        matches!(method.to_ascii_uppercase().as_str(), "GET" | "HEAD")
+    }
+}
+
+impl Cacheable for WikiLookup {
+    fn is_cacheable(&self, _: &WikiInput) -> bool {
+        true
+    }
+}
+
+impl Cacheable for FeedReader {
+    fn is_cacheable(&self, _: &FeedInput) -> bool {
+        true
+    }
+}
+
+// Confirmation Defaults
+impl Confirmation for Calculator {
+    fn requires_confirmation(&self, _: &CalculatorInput) -> bool {
+        false
+    }
+}
+
+impl Confirmation for HttpClient {
+    /// Anything that can change remote state needs approval
+    fn requires_confirmation(&self, args: &HttpInput) -> bool {
+        let method = if args.method.is_empty() { "GET" } else { &args.method };
+        !matches!(method.to_ascii_uppercase().as_str(), "GET" | "HEAD" | "OPTIONS")
+    }
+}
+
+impl Confirmation for RedisTool {
+    fn requires_confirmation(&self, args: &RedisInput) -> bool {
+        matches!(args.action, RedisAction::Set | RedisAction::Del)
+    }
+}
+
+impl Confirmation for BrowserTool {
+    /// Clicks can submit forms or trigger purchases
+    fn requires_confirmation(&self, args: &BrowserInput) -> bool {
+        matches!(args.action, BrowserAction::Click)
+    }
+}
+
+impl Confirmation for KubeTool {
+    fn requires_confirmation(&self, _: &KubeInput) -> bool {
+        false
+    }
+}
+
+impl Confirmation for DockerTool {
+    fn requires_confirmation(&self, args: &DockerInput) -> bool {
+        matches!(args.action, DockerAction::Restart)
+    }
+}
+
+impl Confirmation for WikiLookup {
+    fn requires_confirmation(&self, _: &WikiInput) -> bool {
+        false
+    }
+}
+
+impl Confirmation for FeedReader {
+    fn requires_confirmation(&self, _: &FeedInput) -> bool {
+        false
     }
 }
@@ -0,0 +1,222 @@
+//! Watcher Templates for Rig
+//!
+//! Keep an index or a set of summaries current by re-running the pipeline
+//! when files change, instead of on a timer or by hand. Editors save in
+//! bursts (temp file, rename, touch), so events are debounced: the pipeline
+//! runs once the directory has been quiet for a moment, with every path that
+//! changed in the burst. Changes that arrive while it runs are batched into
+//! the next run rather than starting an overlapping one.
+//!
+//! ```toml
+//! [dependencies]
+//! notify = "6"
+//! ```
+
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::config_template::CLIENTS;
+use crate::loader_template::Indexer;
+use crate::rag_template::{InMemoryVectorStore, Metric};
+
+// =============================================================================
+// CHANGES
+// =============================================================================
+
+/// Paths touched during one debounced burst, sorted. A directory that was
+/// created, renamed, or deleted appears as the directory itself, not the
+/// files in it
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    /// Created or modified, and present when the burst ended
+    pub changed: BTreeSet<PathBuf>,
+    /// Gone when the burst ended
+    pub removed: BTreeSet<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changed.len() + self.removed.len()
+    }
+}
+
+// =============================================================================
+// DIRECTORY WATCHER
+// =============================================================================
+
+// Usage:
+//     DirWatcher::new("docs")
+//         .extensions(&["md", "txt"])
+//         .debounce(Duration::from_millis(750))
+//         .run(|changes| async move { summarize(&changes.changed).await }, shutdown)
+//         .await?;
+//
+// The directory is watched recursively. Files in hidden directories and
+// editor scratch files (`.swp`, `~`, `.tmp`) are ignored. A failed run is
+// logged and the watcher keeps going; the next change retries it. Keep the
+// pipeline's own output (an index file, summaries) outside the watched
+// directory or outside `extensions`, or every run triggers the next.
+
+pub struct DirWatcher {
+    root: PathBuf,
+    extensions: Vec<String>,
+    debounce: Duration,
+    max_wait: Duration,
+}
+
+impl DirWatcher {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), extensions: Vec::new(), debounce: Duration::from_millis(500), max_wait: Duration::from_secs(10) }
+    }
+
+    /// Only react to these extensions (without the dot); all files when empty
+    pub fn extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|e| e.trim_start_matches('.').to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Quiet period that ends a burst
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Longest a burst may grow before the pipeline runs anyway, for files
+    /// that are written continuously
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Call `on_change` after each burst until `shutdown` resolves
+    pub async fn run<F, Fut>(&self, on_change: F, shutdown: impl Future<Output = ()>) -> Result<()>
+    where
+        F: Fn(Changes) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let root = self.root.canonicalize().map_err(|e| anyhow::anyhow!("cannot watch {}: {e}", self.root.display()))?;
+        let (tx, mut events) = mpsc::unbounded_channel();
+        // notify calls this from its own thread
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let _ = tx.send(event);
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        tracing::info!(root = %root.display(), "watching for changes");
+        tokio::pin!(shutdown);
+
+        loop {
+            let first = tokio::select! {
+                _ = &mut shutdown => break,
+                event = events.recv() => match event {
+                    Some(event) => event,
+                    None => anyhow::bail!("file watcher stopped"),
+                },
+            };
+
+            let mut touched = BTreeSet::new();
+            self.collect(first, &root, &mut touched);
+            let started = Instant::now();
+            while started.elapsed() < self.max_wait {
+                match tokio::time::timeout(self.debounce, events.recv()).await {
+                    Ok(Some(event)) => self.collect(event, &root, &mut touched),
+                    Ok(None) => anyhow::bail!("file watcher stopped"),
+                    Err(_) => break,
+                }
+            }
+
+            // Decide by what's on disk now: an editor's save may show up as a
+            // remove followed by a create of the same path
+            let (changed, removed) = touched.into_iter().partition(|path: &PathBuf| path.exists());
+            let changes = Changes { changed, removed };
+            if changes.is_empty() {
+                continue;
+            }
+
+            let run_started = Instant::now();
+            tracing::info!(changed = changes.changed.len(), removed = changes.removed.len(), "files changed");
+            match on_change(changes).await {
+                Ok(()) => tracing::info!(elapsed = ?run_started.elapsed(), "pipeline finished"),
+                Err(e) => tracing::error!(error = %e, "pipeline failed; will retry on the next change"),
+            }
+        }
+        Ok(())
+    }
+
+    fn collect(&self, event: notify::Result<Event>, root: &Path, touched: &mut BTreeSet<PathBuf>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!(error = %e, "file watch error");
+                return;
+            }
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+            return;
+        }
+        // Deleting or renaming a directory reports only the directory, which
+        // has no extension; let it through so the files inside aren't missed
+        let moved = matches!(event.kind, EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)));
+        touched.extend(event.paths.into_iter().filter(|path| self.wanted(path, root, moved)));
+    }
+
+    fn wanted(&self, path: &Path, root: &Path, moved: bool) -> bool {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let hidden = relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let scratch = name.ends_with('~') || name.ends_with(".swp") || name.ends_with(".tmp");
+        let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let directory = path.is_dir() || (moved && extension.is_none());
+        let listed = self.extensions.is_empty() || directory || extension.is_some_and(|e| self.extensions.contains(&e));
+        !hidden && !scratch && listed
+    }
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// Re-index `docs/` whenever a Markdown or text file in it changes. The
+/// manifest makes each run re-embed only the files whose content changed,
+/// and the index lives outside `docs/` so saving it doesn't trigger a run
+pub async fn watch_and_index() -> Result<()> {
+    let model = CLIENTS.openai().embedding_model(rig::providers::openai::TEXT_EMBEDDING_3_SMALL);
+    let store = Arc::new(InMemoryVectorStore::load_or_new("index.json", Metric::Cosine)?);
+    let indexer = Indexer::new(store.clone(), model).manifest("index.manifest.json");
+
+    // Catch up on anything that changed while we weren't watching
+    indexer.run(Path::new("docs")).await?;
+    store.save("index.json")?;
+
+    DirWatcher::new("docs")
+        .extensions(&["md", "txt"])
+        .debounce(Duration::from_millis(750))
+        .run(
+            // The indexer finds what changed from the manifest; removed files
+            // have their vectors deleted there too
+            |_changes| {
+                let (indexer, store) = (&indexer, &store);
+                async move {
+                    let stats = indexer.run(Path::new("docs")).await?;
+                    store.save("index.json")?;
+                    tracing::info!(files = stats.files, removed = stats.removed, "index updated");
+                    Ok(())
+                }
+            },
+            async {
+                tokio::signal::ctrl_c().await.ok();
+            },
+        )
+        .await
+}
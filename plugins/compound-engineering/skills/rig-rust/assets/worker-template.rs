@@ -0,0 +1,584 @@
+//! Worker Templates for Rig
+//!
+//! Agent jobs queued on a Redis stream and processed by any number of worker
+//! processes. Workers share a consumer group, so each task goes to exactly
+//! one of them; a task is acknowledged only after its result is stored, so a
+//! worker that dies mid-task loses nothing: another worker claims the task
+//! once it has sat idle long enough. Failed tasks are retried, and tasks that
+//! keep failing land on a dead-letter stream for a person to look at. A panic
+//! in a handler counts as a failed attempt, and so does a crash: a task that
+//! keeps killing its worker is dead-lettered rather than claimed forever.
+//!
+//! ```toml
+//! [dependencies]
+//! redis = { version = "0.27", features = ["tokio-comp", "connection-manager"] }
+//! ```
+//!
+//! Keys, for a stream named `agent-tasks`:
+//! ```text
+//! agent-tasks              pending tasks (XADD by `Queue::enqueue`)
+//! agent-tasks:dead         tasks that failed `max_attempts` times
+//! agent-tasks:result:<id>  each task's JSON output, expiring after `result_ttl`
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rig::completion::Prompt;
+use rig::pipeline::Op;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+
+use crate::config_template::CLIENTS;
+use crate::pipeline_template::{article_pipeline, Brief, Outline, OUTLINE_PREAMBLE, SUMMARY_PREAMBLE, WRITER_PREAMBLE};
+
+// =============================================================================
+// TASKS
+// =============================================================================
+
+/// Stream entry fields, as (entry id, field -> value)
+type Entry = (String, HashMap<String, String>);
+
+/// An entry and how many times it has been delivered, 1 unless it was claimed
+type Delivery = (Entry, u32);
+
+#[derive(Debug, Clone)]
+pub struct Task {
+    /// Stable across retries: the stream id the task was first enqueued under
+    pub id: String,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    /// 0 on the first run
+    pub attempt: u32,
+    /// Stream id of this delivery
+    entry: String,
+}
+
+impl Task {
+    fn parse((entry, fields): &Entry) -> Result<Self> {
+        let field = |name: &str| fields.get(name).ok_or_else(|| anyhow::anyhow!("entry {entry} has no `{name}` field"));
+        Ok(Self {
+            id: fields.get("task_id").unwrap_or(entry).clone(),
+            kind: field("kind")?.clone(),
+            payload: serde_json::from_str(field("payload")?)
+                .map_err(|e| anyhow::anyhow!("entry {entry} has a malformed payload: {e}"))?,
+            attempt: fields.get("attempt").and_then(|a| a.parse().ok()).unwrap_or(0),
+            entry: entry.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    /// Stream id on the dead-letter stream
+    pub entry: String,
+    pub task_id: String,
+    pub kind: String,
+    /// Raw payload; not parsed, since a malformed payload may be why it's here
+    pub payload: String,
+    pub attempts: u32,
+    pub error: String,
+}
+
+// =============================================================================
+// HANDLERS
+// =============================================================================
+
+/// Turns a task's payload into its result; one handler per task kind
+pub trait Handler: Send + Sync {
+    fn handle<'a>(&'a self, payload: serde_json::Value) -> BoxFuture<'a, Result<serde_json::Value>>;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(serde_json::Value) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<serde_json::Value>> + Send + 'static,
+{
+    fn handle<'a>(&'a self, payload: serde_json::Value) -> BoxFuture<'a, Result<serde_json::Value>> {
+        Box::pin(self(payload))
+    }
+}
+
+/// A `rig::pipeline` op as a handler: the payload deserializes into the op's
+/// input and its output serializes into the result
+pub struct OpHandler<O>(pub O);
+
+impl<O, I, T, E> Handler for OpHandler<O>
+where
+    O: Op<Input = I, Output = Result<T, E>>,
+    I: DeserializeOwned + Send,
+    T: Serialize,
+    E: Into<anyhow::Error>,
+{
+    fn handle<'a>(&'a self, payload: serde_json::Value) -> BoxFuture<'a, Result<serde_json::Value>> {
+        Box::pin(async move {
+            let input: I = serde_json::from_value(payload)?;
+            let output = self.0.call(input).await.map_err(Into::into)?;
+            Ok(serde_json::to_value(output)?)
+        })
+    }
+}
+
+// =============================================================================
+// QUEUE
+// =============================================================================
+
+// The producer side, for API servers and scripts:
+//
+//     let queue = Queue::connect("redis://127.0.0.1/", "agent-tasks").await?;
+//     let id = queue.enqueue("summarize", &json!({ "text": text })).await?;
+//     ...
+//     if let Some(result) = queue.result(&id).await? { ... }
+
+#[derive(Clone)]
+pub struct Queue {
+    conn: redis::aio::ConnectionManager,
+    stream: String,
+}
+
+impl Queue {
+    pub async fn connect(url: &str, stream: impl Into<String>) -> Result<Self> {
+        let conn = redis::aio::ConnectionManager::new(redis::Client::open(url)?).await?;
+        Ok(Self { conn, stream: stream.into() })
+    }
+
+    /// Returns the task id results are stored under
+    pub async fn enqueue(&self, kind: &str, payload: &impl Serialize) -> Result<String> {
+        let id: String = redis::cmd("XADD")
+            .arg(&self.stream).arg("*")
+            .arg("kind").arg(kind)
+            .arg("payload").arg(serde_json::to_string(payload)?)
+            .arg("attempt").arg(0)
+            .query_async(&mut self.conn.clone()).await?;
+        Ok(id)
+    }
+
+    /// The task's output, once a worker has finished it
+    pub async fn result(&self, task_id: &str) -> Result<Option<serde_json::Value>> {
+        let raw: Option<String> = redis::cmd("GET")
+            .arg(result_key(&self.stream, task_id))
+            .query_async(&mut self.conn.clone()).await?;
+        Ok(raw.map(|raw| serde_json::from_str(&raw)).transpose()?)
+    }
+
+    /// Oldest first
+    pub async fn dead_letters(&self, count: usize) -> Result<Vec<DeadLetter>> {
+        let entries: Vec<Entry> = redis::cmd("XRANGE")
+            .arg(dead_key(&self.stream)).arg("-").arg("+")
+            .arg("COUNT").arg(count)
+            .query_async(&mut self.conn.clone()).await?;
+        Ok(entries.into_iter()
+            .map(|(entry, mut fields)| {
+                let mut take = |name: &str| fields.remove(name).unwrap_or_default();
+                DeadLetter {
+                    task_id: take("task_id"),
+                    kind: take("kind"),
+                    payload: take("payload"),
+                    attempts: take("attempt").parse::<u32>().map_or(0, |a| a + 1),
+                    error: take("error"),
+                    entry,
+                }
+            })
+            .collect())
+    }
+
+    /// Put a dead letter back on the queue with a fresh attempt count, after
+    /// fixing whatever made it fail
+    pub async fn requeue(&self, dead: &DeadLetter) -> Result<()> {
+        let _: () = redis::pipe()
+            .atomic()
+            .cmd("XADD").arg(&self.stream).arg("*")
+            .arg("task_id").arg(&dead.task_id)
+            .arg("kind").arg(&dead.kind)
+            .arg("payload").arg(&dead.payload)
+            .arg("attempt").arg(0)
+            .ignore()
+            .cmd("XDEL").arg(dead_key(&self.stream)).arg(&dead.entry)
+            .ignore()
+            .query_async(&mut self.conn.clone()).await?;
+        Ok(())
+    }
+}
+
+fn dead_key(stream: &str) -> String {
+    format!("{stream}:dead")
+}
+
+fn result_key(stream: &str, task_id: &str) -> String {
+    format!("{stream}:result:{task_id}")
+}
+
+// =============================================================================
+// WORKER
+// =============================================================================
+
+// Usage:
+//     Worker::connect("redis://127.0.0.1/", "agent-tasks", "agents").await?
+//         .handler("summarize", summarize)
+//         .handler("article", OpHandler(article_pipeline(outliner, writer)))
+//         .run(async { tokio::signal::ctrl_c().await.ok(); })
+//         .await?;
+//
+// Run as many copies as the load needs. A retry goes to the back of the
+// stream, so it waits behind the tasks already queued. Tasks of an unknown
+// kind, or with a payload that isn't JSON, go straight to the dead letters.
+
+pub struct Worker {
+    client: redis::Client,
+    conn: redis::aio::ConnectionManager,
+    stream: String,
+    group: String,
+    consumer: String,
+    handlers: HashMap<String, Arc<dyn Handler>>,
+    concurrency: usize,
+    max_attempts: u32,
+    timeout: Duration,
+    claim_idle: Duration,
+    block: Duration,
+    result_ttl: Duration,
+}
+
+impl Worker {
+    /// Creates the stream and consumer group if they don't exist yet
+    pub async fn connect(url: &str, stream: impl Into<String>, group: impl Into<String>) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        let mut conn = redis::aio::ConnectionManager::new(client.clone()).await?;
+        let (stream, group) = (stream.into(), group.into());
+
+        // From "0", so tasks enqueued before the first worker started are not skipped
+        let created: redis::RedisResult<()> = redis::cmd("XGROUP")
+            .arg("CREATE").arg(&stream).arg(&group).arg("0").arg("MKSTREAM")
+            .query_async(&mut conn).await;
+        match created {
+            Err(e) if e.code() != Some("BUSYGROUP") => return Err(e.into()),
+            _ => {}
+        }
+
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string());
+        Ok(Self {
+            client,
+            conn,
+            stream,
+            group,
+            consumer: format!("{host}-{}", std::process::id()),
+            handlers: HashMap::new(),
+            concurrency: 4,
+            max_attempts: 3,
+            timeout: Duration::from_secs(300),
+            claim_idle: Duration::from_secs(900),
+            block: Duration::from_secs(2),
+            result_ttl: Duration::from_secs(24 * 3600),
+        })
+    }
+
+    pub fn handler(mut self, kind: impl Into<String>, handler: impl Handler + 'static) -> Self {
+        self.handlers.insert(kind.into(), Arc::new(handler));
+        self
+    }
+
+    /// Consumer name within the group; defaults to `$HOSTNAME-pid`, and must be
+    /// unique among running workers
+    pub fn consumer(mut self, name: impl Into<String>) -> Self {
+        self.consumer = name.into();
+        self
+    }
+
+    /// Tasks in flight at once
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Runs before a task is dead-lettered, counting the first
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Per-task limit; a task that hits it counts as a failed attempt
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long a delivered, unacknowledged task sits before another worker
+    /// takes it over; must be longer than `timeout`
+    pub fn claim_idle(mut self, idle: Duration) -> Self {
+        self.claim_idle = idle;
+        self
+    }
+
+    pub fn result_ttl(mut self, ttl: Duration) -> Self {
+        self.result_ttl = ttl;
+        self
+    }
+
+    /// Process tasks until `shutdown` resolves, then finish the ones in flight
+    pub async fn run(self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        anyhow::ensure!(!self.handlers.is_empty(), "worker has no handlers");
+        anyhow::ensure!(
+            self.claim_idle > self.timeout,
+            "claim_idle ({:?}) must exceed the task timeout ({:?}), or running tasks get claimed twice",
+            self.claim_idle,
+            self.timeout
+        );
+
+        // Blocking reads get their own connection so they don't stall acks
+        let mut reader = self.client.get_multiplexed_async_connection().await?;
+        let worker = Arc::new(self);
+        let permits = Arc::new(Semaphore::new(worker.concurrency));
+        let mut last_claim: Option<Instant> = None;
+        tokio::pin!(shutdown);
+        tracing::info!(stream = %worker.stream, consumer = %worker.consumer, "worker started");
+
+        loop {
+            let permit = tokio::select! {
+                _ = &mut shutdown => break,
+                permit = permits.clone().acquire_owned() => permit?,
+            };
+            let room = permits.available_permits() + 1;
+
+            let entries = if !matches!(last_claim, Some(at) if at.elapsed() < worker.claim_idle / 2) {
+                last_claim = Some(Instant::now());
+                worker.claim(&mut reader, room).await?
+            } else {
+                Vec::new()
+            };
+            let entries = if entries.is_empty() { worker.read(&mut reader, room).await? } else { entries };
+
+            let mut permit = Some(permit);
+            for (entry, deliveries) in entries {
+                // The first entry reuses the permit we waited for; the rest were free when we asked
+                let permit = match permit.take() {
+                    Some(permit) => permit,
+                    None => permits.clone().acquire_owned().await?,
+                };
+                let worker = worker.clone();
+                tokio::spawn(async move {
+                    worker.process(entry, deliveries).await;
+                    drop(permit);
+                });
+            }
+        }
+
+        tracing::info!(consumer = %worker.consumer, "worker draining");
+        let _all = permits.acquire_many(worker.concurrency as u32).await?;
+        Ok(())
+    }
+
+    async fn read(&self, reader: &mut redis::aio::MultiplexedConnection, count: usize) -> Result<Vec<Delivery>> {
+        let reply: Option<Vec<(String, Vec<Entry>)>> = redis::cmd("XREADGROUP")
+            .arg("GROUP").arg(&self.group).arg(&self.consumer)
+            .arg("COUNT").arg(count)
+            .arg("BLOCK").arg(self.block.as_millis() as u64)
+            .arg("STREAMS").arg(&self.stream).arg(">")
+            .query_async(reader).await?;
+        Ok(reply.into_iter().flatten().flat_map(|(_, entries)| entries).map(|entry| (entry, 1)).collect())
+    }
+
+    /// Take over tasks left unacknowledged by workers that died mid-task
+    async fn claim(&self, reader: &mut redis::aio::MultiplexedConnection, count: usize) -> Result<Vec<Delivery>> {
+        // [next cursor, entries, deleted ids]; the last is missing before Redis 7
+        let reply: Vec<redis::Value> = redis::cmd("XAUTOCLAIM")
+            .arg(&self.stream).arg(&self.group).arg(&self.consumer)
+            .arg(self.claim_idle.as_millis() as u64).arg("0-0")
+            .arg("COUNT").arg(count)
+            .query_async(reader).await?;
+        let entries: Vec<Entry> = match reply.get(1) {
+            Some(entries) => redis::from_redis_value(entries)?,
+            None => Vec::new(),
+        };
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+        tracing::warn!(stream = %self.stream, claimed = entries.len(), "claimed abandoned tasks");
+
+        // XAUTOCLAIM bumped each entry's delivery count; XPENDING reports it as
+        // [id, consumer, idle ms, deliveries]
+        let mut pipe = redis::pipe();
+        for (id, _) in &entries {
+            pipe.cmd("XPENDING").arg(&self.stream).arg(&self.group).arg(id).arg(id).arg(1);
+        }
+        let pending: Vec<Vec<(String, String, u64, u32)>> = pipe.query_async(reader).await?;
+        Ok(entries
+            .into_iter()
+            .zip(pending)
+            .map(|(entry, info)| {
+                let deliveries = info.first().map_or(1, |(_, _, _, deliveries)| *deliveries);
+                (entry, deliveries)
+            })
+            .collect())
+    }
+
+    async fn process(&self, entry: Entry, deliveries: u32) {
+        let mut task = match Task::parse(&entry) {
+            Ok(task) => task,
+            Err(e) => {
+                let (id, fields) = &entry;
+                let get = |name: &str| fields.get(name).cloned().unwrap_or_default();
+                return self.settle_dead(id, &get("task_id"), &get("kind"), &get("payload"), 0, &e).await;
+            }
+        };
+        // Each earlier delivery of this entry ended with its worker gone mid-task
+        let crashes = deliveries.saturating_sub(1);
+        task.attempt += crashes;
+        if crashes > 0 && task.attempt >= self.max_attempts {
+            let e = anyhow::anyhow!("abandoned mid-task {crashes} times; the handler may be crashing its worker");
+            tracing::error!(id = %task.id, kind = %task.kind, error = %e, "task failed for good");
+            let payload = task.payload.to_string();
+            return self.settle_dead(&task.entry, &task.id, &task.kind, &payload, task.attempt, &e).await;
+        }
+
+        let span = tracing::info_span!("task", id = %task.id, kind = %task.kind, attempt = task.attempt);
+        async {
+            let started = Instant::now();
+            let outcome = match self.handlers.get(&task.kind) {
+                Some(handler) => {
+                    let handled = std::panic::AssertUnwindSafe(handler.handle(task.payload.clone())).catch_unwind();
+                    match tokio::time::timeout(self.timeout, handled).await {
+                        Ok(Ok(result)) => result.map_err(|e| (e, true)),
+                        Ok(Err(panic)) => Err((anyhow::anyhow!("handler panicked: {}", panic_message(&*panic)), true)),
+                        Err(_) => Err((anyhow::anyhow!("timed out after {:?}", self.timeout), true)),
+                    }
+                }
+                None => Err((anyhow::anyhow!("no handler for task kind `{}`", task.kind), false)),
+            };
+
+            match outcome {
+                Ok(output) => {
+                    tracing::info!(elapsed = ?started.elapsed(), "task done");
+                    self.settle_done(&task, &output).await;
+                }
+                Err((e, retryable)) if retryable && task.attempt + 1 < self.max_attempts => {
+                    tracing::warn!(error = %e, "task failed, retrying");
+                    self.settle_retry(&task, &e).await;
+                }
+                Err((e, _)) => {
+                    tracing::error!(error = %e, "task failed for good");
+                    let payload = task.payload.to_string();
+                    self.settle_dead(&task.entry, &task.id, &task.kind, &payload, task.attempt, &e).await;
+                }
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Store the result, then ack, in one transaction
+    async fn settle_done(&self, task: &Task, output: &serde_json::Value) {
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("SET").arg(result_key(&self.stream, &task.id)).arg(output.to_string())
+            .arg("EX").arg(self.result_ttl.as_secs().max(1))
+            .ignore();
+        self.ack(&mut pipe, &task.entry).await;
+    }
+
+    /// Re-add at the back of the stream with the attempt bumped, then ack
+    async fn settle_retry(&self, task: &Task, error: &anyhow::Error) {
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("XADD").arg(&self.stream).arg("*")
+            .arg("task_id").arg(&task.id)
+            .arg("kind").arg(&task.kind)
+            .arg("payload").arg(task.payload.to_string())
+            .arg("attempt").arg(task.attempt + 1)
+            .arg("error").arg(format!("{error:#}"))
+            .ignore();
+        self.ack(&mut pipe, &task.entry).await;
+    }
+
+    async fn settle_dead(&self, entry: &str, task_id: &str, kind: &str, payload: &str, attempt: u32, error: &anyhow::Error) {
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("XADD").arg(dead_key(&self.stream)).arg("*")
+            .arg("task_id").arg(if task_id.is_empty() { entry } else { task_id })
+            .arg("kind").arg(kind)
+            .arg("payload").arg(payload)
+            .arg("attempt").arg(attempt)
+            .arg("error").arg(format!("{error:#}"))
+            .arg("consumer").arg(&self.consumer)
+            .ignore();
+        self.ack(&mut pipe, entry).await;
+    }
+
+    /// Append XACK + XDEL for `entry` and run the transaction. If Redis is
+    /// unreachable the entry stays pending and is claimed again later, so the
+    /// task may run twice; handlers should tolerate that
+    async fn ack(&self, pipe: &mut redis::Pipeline, entry: &str) {
+        pipe.cmd("XACK").arg(&self.stream).arg(&self.group).arg(entry).ignore()
+            .cmd("XDEL").arg(&self.stream).arg(entry).ignore();
+        let result: redis::RedisResult<()> = pipe.query_async(&mut self.conn.clone()).await;
+        if let Err(e) = result {
+            tracing::error!(entry, error = %e, "cannot acknowledge task; it will be claimed again");
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    panic.downcast_ref::<&str>().copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+/// A worker for two task kinds: `summarize` (`{"text": ...}`) through a plain
+/// agent, and `article` (a `Brief`) through the article pipeline
+pub async fn run_worker() -> Result<()> {
+    let client = CLIENTS.openai();
+    let summarizer = Arc::new(client.agent("gpt-4o-mini").preamble(SUMMARY_PREAMBLE).build());
+    let outliner = client.extractor::<Outline>("gpt-4o-mini").preamble(OUTLINE_PREAMBLE).build();
+    let writer = client.agent("gpt-4o").preamble(WRITER_PREAMBLE).build();
+
+    Worker::connect("redis://127.0.0.1/", "agent-tasks", "agents").await?
+        .concurrency(8)
+        .timeout(Duration::from_secs(180))
+        .handler("summarize", move |payload: serde_json::Value| {
+            let summarizer = summarizer.clone();
+            async move {
+                let text = payload["text"].as_str().ok_or_else(|| anyhow::anyhow!("payload needs a `text` string"))?;
+                Ok(serde_json::json!({ "summary": summarizer.prompt(text).await? }))
+            }
+        })
+        .handler("article", OpHandler(article_pipeline(outliner, writer)))
+        .run(async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await
+}
+
+/// Queue an article and wait for a worker to write it
+pub async fn queue_article() -> Result<()> {
+    let queue = Queue::connect("redis://127.0.0.1/", "agent-tasks").await?;
+    let brief = Brief {
+        topic: "Consumer groups in Redis streams".into(),
+        audience: "backend engineers".into(),
+    };
+    let id = queue.enqueue("article", &brief).await?;
+
+    let deadline = Instant::now() + Duration::from_secs(600);
+    while Instant::now() < deadline {
+        if let Some(article) = queue.result(&id).await? {
+            println!("{}", article["body"].as_str().unwrap_or_default());
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+
+    for dead in queue.dead_letters(20).await? {
+        if dead.task_id == id {
+            anyhow::bail!("article task failed after {} attempts: {}", dead.attempts, dead.error);
+        }
+    }
+    anyhow::bail!("article task {id} is still running")
+}
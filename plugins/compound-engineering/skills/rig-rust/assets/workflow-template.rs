@@ -0,0 +1,1067 @@
+//! Workflow Templates for Rig
+//!
+//! Workflows that outgrow a linear pipeline: steps that need the outputs of
+//! several earlier steps, and independent steps that should run side by
+//! side. A `Dag` runs async steps (agent calls, tool calls, plain functions)
+//! as soon as their declared dependencies have finished, and hands each step
+//! its inputs with their types intact.
+//!
+//! Checkpoints are stored as JSON files by default; the SQLite store sits
+//! behind a cargo feature:
+//!
+//! ```toml
+//! [features]
+//! checkpoints-sqlite = ["dep:rusqlite"]
+//!
+//! [dependencies]
+//! rusqlite = { version = "0.32", features = ["bundled"], optional = true }
+//! ```
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use rig::completion::Prompt;
+use rig::tool::Tool;
+#[cfg(feature = "checkpoints-sqlite")]
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config_template::{Config, DynAgent, ProviderPolicy, CLIENTS};
+use crate::registry_template::ToolRegistry;
+use crate::tool_template::{WikiLookup, WikiSummary};
+
+// =============================================================================
+// DAG
+// =============================================================================
+
+// Usage:
+//     let mut dag = Dag::new();
+//     let facts = dag.node("facts", &[], |_| async { lookup().await });
+//     let angles = dag.node("angles", &[], |_| async { brainstorm().await });
+//     let draft = dag.node("draft", &[facts.id(), angles.id()], move |inputs| async move {
+//         write(&inputs.get(&facts)?, &inputs.get(&angles)?).await
+//     });
+//     let outputs = dag.run().await?;
+//     let article = outputs.get(&draft)?;
+//
+// `facts` and `angles` run concurrently; `draft` starts once both are done.
+// A node can only depend on nodes added before it, so there are no cycles.
+
+type Value = Arc<dyn Any + Send + Sync>;
+type Step = Box<dyn Fn(Inputs) -> BoxFuture<'static, Result<Value>> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Typed reference to a node's output
+pub struct Handle<T> {
+    id: NodeId,
+    _output: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+fn downcast<T: Send + Sync + 'static>(value: &Value, node: &str) -> Result<Arc<T>> {
+    value.clone()
+        .downcast::<T>()
+        .map_err(|_| anyhow::anyhow!("output of `{node}` is not a {}", std::any::type_name::<T>()))
+}
+
+/// The outputs of the dependencies a node declared
+pub struct Inputs {
+    node: String,
+    values: HashMap<NodeId, (String, Value)>,
+}
+
+impl Inputs {
+    pub fn get<T: Send + Sync + 'static>(&self, handle: &Handle<T>) -> Result<Arc<T>> {
+        let (name, value) = self.values.get(&handle.id)
+            .ok_or_else(|| anyhow::anyhow!("`{}` reads a node it doesn't list as a dependency", self.node))?;
+        downcast(value, name)
+    }
+}
+
+/// Every node's output, plus how long each node took
+pub struct Outputs {
+    names: Vec<String>,
+    values: HashMap<NodeId, Value>,
+    pub timings: Vec<(String, Duration)>,
+    /// Durable nodes restored from a checkpoint instead of run
+    pub resumed: Vec<String>,
+}
+
+impl Outputs {
+    pub fn get<T: Send + Sync + 'static>(&self, handle: &Handle<T>) -> Result<Arc<T>> {
+        let value = self.values.get(&handle.id)
+            .ok_or_else(|| anyhow::anyhow!("no output for node {:?}", handle.id))?;
+        downcast(value, &self.names[handle.id.0])
+    }
+}
+
+/// How a durable node's output goes to and from a checkpoint
+struct Codec {
+    encode: fn(&Value) -> Result<serde_json::Value>,
+    decode: fn(serde_json::Value) -> Result<Value>,
+}
+
+fn encode<T: Serialize + Send + Sync + 'static>(value: &Value) -> Result<serde_json::Value> {
+    let value = value.downcast_ref::<T>().context("checkpointed output has an unexpected type")?;
+    Ok(serde_json::to_value(value)?)
+}
+
+fn decode<T: DeserializeOwned + Send + Sync + 'static>(json: serde_json::Value) -> Result<Value> {
+    Ok(Arc::new(serde_json::from_value::<T>(json)?) as Value)
+}
+
+struct Node {
+    name: String,
+    deps: Vec<NodeId>,
+    step: Step,
+    codec: Option<Codec>,
+}
+
+/// Bookkeeping for one run: what each node still waits on and what is ready
+struct Progress {
+    waiting: Vec<usize>,
+    dependents: Vec<Vec<usize>>,
+    ready: VecDeque<usize>,
+    values: HashMap<NodeId, Value>,
+}
+
+impl Progress {
+    fn finish(&mut self, i: usize, value: Value) {
+        self.values.insert(NodeId(i), value);
+        for &next in &self.dependents[i] {
+            self.waiting[next] -= 1;
+            if self.waiting[next] == 0 {
+                self.ready.push_back(next);
+            }
+        }
+    }
+}
+
+pub struct Dag {
+    nodes: Vec<Node>,
+    concurrency: usize,
+}
+
+impl Default for Dag {
+    fn default() -> Self {
+        Self { nodes: Vec::new(), concurrency: 8 }
+    }
+}
+
+impl Dag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Most nodes running at once; keep it near the provider's rate limit
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Add a step that runs once every node in `deps` has succeeded
+    pub fn node<T, F, Fut>(&mut self, name: impl Into<String>, deps: &[NodeId], step: F) -> Handle<T>
+    where
+        T: Send + Sync + 'static,
+        F: Fn(Inputs) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        self.push(name.into(), deps, step, None)
+    }
+
+    /// Like `node`, but `resume` checkpoints the output and skips the step
+    /// when a checkpoint exists; use it for model calls and other slow or
+    /// paid steps
+    pub fn durable_node<T, F, Fut>(&mut self, name: impl Into<String>, deps: &[NodeId], step: F) -> Handle<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+        F: Fn(Inputs) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        self.push(name.into(), deps, step, Some(Codec { encode: encode::<T>, decode: decode::<T> }))
+    }
+
+    fn push<T, F, Fut>(&mut self, name: String, deps: &[NodeId], step: F, codec: Option<Codec>) -> Handle<T>
+    where
+        T: Send + Sync + 'static,
+        F: Fn(Inputs) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let mut deps = deps.to_vec();
+        deps.sort_by_key(|d| d.0);
+        deps.dedup();
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            name,
+            deps,
+            step: Box::new(move |inputs| {
+                let output = step(inputs);
+                Box::pin(async move { Ok(Arc::new(output.await?) as Value) })
+            }),
+            codec,
+        });
+        Handle { id, _output: PhantomData }
+    }
+
+    /// Run every node; the first failure cancels the nodes still running
+    pub async fn run(&self) -> Result<Outputs> {
+        self.execute(None).await
+    }
+
+    /// Run under `run_id`, restoring durable nodes checkpointed by an earlier
+    /// attempt with the same id and checkpointing the rest as they finish.
+    /// The id must identify the workflow's input, or a rerun with new input
+    /// picks up stale outputs
+    pub async fn resume(&self, checkpoints: &dyn Checkpoints, run_id: &str) -> Result<Outputs> {
+        self.execute(Some((checkpoints, run_id))).await
+    }
+
+    async fn execute(&self, checkpoints: Option<(&dyn Checkpoints, &str)>) -> Result<Outputs> {
+        let mut waiting = Vec::with_capacity(self.nodes.len());
+        let mut dependents = vec![Vec::new(); self.nodes.len()];
+        let mut durable = HashSet::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            // Handles from another Dag would point anywhere
+            anyhow::ensure!(
+                node.deps.iter().all(|d| d.0 < i),
+                "node `{}` depends on a node that isn't part of this workflow",
+                node.name
+            );
+            for dep in &node.deps {
+                dependents[dep.0].push(i);
+            }
+            // Checkpoints are keyed by node name
+            anyhow::ensure!(
+                node.codec.is_none() || durable.insert(node.name.as_str()),
+                "two durable nodes are named `{}`",
+                node.name
+            );
+            waiting.push(node.deps.len());
+        }
+
+        let saved = match checkpoints {
+            Some((store, run_id)) => store.load(run_id).await?,
+            None => HashMap::new(),
+        };
+        let mut progress = Progress {
+            ready: (0..self.nodes.len()).filter(|&i| waiting[i] == 0).collect(),
+            waiting,
+            dependents,
+            values: HashMap::new(),
+        };
+        let mut running = FuturesUnordered::new();
+        let mut timings = Vec::with_capacity(self.nodes.len());
+        let mut resumed = Vec::new();
+
+        loop {
+            while running.len() < self.concurrency {
+                let Some(i) = progress.ready.pop_front() else { break };
+                let node = &self.nodes[i];
+                if let (Some(codec), Some(json)) = (&node.codec, saved.get(&node.name)) {
+                    match (codec.decode)(json.clone()) {
+                        Ok(value) => {
+                            tracing::debug!(node = %node.name, "workflow node restored from checkpoint");
+                            resumed.push(node.name.clone());
+                            progress.finish(i, value);
+                            continue;
+                        }
+                        // The output type changed since the checkpoint was written
+                        Err(e) => tracing::warn!(node = %node.name, error = %e, "ignoring unreadable checkpoint"),
+                    }
+                }
+                let inputs = Inputs {
+                    node: node.name.clone(),
+                    values: node.deps.iter()
+                        .map(|d| (*d, (self.nodes[d.0].name.clone(), progress.values[d].clone())))
+                        .collect(),
+                };
+                let output = (node.step)(inputs);
+                let started = Instant::now();
+                running.push(async move { (i, output.await, started.elapsed()) });
+            }
+
+            let Some((i, output, elapsed)) = running.next().await else { break };
+            let node = &self.nodes[i];
+            let value = output.with_context(|| format!("workflow node `{}` failed", node.name))?;
+            tracing::debug!(node = %node.name, ?elapsed, "workflow node finished");
+            if let (Some(codec), Some((store, run_id))) = (&node.codec, checkpoints) {
+                let json = (codec.encode)(&value)?;
+                store.save(run_id, &node.name, json).await
+                    .with_context(|| format!("cannot checkpoint workflow node `{}`", node.name))?;
+            }
+            timings.push((node.name.clone(), elapsed));
+            progress.finish(i, value);
+        }
+
+        Ok(Outputs {
+            names: self.nodes.iter().map(|n| n.name.clone()).collect(),
+            values: progress.values,
+            timings,
+            resumed,
+        })
+    }
+}
+
+// =============================================================================
+// CHECKPOINTS
+// =============================================================================
+
+// A long workflow that dies at step 9 of 10 shouldn't pay for steps 1-8
+// again. `Dag::resume` saves each durable node's output under the run id as
+// it finishes and, on the next attempt with that id, restores them instead
+// of calling the model:
+//
+//     let checkpoints = JsonCheckpoints::new(".checkpoints");
+//     let outputs = dag.resume(&checkpoints, "brief-2024-06-01").await?;
+//     checkpoints.clear("brief-2024-06-01").await?;
+
+pub trait Checkpoints: Send + Sync {
+    /// Saved outputs of `run_id`, by node name
+    fn load<'a>(&'a self, run_id: &'a str) -> BoxFuture<'a, Result<HashMap<String, serde_json::Value>>>;
+
+    fn save<'a>(&'a self, run_id: &'a str, node: &'a str, output: serde_json::Value) -> BoxFuture<'a, Result<()>>;
+
+    /// Forget a run, once its result is stored somewhere else
+    fn clear<'a>(&'a self, run_id: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// One JSON file per run: `{dir}/{run_id}.json`
+pub struct JsonCheckpoints {
+    dir: PathBuf,
+    // Serializes read-modify-write of a run file between concurrent nodes
+    write: tokio::sync::Mutex<()>,
+}
+
+impl JsonCheckpoints {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self { dir: dir.as_ref().to_path_buf(), write: tokio::sync::Mutex::new(()) }
+    }
+
+    fn path(&self, run_id: &str) -> Result<PathBuf> {
+        // The id becomes a file name
+        anyhow::ensure!(
+            !run_id.is_empty() && run_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "run id {run_id:?} must be letters, digits, `-`, and `_`"
+        );
+        Ok(self.dir.join(format!("{run_id}.json")))
+    }
+
+    async fn read(path: &Path) -> Result<HashMap<String, serde_json::Value>> {
+        match tokio::fs::read_to_string(path).await {
+            Ok(raw) => serde_json::from_str(&raw).map_err(|e| anyhow::anyhow!("cannot parse {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(anyhow::anyhow!("cannot read {}: {e}", path.display())),
+        }
+    }
+}
+
+impl Checkpoints for JsonCheckpoints {
+    fn load<'a>(&'a self, run_id: &'a str) -> BoxFuture<'a, Result<HashMap<String, serde_json::Value>>> {
+        Box::pin(async move { Self::read(&self.path(run_id)?).await })
+    }
+
+    fn save<'a>(&'a self, run_id: &'a str, node: &'a str, output: serde_json::Value) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = self.path(run_id)?;
+            let _guard = self.write.lock().await;
+            let mut saved = Self::read(&path).await?;
+            saved.insert(node.to_string(), output);
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // Write then rename, so a crash mid-write leaves the previous file intact
+            let tmp = path.with_extension("json.tmp");
+            tokio::fs::write(&tmp, serde_json::to_vec_pretty(&saved)?).await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            Ok(())
+        })
+    }
+
+    fn clear<'a>(&'a self, run_id: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(run_id)?).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// All runs in one SQLite file; suits many concurrent runs on one host
+#[cfg(feature = "checkpoints-sqlite")]
+pub struct SqliteCheckpoints {
+    conn: Arc<std::sync::Mutex<Connection>>,
+}
+
+#[cfg(feature = "checkpoints-sqlite")]
+impl SqliteCheckpoints {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS checkpoints (
+                 run_id TEXT NOT NULL,
+                 node TEXT NOT NULL,
+                 output TEXT NOT NULL,
+                 saved_at INTEGER NOT NULL DEFAULT (unixepoch()),
+                 PRIMARY KEY (run_id, node)
+             ) WITHOUT ROWID;",
+        )?;
+        Ok(Self { conn: Arc::new(std::sync::Mutex::new(conn)) })
+    }
+
+    /// Run blocking SQLite work off the async runtime
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+}
+
+#[cfg(feature = "checkpoints-sqlite")]
+impl Checkpoints for SqliteCheckpoints {
+    fn load<'a>(&'a self, run_id: &'a str) -> BoxFuture<'a, Result<HashMap<String, serde_json::Value>>> {
+        let run_id = run_id.to_string();
+        Box::pin(self.with_conn(move |conn| {
+            let mut stmt = conn.prepare_cached("SELECT node, output FROM checkpoints WHERE run_id = ?1")?;
+            let rows = stmt.query_map(params![run_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            let mut saved = HashMap::new();
+            for row in rows {
+                let (node, output) = row?;
+                saved.insert(node, serde_json::from_str(&output)?);
+            }
+            Ok(saved)
+        }))
+    }
+
+    fn save<'a>(&'a self, run_id: &'a str, node: &'a str, output: serde_json::Value) -> BoxFuture<'a, Result<()>> {
+        let (run_id, node) = (run_id.to_string(), node.to_string());
+        Box::pin(self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO checkpoints (run_id, node, output) VALUES (?1, ?2, ?3)",
+                params![run_id, node, output.to_string()],
+            )?;
+            Ok(())
+        }))
+    }
+
+    fn clear<'a>(&'a self, run_id: &'a str) -> BoxFuture<'a, Result<()>> {
+        let run_id = run_id.to_string();
+        Box::pin(self.with_conn(move |conn| {
+            conn.execute("DELETE FROM checkpoints WHERE run_id = ?1", params![run_id])?;
+            Ok(())
+        }))
+    }
+}
+
+// =============================================================================
+// EXAMPLE
+// =============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResearchBrief {
+    pub title: String,
+    pub summary: String,
+    pub outline: String,
+    pub source: String,
+}
+
+/// wiki (tool) + angles (agent) -> outline (agent) -> title + summary (agents) -> brief (function)
+fn research_dag(topic: &str) -> (Dag, Handle<ResearchBrief>) {
+    let client = CLIENTS.openai();
+    let fast = Arc::new(client.agent("gpt-4o-mini").preamble("You are a concise research assistant.").build());
+    let writer = Arc::new(client.agent("gpt-4o").preamble("You plan well-structured technical briefs.").build());
+    let wiki = Arc::new(WikiLookup::default());
+    let topic = topic.to_string();
+
+    let mut dag = Dag::new().concurrency(4);
+
+    // Model calls are durable; the Wikipedia lookup is free to repeat
+    let facts = dag.node("wiki", &[], {
+        let (wiki, topic) = (wiki.clone(), topic.clone());
+        move |_| {
+            let (wiki, topic) = (wiki.clone(), topic.clone());
+            async move {
+                let args = serde_json::from_value(serde_json::json!({ "topic": topic }))?;
+                Ok(wiki.call(args).await?)
+            }
+        }
+    });
+
+    let angles = dag.durable_node("angles", &[], {
+        let (fast, topic) = (fast.clone(), topic.clone());
+        move |_| {
+            let (fast, topic) = (fast.clone(), topic.clone());
+            async move { Ok(fast.prompt(format!("List five angles a brief on {topic} should cover.")).await?) }
+        }
+    });
+
+    let outline = dag.durable_node("outline", &[facts.id(), angles.id()], {
+        let writer = writer.clone();
+        move |inputs| {
+            let writer = writer.clone();
+            async move {
+                let facts: Arc<WikiSummary> = inputs.get(&facts)?;
+                let angles = inputs.get(&angles)?;
+                let prompt = format!("Background:\n{}\n\nAngles:\n{angles}\n\nWrite a section outline.", facts.extract);
+                Ok(writer.prompt(prompt).await?)
+            }
+        }
+    });
+
+    // Both depend only on the outline, so they run side by side
+    let title = dag.durable_node("title", &[outline.id()], {
+        let fast = fast.clone();
+        move |inputs| {
+            let fast = fast.clone();
+            async move {
+                let outline = inputs.get(&outline)?;
+                Ok(fast.prompt(format!("Give this outline a title. Reply with the title only.\n\n{outline}")).await?)
+            }
+        }
+    });
+    let summary = dag.durable_node("summary", &[outline.id()], {
+        let fast = fast.clone();
+        move |inputs| {
+            let fast = fast.clone();
+            async move {
+                let outline = inputs.get(&outline)?;
+                Ok(fast.prompt(format!("Summarize this outline in two sentences.\n\n{outline}")).await?)
+            }
+        }
+    });
+
+    let brief = dag.node("brief", &[facts.id(), outline.id(), title.id(), summary.id()], move |inputs| async move {
+        Ok(ResearchBrief {
+            title: inputs.get(&title)?.trim().trim_matches('"').to_string(),
+            summary: inputs.get(&summary)?.to_string(),
+            outline: inputs.get(&outline)?.to_string(),
+            source: inputs.get(&facts)?.url.clone(),
+        })
+    });
+
+    (dag, brief)
+}
+
+pub async fn research_brief(topic: &str) -> Result<ResearchBrief> {
+    let (dag, brief) = research_dag(topic);
+    let outputs = dag.run().await?;
+    for (node, elapsed) in &outputs.timings {
+        tracing::info!(node = %node, ?elapsed, "research brief step");
+    }
+    Ok(outputs.get(&brief)?.as_ref().clone())
+}
+
+/// The same workflow, resumable: rerun it after a crash and only the steps
+/// that hadn't finished call the model
+pub async fn resumable_research_brief(topic: &str) -> Result<ResearchBrief> {
+    let (dag, brief) = research_dag(topic);
+    let checkpoints = JsonCheckpoints::new(".checkpoints");
+    let slug: String = topic.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let run_id = format!("brief-{slug}");
+
+    let outputs = dag.resume(&checkpoints, &run_id).await?;
+    if !outputs.resumed.is_empty() {
+        tracing::info!(run_id = %run_id, resumed = ?outputs.resumed, "resumed research brief");
+    }
+    let brief = outputs.get(&brief)?.as_ref().clone();
+    checkpoints.clear(&run_id).await?;
+    Ok(brief)
+}
+
+// =============================================================================
+// YAML WORKFLOWS
+// =============================================================================
+
+// Workflows defined in YAML and compiled onto `Dag`, so prompts and wiring
+// change without a rebuild. Agents are model roles from the config
+// (`[models.<role>]`), tools are names in a `ToolRegistry`, and `{{name}}`
+// inserts a workflow input or an earlier step's output:
+//
+//     # workflows/brief.yaml
+//     name: brief
+//     inputs: [topic]
+//     steps:
+//       - id: facts
+//         tool: wikipedia
+//         args: { topic: "{{topic}}" }
+//       - id: angles
+//         agent: fast
+//         prompt: "List five angles a brief on {{topic}} should cover."
+//       - id: brief
+//         agent: writer
+//         preamble: You write concise technical briefs.
+//         prompt: "Background:\n{{facts}}\n\nAngles:\n{{angles}}\n\nWrite the brief."
+//     output: brief
+//
+// A step runs once every step it references (or lists under `after`) is
+// done, so `facts` and `angles` above run concurrently. Steps may only
+// reference inputs and earlier steps. Every step's output is text; tool
+// output is the tool's JSON.
+
+fn default_concurrency() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkflowSpec {
+    pub name: String,
+    /// Values the caller supplies to `Workflow::run`
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    pub steps: Vec<StepSpec>,
+    /// Step whose output the workflow returns; defaults to the last
+    pub output: Option<String>,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StepSpec {
+    pub id: String,
+    /// Model role from the config
+    pub agent: Option<String>,
+    /// Overrides the role's preamble
+    pub preamble: Option<String>,
+    pub prompt: Option<String>,
+    /// Tool name in the registry
+    pub tool: Option<String>,
+    #[serde(default)]
+    pub args: serde_json::Value,
+    /// Steps to wait for without using their output
+    #[serde(default)]
+    pub after: Vec<String>,
+}
+
+impl StepSpec {
+    /// Names this step reads or waits for
+    fn references(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if let Some(prompt) = &self.prompt {
+            names.extend(placeholders(prompt));
+        }
+        json_placeholders(&self.args, &mut names);
+        names.extend(self.after.iter().cloned());
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+fn placeholders(template: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else { break };
+        names.push(rest[start + 2..start + 2 + end].trim().to_string());
+        rest = &rest[start + 2 + end + 2..];
+    }
+    names
+}
+
+fn json_placeholders(value: &serde_json::Value, names: &mut Vec<String>) {
+    match value {
+        serde_json::Value::String(s) => names.extend(placeholders(s)),
+        serde_json::Value::Array(items) => items.iter().for_each(|v| json_placeholders(v, names)),
+        serde_json::Value::Object(map) => map.values().for_each(|v| json_placeholders(v, names)),
+        _ => {}
+    }
+}
+
+/// Replace each `{{name}}` with its value; unknown names are left as written
+fn render(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else { break };
+        let whole = &rest[start..start + 2 + end + 2];
+        out.push_str(&rest[..start]);
+        match vars.get(rest[start + 2..start + 2 + end].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(whole),
+        }
+        rest = &rest[start + whole.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn render_json(value: &serde_json::Value, vars: &HashMap<String, String>) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => serde_json::Value::String(render(s, vars)),
+        serde_json::Value::Array(items) => items.iter().map(|v| render_json(v, vars)).collect(),
+        serde_json::Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), render_json(v, vars))).collect(),
+        other => other.clone(),
+    }
+}
+
+impl WorkflowSpec {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        let spec: Self = serde_yaml::from_str(&raw).map_err(|e| anyhow::anyhow!("malformed {}: {e}", path.display()))?;
+        let issues = spec.issues();
+        anyhow::ensure!(issues.is_empty(), "invalid workflow {}:\n  - {}", path.display(), issues.join("\n  - "));
+        Ok(spec)
+    }
+
+    /// Every problem with the spec, so one edit can fix them all
+    pub fn issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if self.steps.is_empty() {
+            issues.push("no steps".to_string());
+        }
+        if self.concurrency == 0 {
+            issues.push("concurrency must be at least 1".to_string());
+        }
+        let mut known: HashSet<&str> = self.inputs.iter().map(String::as_str).collect();
+        for step in &self.steps {
+            let id = &step.id;
+            if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                issues.push(format!("step id {id:?} must be letters, digits, `_`, and `-`"));
+            }
+            match (&step.agent, &step.tool) {
+                (Some(_), None) if step.prompt.is_none() => issues.push(format!("step `{id}`: an agent step needs a prompt")),
+                (Some(_), None) if !step.args.is_null() => issues.push(format!("step `{id}`: args are for tool steps")),
+                (None, Some(_)) if step.prompt.is_some() || step.preamble.is_some() => {
+                    issues.push(format!("step `{id}`: prompt and preamble are for agent steps"))
+                }
+                (Some(_), Some(_)) | (None, None) => issues.push(format!("step `{id}`: set exactly one of agent and tool")),
+                _ => {}
+            }
+            for name in step.references() {
+                if !known.contains(name.as_str()) {
+                    issues.push(format!("step `{id}` references `{name}`, which is not an input or an earlier step"));
+                }
+            }
+            if !known.insert(id) {
+                issues.push(format!("`{id}` is defined twice"));
+            }
+        }
+        if let Some(output) = &self.output {
+            if !self.steps.iter().any(|s| &s.id == output) {
+                issues.push(format!("output `{output}` is not a step"));
+            }
+        }
+        issues
+    }
+
+    /// Resolve agents and tools; fails on roles or tools that don't exist
+    pub fn build(self, config: &Config, registry: &ToolRegistry) -> Result<Workflow> {
+        let actions = self.steps.iter()
+            .map(|step| match (&step.agent, &step.tool) {
+                (Some(role), _) => {
+                    let agent = config.agent(role, step.preamble.as_deref().unwrap_or_default())
+                        .map_err(|e| anyhow::anyhow!("step `{}`: {e}", step.id))?;
+                    Ok(Action::Prompt { agent: agent.into(), template: step.prompt.clone().unwrap_or_default() })
+                }
+                (None, Some(tool)) => {
+                    anyhow::ensure!(registry.get(tool).is_some(), "step `{}`: no tool named `{tool}` is registered", step.id);
+                    let args = if step.args.is_null() { serde_json::json!({}) } else { step.args.clone() };
+                    Ok(Action::Tool { name: tool.clone(), args })
+                }
+                (None, None) => anyhow::bail!("step `{}` has neither agent nor tool", step.id),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Workflow { spec: self, actions, registry: registry.clone() })
+    }
+}
+
+#[derive(Clone)]
+enum Action {
+    Prompt { agent: Arc<dyn DynAgent>, template: String },
+    Tool { name: String, args: serde_json::Value },
+}
+
+/// A validated spec with its agents and tools resolved; run it many times
+pub struct Workflow {
+    spec: WorkflowSpec,
+    actions: Vec<Action>,
+    registry: ToolRegistry,
+}
+
+impl Workflow {
+    pub async fn run(&self, inputs: &HashMap<String, String>) -> Result<String> {
+        for name in &self.spec.inputs {
+            anyhow::ensure!(inputs.contains_key(name), "workflow `{}` needs input `{name}`", self.spec.name);
+        }
+
+        let mut dag = Dag::new().concurrency(self.spec.concurrency);
+        let mut handles: HashMap<&str, Handle<String>> = HashMap::new();
+        for (step, action) in self.spec.steps.iter().zip(&self.actions) {
+            let references = step.references();
+            let upstream: Vec<(String, Handle<String>)> = references.iter()
+                .filter_map(|name| handles.get(name.as_str()).map(|handle| (name.clone(), *handle)))
+                .collect();
+            let given: HashMap<String, String> = references.iter()
+                .filter_map(|name| inputs.get(name).map(|value| (name.clone(), value.clone())))
+                .collect();
+            let deps: Vec<NodeId> = upstream.iter().map(|(_, handle)| handle.id()).collect();
+            let (action, registry) = (action.clone(), self.registry.clone());
+
+            let handle = dag.node(step.id.clone(), &deps, move |outputs| {
+                let (upstream, mut vars, action, registry) = (upstream.clone(), given.clone(), action.clone(), registry.clone());
+                async move {
+                    for (name, handle) in &upstream {
+                        vars.insert(name.clone(), outputs.get(handle)?.to_string());
+                    }
+                    match action {
+                        Action::Prompt { agent, template } => {
+                            let prompt = render(&template, &vars);
+                            Ok(agent.prompt(&prompt).await?)
+                        }
+                        Action::Tool { name, args } => Ok(registry.call(&name, render_json(&args, &vars)).await?),
+                    }
+                }
+            });
+            handles.insert(&step.id, handle);
+        }
+
+        let output = match &self.spec.output {
+            Some(output) => output.as_str(),
+            None => self.spec.steps.last().map(|s| s.id.as_str()).unwrap_or_default(),
+        };
+        let outputs = dag.run().await.map_err(|e| e.context(format!("workflow `{}` failed", self.spec.name)))?;
+        Ok(outputs.get(&handles[output])?.to_string())
+    }
+}
+
+/// Run `workflows/brief.yaml` with agents from config.toml and the Wikipedia tool
+pub async fn yaml_workflow() -> Result<()> {
+    let config = Config::load("config.toml")?;
+    let registry = ToolRegistry::new();
+    registry.register(WikiLookup::default());
+
+    let workflow = WorkflowSpec::load("workflows/brief.yaml")?.build(&config, &registry)?;
+    let inputs = HashMap::from([("topic".to_string(), "Raft consensus".to_string())]);
+    println!("{}", workflow.run(&inputs).await?);
+    Ok(())
+}
+
+// =============================================================================
+// SAGAS
+// =============================================================================
+
+// Steps with side effects (tickets, deployments, emails) can't be retried
+// from a checkpoint the way model calls can; a run that fails halfway has to
+// undo what it already did. A `Saga` runs its steps in order and, when one
+// fails, calls the compensation of every step that succeeded, newest first:
+//
+//     let saga = Saga::new("ship-release")
+//         .step(ToolStep::new("ticket", &registry, "create_ticket", |ctx| json!({ "title": ctx["title"] }))
+//             .compensate_with("close_ticket", |ctx| json!({ "id": ctx["ticket"]["id"] })))
+//         .step(ToolStep::new("deploy", &registry, "deploy", |ctx| json!({ "version": ctx["version"] }))
+//             .compensate_with("rollback", |ctx| json!({ "deployment": ctx["deploy"]["id"] })));
+//     let ctx = saga.run(json!({ "title": "Release 1.4", "version": "1.4.0" })).await?;
+//
+// Compensations are retried under the saga's policy, so they must be safe
+// to repeat. Put steps that can't be undone (an email) last.
+
+/// One side-effecting step and how to undo it
+pub trait SagaStep<C>: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn run<'a>(&'a self, ctx: &'a mut C) -> BoxFuture<'a, Result<()>>;
+
+    /// Undo `run`; only called for steps whose `run` succeeded
+    fn compensate<'a>(&'a self, ctx: &'a mut C) -> BoxFuture<'a, Result<()>>;
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "saga `{saga}` failed at `{step}`: {error:#} ({} compensated, {} left to undo by hand)",
+    .compensated.len(),
+    .unresolved.len()
+)]
+pub struct SagaError {
+    pub saga: String,
+    pub step: String,
+    pub error: anyhow::Error,
+    /// Steps rolled back, in the order they were undone
+    pub compensated: Vec<String>,
+    /// Steps whose compensation still failed after retries
+    pub unresolved: Vec<(String, anyhow::Error)>,
+}
+
+pub struct Saga<C> {
+    name: String,
+    steps: Vec<Box<dyn SagaStep<C>>>,
+    policy: ProviderPolicy,
+}
+
+impl<C: Send> Saga<C> {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), steps: Vec::new(), policy: ProviderPolicy::default() }
+    }
+
+    pub fn step(mut self, step: impl SagaStep<C> + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Retries and backoff for each compensation
+    pub fn compensation_policy(mut self, policy: ProviderPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Run every step, or roll back the ones that ran and return why
+    pub async fn run(&self, mut ctx: C) -> Result<C, SagaError> {
+        for (i, step) in self.steps.iter().enumerate() {
+            let Err(error) = step.run(&mut ctx).await else { continue };
+            tracing::warn!(saga = %self.name, step = step.name(), error = %error, "saga step failed, compensating");
+
+            let (mut compensated, mut unresolved) = (Vec::new(), Vec::new());
+            for done in self.steps[..i].iter().rev() {
+                match self.compensate(done.as_ref(), &mut ctx).await {
+                    Ok(()) => compensated.push(done.name().to_string()),
+                    Err(e) => {
+                        tracing::error!(saga = %self.name, step = done.name(), error = %e, "compensation failed");
+                        unresolved.push((done.name().to_string(), e));
+                    }
+                }
+            }
+            return Err(SagaError { saga: self.name.clone(), step: step.name().to_string(), error, compensated, unresolved });
+        }
+        Ok(ctx)
+    }
+
+    /// Every error is retried; a half-undone run is worse than a slow rollback
+    async fn compensate(&self, step: &dyn SagaStep<C>, ctx: &mut C) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match step.compensate(ctx).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.policy.max_retries => {
+                    attempt += 1;
+                    tracing::warn!(saga = %self.name, step = step.name(), attempt, error = %e, "retrying compensation");
+                    tokio::time::sleep(self.policy.delay(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+type ToolArgs = Box<dyn Fn(&serde_json::Value) -> serde_json::Value + Send + Sync>;
+
+/// A registry tool call as a saga step. The context is a JSON object: the
+/// saga's input plus each step's output under the step's name (tool output
+/// parsed as JSON when it is JSON)
+pub struct ToolStep {
+    name: String,
+    registry: ToolRegistry,
+    tool: String,
+    args: ToolArgs,
+    undo: Option<(String, ToolArgs)>,
+}
+
+impl ToolStep {
+    pub fn new(
+        name: impl Into<String>,
+        registry: &ToolRegistry,
+        tool: impl Into<String>,
+        args: impl Fn(&serde_json::Value) -> serde_json::Value + Send + Sync + 'static,
+    ) -> Self {
+        Self { name: name.into(), registry: registry.clone(), tool: tool.into(), args: Box::new(args), undo: None }
+    }
+
+    /// The tool that undoes this one; without it the step is never rolled back
+    pub fn compensate_with(
+        mut self,
+        tool: impl Into<String>,
+        args: impl Fn(&serde_json::Value) -> serde_json::Value + Send + Sync + 'static,
+    ) -> Self {
+        self.undo = Some((tool.into(), Box::new(args)));
+        self
+    }
+}
+
+impl SagaStep<serde_json::Value> for ToolStep {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run<'a>(&'a self, ctx: &'a mut serde_json::Value) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let output = self.registry.call(&self.tool, (self.args)(ctx)).await?;
+            ctx[self.name.as_str()] = serde_json::from_str(&output).unwrap_or(serde_json::Value::String(output));
+            Ok(())
+        })
+    }
+
+    fn compensate<'a>(&'a self, ctx: &'a mut serde_json::Value) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if let Some((tool, args)) = &self.undo {
+                self.registry.call(tool, args(ctx)).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Open a release ticket, deploy, then announce. A failed deploy closes the
+/// ticket; a failed announcement also rolls the deploy back. Expects
+/// `create_ticket`, `close_ticket`, `deploy`, `rollback`, and `send_email`
+/// tools in `registry`
+pub async fn ship_release(registry: &ToolRegistry, version: &str) -> Result<()> {
+    use serde_json::json;
+
+    let saga = Saga::new("ship-release")
+        .step(
+            ToolStep::new("ticket", registry, "create_ticket", |ctx| json!({ "title": format!("Release {}", ctx["version"].as_str().unwrap_or_default()) }))
+                .compensate_with("close_ticket", |ctx| json!({ "id": ctx["ticket"]["id"], "resolution": "release aborted" })),
+        )
+        .step(
+            ToolStep::new("deploy", registry, "deploy", |ctx| json!({ "version": ctx["version"], "ticket": ctx["ticket"]["id"] }))
+                .compensate_with("rollback", |ctx| json!({ "deployment": ctx["deploy"]["id"] })),
+        )
+        // Last, because an email can't be unsent
+        .step(ToolStep::new("announce", registry, "send_email", |ctx| {
+            json!({ "to": "releases@example.com", "subject": format!("Shipped {}", ctx["version"].as_str().unwrap_or_default()) })
+        }));
+
+    match saga.run(json!({ "version": version })).await {
+        Ok(ctx) => tracing::info!(deployment = %ctx["deploy"]["id"], "release shipped"),
+        Err(e) => {
+            for (step, error) in &e.unresolved {
+                tracing::error!(step = %step, error = %error, "undo by hand");
+            }
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}